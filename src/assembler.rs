@@ -38,6 +38,26 @@ pub enum Register32 {
     R15d,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XmmRegister {
+    Xmm0,
+    Xmm1,
+    Xmm2,
+    Xmm3,
+    Xmm4,
+    Xmm5,
+    Xmm6,
+    Xmm7,
+    Xmm8,
+    Xmm9,
+    Xmm10,
+    Xmm11,
+    Xmm12,
+    Xmm13,
+    Xmm14,
+    Xmm15,
+}
+
 impl From<Register64> for Register32 {
     fn from(reg: Register64) -> Self {
         use Register32::*;
@@ -63,7 +83,7 @@ impl From<Register64> for Register32 {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Register8 {
     Al,
     Cl,
@@ -131,6 +151,14 @@ impl Addressing<Register64> {
     }
 }
 
+/// A `byte ptr` memory operand.
+#[derive(Debug, Clone)]
+pub struct BytePtr(pub Addressing<Register64>);
+
+/// A `word ptr` memory operand.
+#[derive(Debug, Clone)]
+pub struct WordPtr(pub Addressing<Register64>);
+
 fn rex(w: bool, r: bool, x: bool, b: bool) -> u8 {
     0x40 | (w as u8) << 3 | (r as u8) << 2 | (x as u8) << 1 | b as u8
 }
@@ -224,6 +252,12 @@ impl RegisterSize for Register32 {
     }
 }
 
+impl RegisterNumber for XmmRegister {
+    fn number(&self) -> u8 {
+        *self as u8
+    }
+}
+
 impl RegisterNumber for Register8 {
     fn number(&self) -> u8 {
         use Register8::*;
@@ -376,6 +410,22 @@ impl Mov<Register64> for Addressing<Register64> {
     }
 }
 
+pub trait Lea<Src> {
+    fn lea(self, src: Src) -> Vec<u8>;
+}
+
+impl Lea<Addressing<Register64>> for Register64 {
+    fn lea(self, src: Addressing<Register64>) -> Vec<u8> {
+        let mut code = vec![];
+        let dest_number = self.number();
+        let src_number = src.base.number();
+        code.push(rex(true, dest_number >= 8, false, src_number >= 8));
+        code.push(0x8d);
+        code.extend_from_slice(&src.to_code(dest_number));
+        code
+    }
+}
+
 pub trait Call {
     fn call(self) -> Vec<u8>;
 }
@@ -414,6 +464,95 @@ where
     code
 }
 
+fn opcode_reg_mem(
+    prefix: &[u8],
+    w: bool,
+    opcode: &[u8],
+    reg: u8,
+    mem: &Addressing<Register64>,
+) -> Vec<u8> {
+    let mut code = prefix.to_vec();
+    code.push(rex(w, reg >= 8, false, mem.base.number() >= 8));
+    code.extend_from_slice(opcode);
+    code.extend_from_slice(&mem.to_code(reg));
+    code
+}
+
+impl Mov<Addressing<Register64>> for Register32 {
+    fn mov(self, src: Addressing<Register64>) -> Vec<u8> {
+        opcode_reg_mem(&[], false, &[0x8b], self.number(), &src)
+    }
+}
+
+impl Mov<Register32> for Addressing<Register64> {
+    fn mov(self, src: Register32) -> Vec<u8> {
+        opcode_reg_mem(&[], false, &[0x89], src.number(), &self)
+    }
+}
+
+impl Mov<Register32> for WordPtr {
+    fn mov(self, src: Register32) -> Vec<u8> {
+        opcode_reg_mem(&[0x66], false, &[0x89], src.number(), &self.0)
+    }
+}
+
+impl Mov<Register32> for BytePtr {
+    fn mov(self, src: Register32) -> Vec<u8> {
+        // the REX prefix selects sil/dil instead of dh/bh
+        opcode_reg_mem(&[], false, &[0x88], src.number(), &self.0)
+    }
+}
+
+impl Movzx<BytePtr> for Register32 {
+    fn movzx(self, src: BytePtr) -> Vec<u8> {
+        opcode_reg_mem(&[], false, &[0x0f, 0xb6], self.number(), &src.0)
+    }
+}
+
+impl Movzx<WordPtr> for Register32 {
+    fn movzx(self, src: WordPtr) -> Vec<u8> {
+        opcode_reg_mem(&[], false, &[0x0f, 0xb7], self.number(), &src.0)
+    }
+}
+
+pub trait Movsx<Src> {
+    fn movsx(self, src: Src) -> Vec<u8>;
+}
+
+impl Movsx<BytePtr> for Register32 {
+    fn movsx(self, src: BytePtr) -> Vec<u8> {
+        opcode_reg_mem(&[], false, &[0x0f, 0xbe], self.number(), &src.0)
+    }
+}
+
+impl Movsx<WordPtr> for Register32 {
+    fn movsx(self, src: WordPtr) -> Vec<u8> {
+        opcode_reg_mem(&[], false, &[0x0f, 0xbf], self.number(), &src.0)
+    }
+}
+
+impl Movsx<BytePtr> for Register64 {
+    fn movsx(self, src: BytePtr) -> Vec<u8> {
+        opcode_reg_mem(&[], true, &[0x0f, 0xbe], self.number(), &src.0)
+    }
+}
+
+impl Movsx<WordPtr> for Register64 {
+    fn movsx(self, src: WordPtr) -> Vec<u8> {
+        opcode_reg_mem(&[], true, &[0x0f, 0xbf], self.number(), &src.0)
+    }
+}
+
+pub trait Movsxd<Src> {
+    fn movsxd(self, src: Src) -> Vec<u8>;
+}
+
+impl Movsxd<Addressing<Register64>> for Register64 {
+    fn movsxd(self, src: Addressing<Register64>) -> Vec<u8> {
+        opcode_reg_mem(&[], true, &[0x63], self.number(), &src)
+    }
+}
+
 pub trait Add<Src> {
     fn add(self, src: Src) -> Vec<u8>;
 }
@@ -488,6 +627,40 @@ impl Cmp<i32> for Register32 {
     }
 }
 
+impl Cmp<Addressing<Register64>> for Register64 {
+    fn cmp(self, src: Addressing<Register64>) -> Vec<u8> {
+        opcode_reg_mem(&[], true, &[0x3b], self.number(), &src)
+    }
+}
+
+impl Cmp<i32> for Register64 {
+    fn cmp(self, src: i32) -> Vec<u8> {
+        let mut code = vec![];
+        let number = self.number();
+        code.push(rex(true, false, false, number >= 8));
+        code.push(0x81);
+        code.push(mod_rm(3, 7, number));
+        code.extend_from_slice(&src.to_le_bytes());
+        code
+    }
+}
+
+pub trait And<Src> {
+    fn and(self, src: Src) -> Vec<u8>;
+}
+
+impl And<i32> for Register64 {
+    fn and(self, src: i32) -> Vec<u8> {
+        let mut code = vec![];
+        let number = self.number();
+        code.push(rex(true, false, false, number >= 8));
+        code.push(0x81);
+        code.push(mod_rm(3, 4, number));
+        code.extend_from_slice(&src.to_le_bytes());
+        code
+    }
+}
+
 pub trait Sete {
     fn sete(self) -> Vec<u8>;
 }
@@ -536,6 +709,22 @@ impl Jmp for i32 {
     }
 }
 
+impl Jmp for Register64 {
+    fn jmp(self) -> Vec<u8> {
+        let mut code = vec![];
+        let number = self.number();
+        if number < 8 {
+            code.push(0xff);
+            code.push(0xe0 + number);
+        } else {
+            code.push(0x41);
+            code.push(0xff);
+            code.push(0xe0 + number - 8);
+        }
+        code
+    }
+}
+
 pub trait Jne {
     fn jne(self) -> Vec<u8>;
 }
@@ -547,3 +736,530 @@ impl Jne for i32 {
         code
     }
 }
+
+pub trait Jae {
+    fn jae(self) -> Vec<u8>;
+}
+
+impl Jae for i32 {
+    fn jae(self) -> Vec<u8> {
+        let mut code = vec![0x0f, 0x83];
+        code.extend_from_slice(&self.to_le_bytes());
+        code
+    }
+}
+
+pub trait Jb {
+    fn jb(self) -> Vec<u8>;
+}
+
+impl Jb for i32 {
+    fn jb(self) -> Vec<u8> {
+        let mut code = vec![0x0f, 0x82];
+        code.extend_from_slice(&self.to_le_bytes());
+        code
+    }
+}
+
+pub trait Jp {
+    fn jp(self) -> Vec<u8>;
+}
+
+impl Jp for i32 {
+    fn jp(self) -> Vec<u8> {
+        let mut code = vec![0x0f, 0x8a];
+        code.extend_from_slice(&self.to_le_bytes());
+        code
+    }
+}
+
+/// Condition codes, numbered like the low nibble of the `jcc` and `setcc` opcodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    B = 0x2,
+    Ae = 0x3,
+    E = 0x4,
+    Ne = 0x5,
+    Be = 0x6,
+    A = 0x7,
+    P = 0xa,
+    Np = 0xb,
+    L = 0xc,
+    Ge = 0xd,
+    Le = 0xe,
+    G = 0xf,
+}
+
+impl Condition {
+    /// Returns the condition that holds exactly when `self` does not.
+    pub fn negate(self) -> Condition {
+        match self {
+            Condition::B => Condition::Ae,
+            Condition::Ae => Condition::B,
+            Condition::E => Condition::Ne,
+            Condition::Ne => Condition::E,
+            Condition::Be => Condition::A,
+            Condition::A => Condition::Be,
+            Condition::P => Condition::Np,
+            Condition::Np => Condition::P,
+            Condition::L => Condition::Ge,
+            Condition::Ge => Condition::L,
+            Condition::Le => Condition::G,
+            Condition::G => Condition::Le,
+        }
+    }
+
+    /// Returns the condition that holds for `cmp a, b` when `self` holds for `cmp b, a`.
+    pub fn swap(self) -> Condition {
+        match self {
+            Condition::B => Condition::A,
+            Condition::Ae => Condition::Be,
+            Condition::Be => Condition::Ae,
+            Condition::A => Condition::B,
+            Condition::L => Condition::G,
+            Condition::Ge => Condition::Le,
+            Condition::Le => Condition::Ge,
+            Condition::G => Condition::L,
+            _ => self,
+        }
+    }
+}
+
+pub trait Jcc {
+    fn jcc(self, condition: Condition) -> Vec<u8>;
+}
+
+impl Jcc for i32 {
+    fn jcc(self, condition: Condition) -> Vec<u8> {
+        let mut code = vec![0x0f, 0x80 | condition as u8];
+        code.extend_from_slice(&self.to_le_bytes());
+        code
+    }
+}
+
+pub trait Setcc {
+    fn setcc(self, condition: Condition) -> Vec<u8>;
+}
+
+impl Setcc for Register8 {
+    fn setcc(self, condition: Condition) -> Vec<u8> {
+        let mut code = vec![0x0f, 0x90 | condition as u8];
+        code.push(0xc0 | self.number());
+        code
+    }
+}
+
+pub trait Or<Src> {
+    fn or(self, src: Src) -> Vec<u8>;
+}
+
+impl Or<Register64> for Register64 {
+    fn or(self, src: Register64) -> Vec<u8> {
+        opcode_rm_reg(0x09, self, src)
+    }
+}
+
+pub trait Xor<Src> {
+    fn xor(self, src: Src) -> Vec<u8>;
+}
+
+impl Xor<Register64> for Register64 {
+    fn xor(self, src: Register64) -> Vec<u8> {
+        opcode_rm_reg(0x31, self, src)
+    }
+}
+
+impl And<Register64> for Register64 {
+    fn and(self, src: Register64) -> Vec<u8> {
+        opcode_rm_reg(0x21, self, src)
+    }
+}
+
+/// Encodes an SSE instruction with register operands. The mandatory prefix goes before REX.
+fn sse_reg_reg(prefix: &[u8], w: bool, opcode: u8, reg: u8, rm: u8) -> Vec<u8> {
+    let mut code = prefix.to_vec();
+    if w || reg >= 8 || rm >= 8 {
+        code.push(rex(w, reg >= 8, false, rm >= 8));
+    }
+    code.push(0x0f);
+    code.push(opcode);
+    code.push(mod_rm(3, reg, rm));
+    code
+}
+
+fn sse_reg_mem(prefix: &[u8], opcode: u8, reg: u8, mem: &Addressing<Register64>) -> Vec<u8> {
+    let mut code = prefix.to_vec();
+    let base = mem.base.number();
+    if reg >= 8 || base >= 8 {
+        code.push(rex(false, reg >= 8, false, base >= 8));
+    }
+    code.push(0x0f);
+    code.push(opcode);
+    code.extend_from_slice(&mem.to_code(reg));
+    code
+}
+
+/// Defines a trait for an SSE instruction that takes two xmm registers.
+macro_rules! sse_op {
+    ($($trait:ident::$method:ident => [$($prefix:expr),*], $opcode:expr;)+) => {
+        $(
+            pub trait $trait<Src> {
+                fn $method(self, src: Src) -> Vec<u8>;
+            }
+
+            impl $trait<XmmRegister> for XmmRegister {
+                fn $method(self, src: XmmRegister) -> Vec<u8> {
+                    sse_reg_reg(&[$($prefix),*], false, $opcode, self.number(), src.number())
+                }
+            }
+        )+
+    };
+}
+
+sse_op! {
+    Addss::addss => [0xf3], 0x58;
+    Addsd::addsd => [0xf2], 0x58;
+    Subss::subss => [0xf3], 0x5c;
+    Subsd::subsd => [0xf2], 0x5c;
+    Mulss::mulss => [0xf3], 0x59;
+    Mulsd::mulsd => [0xf2], 0x59;
+    Divss::divss => [0xf3], 0x5e;
+    Divsd::divsd => [0xf2], 0x5e;
+    Sqrtss::sqrtss => [0xf3], 0x51;
+    Sqrtsd::sqrtsd => [0xf2], 0x51;
+    Minss::minss => [0xf3], 0x5d;
+    Minsd::minsd => [0xf2], 0x5d;
+    Maxss::maxss => [0xf3], 0x5f;
+    Maxsd::maxsd => [0xf2], 0x5f;
+    Ucomiss::ucomiss => [], 0x2e;
+    Ucomisd::ucomisd => [0x66], 0x2e;
+    Andps::andps => [], 0x54;
+    Orps::orps => [], 0x56;
+    Cvtss2sd::cvtss2sd => [0xf3], 0x5a;
+    Cvtsd2ss::cvtsd2ss => [0xf2], 0x5a;
+}
+
+pub trait Movq<Src> {
+    fn movq(self, src: Src) -> Vec<u8>;
+}
+
+impl Movq<Register64> for XmmRegister {
+    fn movq(self, src: Register64) -> Vec<u8> {
+        sse_reg_reg(&[0x66], true, 0x6e, self.number(), src.number())
+    }
+}
+
+impl Movq<XmmRegister> for Register64 {
+    fn movq(self, src: XmmRegister) -> Vec<u8> {
+        sse_reg_reg(&[0x66], true, 0x7e, src.number(), self.number())
+    }
+}
+
+impl Movq<XmmRegister> for Addressing<Register64> {
+    fn movq(self, src: XmmRegister) -> Vec<u8> {
+        sse_reg_mem(&[0x66], 0xd6, src.number(), &self)
+    }
+}
+
+/// `cmpss`/`cmpsd` set the destination to all ones if the predicate holds and to zero otherwise.
+/// Predicates: 0 equal, 1 less than, 2 less or equal, 4 not equal. Only "not equal" holds for NaN.
+pub trait Cmpss<Src> {
+    fn cmpss(self, src: Src, predicate: u8) -> Vec<u8>;
+}
+
+impl Cmpss<XmmRegister> for XmmRegister {
+    fn cmpss(self, src: XmmRegister, predicate: u8) -> Vec<u8> {
+        let mut code = sse_reg_reg(&[0xf3], false, 0xc2, self.number(), src.number());
+        code.push(predicate);
+        code
+    }
+}
+
+pub trait Cmpsd<Src> {
+    fn cmpsd(self, src: Src, predicate: u8) -> Vec<u8>;
+}
+
+impl Cmpsd<XmmRegister> for XmmRegister {
+    fn cmpsd(self, src: XmmRegister, predicate: u8) -> Vec<u8> {
+        let mut code = sse_reg_reg(&[0xf2], false, 0xc2, self.number(), src.number());
+        code.push(predicate);
+        code
+    }
+}
+
+/// Rounding modes of `roundss`/`roundsd` (SSE4.1). Precision exceptions are suppressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RoundMode {
+    Nearest = 0b1000,
+    Floor = 0b1001,
+    Ceil = 0b1010,
+    Trunc = 0b1011,
+}
+
+pub trait Roundss<Src> {
+    fn roundss(self, src: Src, mode: RoundMode) -> Vec<u8>;
+}
+
+impl Roundss<XmmRegister> for XmmRegister {
+    fn roundss(self, src: XmmRegister, mode: RoundMode) -> Vec<u8> {
+        round(0x0a, self, src, mode)
+    }
+}
+
+pub trait Roundsd<Src> {
+    fn roundsd(self, src: Src, mode: RoundMode) -> Vec<u8>;
+}
+
+impl Roundsd<XmmRegister> for XmmRegister {
+    fn roundsd(self, src: XmmRegister, mode: RoundMode) -> Vec<u8> {
+        round(0x0b, self, src, mode)
+    }
+}
+
+fn round(opcode: u8, dest: XmmRegister, src: XmmRegister, mode: RoundMode) -> Vec<u8> {
+    let (dest, src) = (dest.number(), src.number());
+    let mut code = vec![0x66];
+    if dest >= 8 || src >= 8 {
+        code.push(rex(false, dest >= 8, false, src >= 8));
+    }
+    code.extend_from_slice(&[0x0f, 0x3a, opcode, mod_rm(3, dest, src), mode as u8]);
+    code
+}
+
+/// Defines a trait for a conversion between an xmm register and a general purpose register.
+/// The size of the general purpose register picks the 32 or 64-bit integer form.
+macro_rules! sse_convert_op {
+    ($($trait:ident::$method:ident => [$($prefix:expr),*], $opcode:expr, $dest:ident <- $src:ident;)+) => {
+        $(
+            pub trait $trait<Src> {
+                fn $method(self, src: Src) -> Vec<u8>;
+            }
+
+            sse_convert_op!(@impl $trait::$method, [$($prefix),*], $opcode, $dest, $src);
+        )+
+    };
+    (@impl $trait:ident::$method:ident, [$($prefix:expr),*], $opcode:expr, XmmRegister, Gpr) => {
+        impl $trait<Register64> for XmmRegister {
+            fn $method(self, src: Register64) -> Vec<u8> {
+                sse_reg_reg(&[$($prefix),*], true, $opcode, self.number(), src.number())
+            }
+        }
+
+        impl $trait<Register32> for XmmRegister {
+            fn $method(self, src: Register32) -> Vec<u8> {
+                sse_reg_reg(&[$($prefix),*], false, $opcode, self.number(), src.number())
+            }
+        }
+    };
+    (@impl $trait:ident::$method:ident, [$($prefix:expr),*], $opcode:expr, Gpr, XmmRegister) => {
+        impl $trait<XmmRegister> for Register64 {
+            fn $method(self, src: XmmRegister) -> Vec<u8> {
+                sse_reg_reg(&[$($prefix),*], true, $opcode, self.number(), src.number())
+            }
+        }
+
+        impl $trait<XmmRegister> for Register32 {
+            fn $method(self, src: XmmRegister) -> Vec<u8> {
+                sse_reg_reg(&[$($prefix),*], false, $opcode, self.number(), src.number())
+            }
+        }
+    };
+}
+
+sse_convert_op! {
+    Cvtsi2ss::cvtsi2ss => [0xf3], 0x2a, XmmRegister <- Gpr;
+    Cvtsi2sd::cvtsi2sd => [0xf2], 0x2a, XmmRegister <- Gpr;
+    Cvttss2si::cvttss2si => [0xf3], 0x2c, Gpr <- XmmRegister;
+    Cvttsd2si::cvttsd2si => [0xf2], 0x2c, Gpr <- XmmRegister;
+}
+
+/// Encodes `opcode reg, r/m` with register operands, the reverse of `opcode_rm_reg`.
+fn opcode_reg_rm<R>(prefix: &[u8], opcode: &[u8], dest: R, src: R) -> Vec<u8>
+where
+    R: RegisterNumber + RegisterSize,
+{
+    let mut code = prefix.to_vec();
+    let dest_number = dest.number();
+    let src_number = src.number();
+    code.push(rex(
+        dest.size() == 8,
+        dest_number >= 8,
+        false,
+        src_number >= 8,
+    ));
+    code.extend_from_slice(opcode);
+    code.push(mod_rm(3, dest_number, src_number));
+    code
+}
+
+/// Encodes a group instruction (`opcode /ext`) with a single register operand.
+fn opcode_ext_rm<R>(opcode: u8, ext: u8, dest: R) -> Vec<u8>
+where
+    R: RegisterNumber + RegisterSize,
+{
+    let number = dest.number();
+    vec![
+        rex(dest.size() == 8, false, false, number >= 8),
+        opcode,
+        mod_rm(3, ext, number),
+    ]
+}
+
+impl Or<Register32> for Register32 {
+    fn or(self, src: Register32) -> Vec<u8> {
+        opcode_rm_reg(0x09, self, src)
+    }
+}
+
+impl Xor<Register32> for Register32 {
+    fn xor(self, src: Register32) -> Vec<u8> {
+        opcode_rm_reg(0x31, self, src)
+    }
+}
+
+impl And<Register32> for Register32 {
+    fn and(self, src: Register32) -> Vec<u8> {
+        opcode_rm_reg(0x21, self, src)
+    }
+}
+
+pub trait Imul<Src> {
+    fn imul(self, src: Src) -> Vec<u8>;
+}
+
+impl Imul<Register64> for Register64 {
+    fn imul(self, src: Register64) -> Vec<u8> {
+        opcode_reg_rm(&[], &[0x0f, 0xaf], self, src)
+    }
+}
+
+impl Imul<Register32> for Register32 {
+    fn imul(self, src: Register32) -> Vec<u8> {
+        opcode_reg_rm(&[], &[0x0f, 0xaf], self, src)
+    }
+}
+
+/// Signed division of rdx:rax (edx:eax) by the operand. The quotient is left in rax and the
+/// remainder in rdx.
+pub trait Idiv {
+    fn idiv(self) -> Vec<u8>;
+}
+
+impl Idiv for Register64 {
+    fn idiv(self) -> Vec<u8> {
+        opcode_ext_rm(0xf7, 7, self)
+    }
+}
+
+impl Idiv for Register32 {
+    fn idiv(self) -> Vec<u8> {
+        opcode_ext_rm(0xf7, 7, self)
+    }
+}
+
+/// Unsigned counterpart of `Idiv`.
+pub trait Div {
+    fn div(self) -> Vec<u8>;
+}
+
+impl Div for Register64 {
+    fn div(self) -> Vec<u8> {
+        opcode_ext_rm(0xf7, 6, self)
+    }
+}
+
+impl Div for Register32 {
+    fn div(self) -> Vec<u8> {
+        opcode_ext_rm(0xf7, 6, self)
+    }
+}
+
+/// Sign-extends rax into rdx.
+pub fn cqo() -> Vec<u8> {
+    vec![0x48, 0x99]
+}
+
+/// Sign-extends eax into edx.
+pub fn cdq() -> Vec<u8> {
+    vec![0x99]
+}
+
+/// Defines a trait for a shift or rotate, which takes the count in cl or as an immediate.
+macro_rules! shift_op {
+    ($($trait:ident::$method:ident => $ext:expr;)+) => {
+        $(
+            pub trait $trait<Src> {
+                fn $method(self, src: Src) -> Vec<u8>;
+            }
+
+            impl $trait<Register8> for Register64 {
+                fn $method(self, src: Register8) -> Vec<u8> {
+                    assert_eq!(src, Register8::Cl);
+                    opcode_ext_rm(0xd3, $ext, self)
+                }
+            }
+
+            impl $trait<Register8> for Register32 {
+                fn $method(self, src: Register8) -> Vec<u8> {
+                    assert_eq!(src, Register8::Cl);
+                    opcode_ext_rm(0xd3, $ext, self)
+                }
+            }
+
+            impl $trait<u8> for Register64 {
+                fn $method(self, src: u8) -> Vec<u8> {
+                    let mut code = opcode_ext_rm(0xc1, $ext, self);
+                    code.push(src);
+                    code
+                }
+            }
+
+            impl $trait<u8> for Register32 {
+                fn $method(self, src: u8) -> Vec<u8> {
+                    let mut code = opcode_ext_rm(0xc1, $ext, self);
+                    code.push(src);
+                    code
+                }
+            }
+        )+
+    };
+}
+
+shift_op! {
+    Rol::rol => 0;
+    Ror::ror => 1;
+    Shl::shl => 4;
+    Shr::shr => 5;
+    Sar::sar => 7;
+}
+
+/// Defines a trait for a bit counting instruction.
+macro_rules! bit_count_op {
+    ($($trait:ident::$method:ident => [$($prefix:expr),*], $opcode:expr;)+) => {
+        $(
+            pub trait $trait<Src> {
+                fn $method(self, src: Src) -> Vec<u8>;
+            }
+
+            impl $trait<Register64> for Register64 {
+                fn $method(self, src: Register64) -> Vec<u8> {
+                    opcode_reg_rm(&[$($prefix),*], &[0x0f, $opcode], self, src)
+                }
+            }
+
+            impl $trait<Register32> for Register32 {
+                fn $method(self, src: Register32) -> Vec<u8> {
+                    opcode_reg_rm(&[$($prefix),*], &[0x0f, $opcode], self, src)
+                }
+            }
+        )+
+    };
+}
+
+bit_count_op! {
+    // bsr and bsf set ZF and leave the destination undefined if the source is zero
+    Bsr::bsr => [], 0xbd;
+    Bsf::bsf => [], 0xbc;
+    Popcnt::popcnt => [0xf3], 0xb8;
+}
@@ -1,33 +1,59 @@
+mod code_memory;
+pub mod error;
+#[cfg(feature = "gdb-jit")]
+mod gdb_jit;
+pub mod listing;
+mod perf;
+
 use crate::{
     assembler::{
-        ret, Add, Call, Cmp, Je, Jmp, Jne, Mov, Movzx, Pop, Push,
+        cdq, cqo, ret, Add, Addsd, Addss, And, Andps, Bsf, Bsr, BytePtr, Call, Cmp, Condition,
+        Cvtsd2ss, Cvtsi2sd, Cvtsi2ss, Cvtss2sd, Cvttsd2si, Cvttss2si, Div, Divsd, Divss, Idiv,
+        Imul, Jae, Jb, Jcc, Je, Jmp, Jne, Jp, Lea, Maxsd, Maxss, Minsd, Minss, Mov, Movq, Movsx,
+        Movsxd, Movzx, Mulsd, Mulss, Or, Orps, Pop, Popcnt, Push,
         Register32::{self, *},
         Register64::{self, *},
         Register8::*,
-        Sete, Sub,
+        Rol, Ror, RoundMode, Roundsd, Roundss, Sar, Setcc, Shl, Shr, Sqrtsd, Sqrtss, Sub, Subsd,
+        Subss, Ucomisd, Ucomiss, WordPtr,
+        XmmRegister::{self, *},
+        Xor,
     },
     wasm::Func,
 };
-use anyhow::{bail, Result};
-use libc::{c_int, c_void, size_t, PROT_EXEC, PROT_READ, PROT_WRITE};
-use std::{
-    alloc::{alloc, dealloc, Layout},
-    collections::VecDeque,
-};
-use wasmparser::{BlockType, Operator};
+use anyhow::{bail, Error, Result};
+use code_memory::CodeMemory;
+use error::CompileError;
+use listing::FuncListing;
+use std::{cmp::Ordering, collections::VecDeque, mem::offset_of, ops::Range, path::Path};
+use wasmparser::{BlockType, FuncType, MemArg, Operator, ValType};
 
-use crate::runtime::{store::Store, Runtime};
+use crate::runtime::{error::Trap, store::Store, table::TableElement, Runtime};
 use fxhash::FxHashMap;
 
-extern "C" {
-    fn mprotect(addr: *const c_void, len: size_t, prot: c_int) -> c_int;
-}
+pub use code_memory::RESERVED_SIZE as MAX_CODE_SIZE;
 
 pub struct Compiler {
-    pub p_start: *mut u8,
+    code: CodeMemory,
+    /// Set when emitting ran out of code memory. The rest of the function is dropped.
+    code_error: Option<Error>,
     pub p_current: *mut u8,
     pub p_func_start: *mut u8,
+    /// End of the code of the last compiled function. `p_current` is past the padding after it.
+    p_func_end: *mut u8,
+    pub trap_stub: *const u8,
     pub func_cache: FxHashMap<u32, *const ()>,
+    /// Listings of the functions compiled since `enable_listing`.
+    listings: Option<Vec<FuncListing>>,
+    /// Operators of the function being compiled and the offsets of their code, while listings
+    /// are enabled.
+    listing_ops: Vec<(usize, String)>,
+    /// The `perf` files compiled functions are announced in.
+    perf: perf::Sinks,
+    /// Functions compiled since `enable_gdb_jit` and announced to a debugger, which are
+    /// withdrawn with the code.
+    #[cfg(feature = "gdb-jit")]
+    gdb_registrations: Option<Vec<gdb_jit::Registration>>,
 }
 
 enum Label {
@@ -42,12 +68,18 @@ enum Label {
         start_offset: usize,
         block_type: BlockType,
         else_vartual_stack: Option<VartualStack>,
+        /// Jumps from an `if` to its `else`, or to the end if there is none.
+        else_reserved: Vec<*mut u8>,
     },
 }
 
-const CODE_AREA_SIZE: usize = 1024;
-const PAGE_SIZE: usize = 4096;
-pub type JITFunc = fn(runtime: &mut Runtime, sp: *mut u64) -> u64;
+/// Space that has to be available before a function is started, so that its prologue is
+/// always emitted and jumps are never patched into the previous function.
+const MIN_FUNC_SPACE: usize = 64;
+/// Alignment of the first instruction of every function.
+const FUNC_ALIGN: usize = 16;
+/// Compiled functions and the runtime helpers they call use the SysV calling convention.
+pub type JITFunc = unsafe extern "C" fn(runtime: &mut Runtime, sp: *mut u64) -> u64;
 
 macro_rules! code {
     {$self:expr; $($code:expr),+} => {
@@ -61,17 +93,35 @@ macro_rules! code {
 enum StackValue {
     Imm(i64),
     Reg(Register64),
+    /// A float in the low lane of an xmm register.
+    FReg(XmmRegister),
 }
 
-#[derive(Debug, Clone)]
+/// A comparison whose result is still in the flags.
+#[derive(Debug, Clone, Copy)]
 enum CmpOp {
-    Eq,
+    Cond(Condition),
+    /// Float `eq` after `ucomis`, which is false when the operands are unordered.
+    FloatEq,
+    /// Float `ne` after `ucomis`, which is true when the operands are unordered.
+    FloatNe,
+}
+
+impl CmpOp {
+    fn negate(self) -> CmpOp {
+        match self {
+            CmpOp::Cond(condition) => CmpOp::Cond(condition.negate()),
+            CmpOp::FloatEq => CmpOp::FloatNe,
+            CmpOp::FloatNe => CmpOp::FloatEq,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 struct VartualStack {
     stack: VecDeque<StackValue>,
     unused_regs: VecDeque<Register64>,
+    unused_fregs: VecDeque<XmmRegister>,
     has_cmp: Option<CmpOp>,
 }
 
@@ -80,20 +130,53 @@ impl VartualStack {
         VartualStack {
             stack: VecDeque::new(),
             unused_regs: VecDeque::from(vec![Rdi, Rsi, Rdx, Rcx, R8, R9, R10]),
+            // xmm0 is a scratch register like rax
+            unused_fregs: VecDeque::from(vec![Xmm1, Xmm2, Xmm3, Xmm4, Xmm5, Xmm6, Xmm7]),
             has_cmp: None,
         }
     }
 
+    /// Materializes a pending comparison as a 0 or 1 on top of the stack.
     unsafe fn push_cmp(&mut self, compiler: &mut Compiler) {
-        if let Some(CmpOp::Eq) = self.has_cmp.take() {
-            let reg = self.unused_regs.pop_front().expect("no unused register");
-            code! {compiler;
-                Al.sete(),
-                Eax.movzx(Al),
-                reg.mov(Rax)
-            };
-            self.stack.push_back(StackValue::Reg(reg));
+        let Some(cmp) = self.has_cmp.take() else {
+            return;
+        };
+        // spilling only moves values around, so the flags survive it
+        let reg = self.get_unused_reg(compiler);
+        match cmp {
+            CmpOp::Cond(condition) => {
+                code! {compiler;
+                    Al.setcc(condition),
+                    Eax.movzx(Al)
+                };
+            }
+            // unordered operands set the parity flag, which skips the setcc
+            CmpOp::FloatEq | CmpOp::FloatNe => {
+                code! {compiler;
+                    Eax.mov(matches!(cmp, CmpOp::FloatNe) as i32),
+                    3_i32.jp(),
+                    Al.setcc(if matches!(cmp, CmpOp::FloatEq) { Condition::E } else { Condition::Ne })
+                };
+            }
+        }
+        code! {compiler;
+            reg.mov(Rax)
+        };
+        self.stack.push_back(StackValue::Reg(reg));
+    }
+
+    /// Pops an i32 condition, leaving it in the flags as the returned comparison.
+    unsafe fn pop_cmp(&mut self, compiler: &mut Compiler) -> CmpOp {
+        if let Some(cmp) = self.has_cmp.take() {
+            return cmp;
         }
+        let reg = self.pop_reg(compiler);
+        let reg32: Register32 = reg.into();
+        code! {compiler;
+            reg32.cmp(0)
+        };
+        self.unused_regs.push_back(reg);
+        CmpOp::Cond(Condition::Ne)
     }
 
     unsafe fn get_unused_reg(&mut self, compiler: &mut Compiler) -> Register64 {
@@ -116,6 +199,42 @@ impl VartualStack {
                     };
                     return reg;
                 }
+                StackValue::FReg(freg) => {
+                    code! {compiler;
+                        Compiler::push_data_float(freg)
+                    };
+                    self.unused_fregs.push_back(freg);
+                }
+            }
+        }
+    }
+
+    unsafe fn get_unused_freg(&mut self, compiler: &mut Compiler) -> XmmRegister {
+        if let Some(freg) = self.unused_fregs.pop_front() {
+            return freg;
+        }
+        loop {
+            let value = self.stack.pop_front().expect("stack is empty");
+            self.push_cmp(compiler);
+            match value {
+                StackValue::Imm(n) => {
+                    code! {compiler;
+                        Rax.mov(n),
+                        Compiler::push_data(Rax)
+                    }
+                }
+                StackValue::Reg(reg) => {
+                    code! {compiler;
+                        Compiler::push_data(reg)
+                    };
+                    self.unused_regs.push_back(reg);
+                }
+                StackValue::FReg(freg) => {
+                    code! {compiler;
+                        Compiler::push_data_float(freg)
+                    };
+                    return freg;
+                }
             }
         }
     }
@@ -132,6 +251,83 @@ impl VartualStack {
         StackValue::Reg(reg)
     }
 
+    /// Pops a value into a general purpose register.
+    unsafe fn pop_reg(&mut self, compiler: &mut Compiler) -> Register64 {
+        let value = self.pop_value(compiler);
+        self.load_reg(compiler, value)
+    }
+
+    /// Moves a popped value into a general purpose register.
+    unsafe fn load_reg(&mut self, compiler: &mut Compiler, value: StackValue) -> Register64 {
+        match value {
+            StackValue::Imm(n) => {
+                let reg = self.get_unused_reg(compiler);
+                code! {compiler;
+                    reg.mov(n)
+                };
+                reg
+            }
+            StackValue::Reg(reg) => reg,
+            StackValue::FReg(freg) => {
+                let reg = self.get_unused_reg(compiler);
+                code! {compiler;
+                    reg.movq(freg)
+                };
+                self.unused_fregs.push_back(freg);
+                reg
+            }
+        }
+    }
+
+    /// Frees `reg` for an instruction that needs that specific register by moving the value
+    /// that lives in it to another register.
+    unsafe fn take_reg(&mut self, compiler: &mut Compiler, reg: Register64) {
+        if let Some(i) = self.unused_regs.iter().position(|unused| *unused == reg) {
+            self.unused_regs.remove(i);
+            return;
+        }
+        let other = self.get_unused_reg(compiler);
+        if other == reg {
+            // the value was spilled to make room
+            return;
+        }
+        for value in self.stack.iter_mut() {
+            if let StackValue::Reg(used) = value {
+                if *used == reg {
+                    code! {compiler;
+                        other.mov(reg)
+                    };
+                    *used = other;
+                    return;
+                }
+            }
+        }
+        unreachable!("{:?} is neither unused nor on the stack", reg);
+    }
+
+    /// Pops a value into an xmm register.
+    unsafe fn pop_freg(&mut self, compiler: &mut Compiler) -> XmmRegister {
+        match self.pop_value(compiler) {
+            StackValue::Imm(n) => {
+                let freg = self.get_unused_freg(compiler);
+                code! {compiler;
+                    Rax.mov(n),
+                    freg.movq(Rax)
+                };
+                freg
+            }
+            StackValue::Reg(reg) => {
+                let freg = self.get_unused_freg(compiler);
+                code! {compiler;
+                    freg.movq(reg)
+                };
+                self.unused_regs.push_back(reg);
+                freg
+            }
+            StackValue::FReg(freg) => freg,
+        }
+    }
+
     unsafe fn push_all(&mut self, compiler: &mut Compiler) {
         self.push_cmp(compiler);
         while let Some(value) = self.stack.pop_front() {
@@ -148,41 +344,107 @@ impl VartualStack {
                     };
                     self.unused_regs.push_back(reg);
                 }
+                StackValue::FReg(freg) => {
+                    code! {compiler;
+                        Compiler::push_data_float(freg)
+                    };
+                    self.unused_fregs.push_back(freg);
+                }
             }
         }
     }
 }
 
 impl Compiler {
-    pub(crate) unsafe fn new() -> Compiler {
-        let layout = Layout::from_size_align(CODE_AREA_SIZE, PAGE_SIZE).unwrap();
-        let p_start = alloc(layout);
-        let r = mprotect(
-            p_start as *const c_void,
-            CODE_AREA_SIZE,
-            PROT_READ | PROT_WRITE | PROT_EXEC,
-        );
-        assert!(r == 0);
-        Compiler {
-            p_start,
+    pub(crate) unsafe fn new() -> Result<Compiler> {
+        let code = CodeMemory::new()?;
+        let p_start = code.start();
+        let mut compiler = Compiler {
+            code,
+            code_error: None,
             p_current: p_start,
             p_func_start: p_start,
+            p_func_end: p_start,
+            trap_stub: p_start,
             func_cache: FxHashMap::default(),
+            listings: None,
+            listing_ops: Vec::new(),
+            perf: perf::Sinks::default(),
+            #[cfg(feature = "gdb-jit")]
+            gdb_registrations: None,
+        };
+        // The trap stub is entered with the trap code in edi, either by a jump from JIT code or by
+        // the signal handler. It unwinds the current frame with the error in rax.
+        code! {compiler;
+            Rsp.and(-16),
+            R10.mov(Runtime::trap_internal as *const () as usize as i64),
+            R10.call(),
+            Rsp.mov(Rbp),
+            Rbp.pop(),
+            ret()
+        };
+        if let Some(err) = compiler.code_error.take() {
+            return Err(err);
         }
+        compiler.code.seal(compiler.p_current)?;
+        compiler.p_func_start = compiler.p_current;
+        Ok(compiler)
     }
 
-    pub(crate) unsafe fn free(&self) {
-        let layout = Layout::from_size_align(CODE_AREA_SIZE, PAGE_SIZE).unwrap();
-        let r = mprotect(
-            self.p_start as *const c_void,
-            CODE_AREA_SIZE,
-            PROT_READ | PROT_WRITE,
-        );
-        assert!(r == 0);
-        dealloc(self.p_start, layout);
+    pub(crate) fn code_range(&self) -> Range<usize> {
+        self.code.reserved_range()
+    }
+
+    /// Limits the total size of compiled code to `limit` bytes, at most `MAX_CODE_SIZE`.
+    pub(crate) fn set_code_limit(&mut self, limit: usize) {
+        self.code.set_limit(limit);
+    }
+
+    /// Keeps a listing of every function compiled from now on.
+    pub(crate) fn enable_listing(&mut self) {
+        self.listings.get_or_insert_with(Vec::new);
+    }
+
+    pub(crate) fn listings(&self) -> &[FuncListing] {
+        self.listings.as_deref().unwrap_or_default()
+    }
+
+    /// Adds the functions compiled from now on to `/tmp/perf-<pid>.map`.
+    pub(crate) fn enable_perf_map(&mut self) -> Result<()> {
+        perf::open_perf_map()?;
+        self.perf.perf_map = true;
+        Ok(())
+    }
+
+    /// Writes the functions compiled from now on to `jit-<pid>.dump` in `dir`.
+    pub(crate) fn enable_jitdump(&mut self, dir: &Path) -> Result<()> {
+        perf::open_jitdump(dir)?;
+        self.perf.jitdump = true;
+        Ok(())
+    }
+
+    /// Announces the functions compiled from now on to gdb and lldb.
+    #[cfg(feature = "gdb-jit")]
+    pub(crate) fn enable_gdb_jit(&mut self) {
+        self.gdb_registrations.get_or_insert_with(Vec::new);
+    }
+
+    /// Marks where the code for `op` starts if listings are enabled.
+    fn record_op(&mut self, op: impl FnOnce() -> String) {
+        if self.listings.is_some() {
+            let offset = self.p_current as usize - self.p_func_start as usize;
+            self.listing_ops.push((offset, op()));
+        }
     }
 
     unsafe fn push_code(&mut self, code: &[u8]) {
+        if self.code_error.is_some() {
+            return;
+        }
+        if let Err(err) = self.code.commit(self.p_current.add(code.len())) {
+            self.code_error = Some(err);
+            return;
+        }
         for byte in code.iter() {
             *self.p_current = *byte;
             self.p_current = self.p_current.add(1);
@@ -196,25 +458,556 @@ impl Compiler {
         }
     }
 
+    // `lea` leaves the flags untouched, so pending comparisons survive a spill
     unsafe fn push_data(data: Register64) -> Vec<u8> {
         let mut code = Vec::new();
         code.extend_from_slice(&R11.to_mem().mov(data));
-        code.extend_from_slice(&R11.add(8));
+        code.extend_from_slice(&R11.lea(R11.with_offset(8)));
+        code
+    }
+
+    unsafe fn push_data_float(data: XmmRegister) -> Vec<u8> {
+        let mut code = Vec::new();
+        code.extend_from_slice(&R11.to_mem().movq(data));
+        code.extend_from_slice(&R11.lea(R11.with_offset(8)));
         code
     }
 
     unsafe fn pop_data(data: Register64) -> Vec<u8> {
         let mut code = Vec::new();
-        code.extend_from_slice(&R11.add(-8));
+        code.extend_from_slice(&R11.lea(R11.with_offset(-8)));
         code.extend_from_slice(&data.mov(R11.to_mem()));
         code
     }
 
+    /// Emits a call to a runtime helper. The arguments have to be set up by the caller
+    /// and the virtual stack has to be spilled.
+    unsafe fn call_helper(&mut self, helper: *const ()) {
+        code! {self;
+            R10.mov(helper as usize as i64),
+            R11.push(),
+            R10.call(),
+            R11.pop()
+        };
+    }
+
+    /// Returns from the current function with the error in rax unless it is zero.
+    unsafe fn propagate_error(&mut self) {
+        code! {self;
+            Rax.cmp(0),
+            0_i32.je()
+        };
+        let ok = self.p_current;
+        code! {self;
+            Rsp.mov(Rbp),
+            Rbp.pop(),
+            ret()
+        };
+        let relative_offset = self.p_current as usize - ok as usize;
+        Compiler::write_i32(ok.sub(4), relative_offset as i32);
+    }
+
+    /// Emits jumps taken when `cmp` evaluates to `taken` and returns the addresses right after
+    /// each of them, which still have to be pointed at the target.
+    unsafe fn jump_if(&mut self, cmp: CmpOp, taken: bool) -> Vec<*mut u8> {
+        let cmp = if taken { cmp } else { cmp.negate() };
+        match cmp {
+            CmpOp::Cond(condition) => {
+                code! {self;
+                    0_i32.jcc(condition)
+                };
+                vec![self.p_current]
+            }
+            CmpOp::FloatEq => {
+                // skip the je when unordered
+                code! {self;
+                    6_i32.jp(),
+                    0_i32.je()
+                };
+                vec![self.p_current]
+            }
+            CmpOp::FloatNe => {
+                code! {self;
+                    0_i32.jp()
+                };
+                let unordered = self.p_current;
+                code! {self;
+                    0_i32.jne()
+                };
+                vec![unordered, self.p_current]
+            }
+        }
+    }
+
+    unsafe fn trap(&mut self, trap: Trap) {
+        code! {self;
+            Edi.mov(trap as i32),
+            R10.mov(self.trap_stub as usize as i64),
+            R10.jmp()
+        };
+    }
+
+    /// Emits `jump` over a trap. `jump` has to be a conditional jump with a zero displacement.
+    unsafe fn trap_unless(&mut self, jump: Vec<u8>, trap: Trap) {
+        self.push_code(&jump);
+        let ok = self.p_current;
+        self.trap(trap);
+        let relative_offset = self.p_current as usize - ok as usize;
+        Compiler::write_i32(ok.sub(4), relative_offset as i32);
+    }
+
+    /// Calls `target` with the arguments on the data stack and leaves the results in their
+    /// place. The function index has to be in edx and the virtual stack has to be spilled.
+    unsafe fn call_func(
+        &mut self,
+        target: *const u8,
+        func_type: &FuncType,
+        stack_count: &mut usize,
+    ) {
+        let args_num = func_type.params().len() as i32;
+        code! {self;
+            Rdi.mov(Rbp.with_offset(-8)),
+            Rsi.mov(R11),
+            R10.mov(target as usize as i64),
+            R11.push(),
+            R10.call()
+        }
+
+        *stack_count -= args_num as usize;
+
+        code! {self;
+            R11.pop()
+        }
+        self.propagate_error();
+        code! {self;
+            R11.add(8 * (func_type.results().len() as i32 - args_num))
+        }
+        *stack_count += func_type.results().len();
+    }
+
+    /// Pops a wasm address and returns a register holding the host address it refers to.
+    /// Addresses past the end of the memory land in its guard region.
+    unsafe fn memory_address(
+        &mut self,
+        store: &Store<'_>,
+        vartual_stack: &mut VartualStack,
+        memarg: &MemArg,
+    ) -> Result<Register64> {
+        let base = store.get_memory(memarg.memory)?.base() as i64 + memarg.offset as i64;
+        match vartual_stack.pop_value(self) {
+            StackValue::Imm(n) => {
+                let reg = vartual_stack.get_unused_reg(self);
+                code! {self;
+                    reg.mov(base + n as u32 as i64)
+                };
+                Ok(reg)
+            }
+            StackValue::Reg(reg) => {
+                code! {self;
+                    Eax.mov(Register32::from(reg)),
+                    reg.mov(base),
+                    reg.add(Rax)
+                };
+                Ok(reg)
+            }
+            StackValue::FReg(_) => unreachable!("float operand"),
+        }
+    }
+
+    /// Only numeric values fit in the registers and stack slots the compiler uses.
+    fn check_value_type(ty: ValType, func_symbol: impl FnOnce() -> String) -> Result<()> {
+        match ty {
+            ValType::I32 | ValType::I64 | ValType::F32 | ValType::F64 => Ok(()),
+            _ => bail!(CompileError::UnsupportedValueType(format!(
+                "{:?} in {}",
+                ty,
+                func_symbol()
+            ))),
+        }
+    }
+
+    /// Returns the number of params and results of a block.
+    fn block_arity(store: &Store<'_>, block_type: &BlockType) -> Result<(usize, usize)> {
+        match block_type {
+            BlockType::Empty => Ok((0, 0)),
+            BlockType::Type(_) => Ok((0, 1)),
+            BlockType::FuncType(n) => {
+                let func_type = store.get_func_type(*n)?;
+                Ok((func_type.params().len(), func_type.results().len()))
+            }
+        }
+    }
+
+    /// Returns the stack height a branch to `label` unwinds to and the number of values it carries.
+    fn label_arity(store: &Store<'_>, func_index: u32, label: &Label) -> Result<(usize, usize)> {
+        match label {
+            Label::FuncEnd(_) => {
+                let func_type = store.get_func_type_from_func_index(func_index)?;
+                Ok((0, func_type.results().len()))
+            }
+            Label::LoopStart {
+                start_offset,
+                block_type,
+                ..
+            } => Ok((*start_offset, Compiler::block_arity(store, block_type)?.0)),
+            Label::End {
+                start_offset,
+                block_type,
+                ..
+            } => Ok((*start_offset, Compiler::block_arity(store, block_type)?.1)),
+        }
+    }
+
+    /// Moves the top `arity` values of the data stack down to `start_offset` and drops the rest.
+    /// Expects every value of the virtual stack to be spilled.
+    unsafe fn move_results(&mut self, stack_count: usize, start_offset: usize, arity: usize) {
+        let dropped = (stack_count - start_offset - arity) as i32;
+        if dropped == 0 {
+            return;
+        }
+        for i in 0..arity as i32 {
+            let src = -8 * (arity as i32 - i);
+            code! {self;
+                Rax.mov(R11.with_offset(src)),
+                R11.with_offset(src - 8 * dropped).mov(Rax)
+            };
+        }
+        code! {self;
+            R11.lea(R11.with_offset(-8 * dropped))
+        };
+    }
+
+    /// Emits an unconditional branch to the label `relative_depth` levels up.
+    unsafe fn branch(
+        &mut self,
+        store: &Store<'_>,
+        func_index: u32,
+        labels: &mut [Label],
+        relative_depth: u32,
+        stack_count: usize,
+    ) -> Result<()> {
+        let index = labels.len() - 1 - relative_depth as usize;
+        let (start_offset, arity) = Compiler::label_arity(store, func_index, &labels[index])?;
+        self.move_results(stack_count, start_offset, arity);
+        code! {self;
+            0_i32.jmp()
+        };
+        match &mut labels[index] {
+            Label::LoopStart { start, .. } => {
+                let relative_offset = *start as isize - self.p_current as isize;
+                Compiler::write_i32(self.p_current.sub(4), relative_offset as i32);
+            }
+            Label::FuncEnd(address_reserved)
+            | Label::End {
+                address_reserved, ..
+            } => {
+                address_reserved.push(self.p_current);
+            }
+        }
+        Ok(())
+    }
+
+    /// Truncates the float in `src` toward zero into `dest`. Out of range values and NaN trap,
+    /// or saturate for the `trunc_sat` variants. `src` is clobbered.
+    unsafe fn trunc(&mut self, op: &Operator, dest: Register64, src: XmmRegister) {
+        use Operator::*;
+        let is_f64 = matches!(
+            op,
+            I32TruncF64S
+                | I32TruncF64U
+                | I64TruncF64S
+                | I64TruncF64U
+                | I32TruncSatF64S
+                | I32TruncSatF64U
+                | I64TruncSatF64S
+                | I64TruncSatF64U
+        );
+        let is_i64 = matches!(
+            op,
+            I64TruncF32S
+                | I64TruncF32U
+                | I64TruncF64S
+                | I64TruncF64U
+                | I64TruncSatF32S
+                | I64TruncSatF32U
+                | I64TruncSatF64S
+                | I64TruncSatF64U
+        );
+        let is_signed = matches!(
+            op,
+            I32TruncF32S
+                | I32TruncF64S
+                | I64TruncF32S
+                | I64TruncF64S
+                | I32TruncSatF32S
+                | I32TruncSatF64S
+                | I64TruncSatF32S
+                | I64TruncSatF64S
+        );
+        let is_sat = matches!(
+            op,
+            I32TruncSatF32S
+                | I32TruncSatF32U
+                | I32TruncSatF64S
+                | I32TruncSatF64U
+                | I64TruncSatF32S
+                | I64TruncSatF32U
+                | I64TruncSatF64S
+                | I64TruncSatF64U
+        );
+        // the result is valid iff lower < src < upper. the signed lower bounds are the
+        // closest floats below the minimum
+        let (lower, upper, min, max) = match (is_i64, is_signed) {
+            (false, true) => (
+                if is_f64 { -2147483649.0 } else { -2147483904.0 },
+                2147483648.0,
+                i32::MIN as i64,
+                i32::MAX as i64,
+            ),
+            (false, false) => (-1.0, 4294967296.0, 0, u32::MAX as i64),
+            (true, true) => (
+                if is_f64 {
+                    -9223372036854777856.0
+                } else {
+                    -9223373136366403584.0
+                },
+                9223372036854775808.0,
+                i64::MIN,
+                i64::MAX,
+            ),
+            (true, false) => (-1.0, 18446744073709551616.0, 0, -1),
+        };
+        let float_bits = |value: f64| {
+            if is_f64 {
+                value.to_bits() as i64
+            } else {
+                (value as f32).to_bits() as i64
+            }
+        };
+        let ucomis = |lhs: XmmRegister, rhs: XmmRegister| {
+            if is_f64 {
+                lhs.ucomisd(rhs)
+            } else {
+                lhs.ucomiss(rhs)
+            }
+        };
+        let convert = |src: XmmRegister| match (is_f64, is_i64) {
+            (true, true) => dest.cvttsd2si(src),
+            (false, true) => dest.cvttss2si(src),
+            // unsigned i32 results need the 64-bit conversion
+            (true, false) if !is_signed => dest.cvttsd2si(src),
+            (false, false) if !is_signed => dest.cvttss2si(src),
+            (true, false) => Register32::from(dest).cvttsd2si(src),
+            (false, false) => Register32::from(dest).cvttss2si(src),
+        };
+        // (jumps to patch, value to saturate to)
+        let mut saturated: Vec<(Vec<*mut u8>, i64)> = Vec::new();
+        code! {self;
+            Rax.mov(float_bits(lower)),
+            Xmm0.movq(Rax),
+            ucomis(src, Xmm0)
+        };
+        if is_sat {
+            code! {self;
+                0_i32.jp()
+            };
+            saturated.push((vec![self.p_current], 0));
+            code! {self;
+                0_i32.jcc(Condition::Be)
+            };
+            saturated.push((vec![self.p_current], min));
+        } else {
+            self.trap_unless(0_i32.jcc(Condition::Np), Trap::InvalidConversionToInteger);
+            self.trap_unless(0_i32.jcc(Condition::A), Trap::IntegerOverflow);
+        }
+        code! {self;
+            Rax.mov(float_bits(upper)),
+            Xmm0.movq(Rax),
+            ucomis(Xmm0, src)
+        };
+        if is_sat {
+            code! {self;
+                0_i32.jcc(Condition::Be)
+            };
+            saturated.push((vec![self.p_current], max));
+        } else {
+            self.trap_unless(0_i32.jcc(Condition::A), Trap::IntegerOverflow);
+        }
+        if is_i64 && !is_signed {
+            // values from 2^63 up are converted minus 2^63, which then sets the top bit
+            code! {self;
+                Rax.mov(float_bits(9223372036854775808.0)),
+                Xmm0.movq(Rax),
+                ucomis(src, Xmm0),
+                0_i32.jae()
+            };
+            let big = self.p_current;
+            code! {self;
+                convert(src),
+                0_i32.jmp()
+            };
+            let done = self.p_current;
+            let relative_offset = self.p_current as usize - big as usize;
+            Compiler::write_i32(big.sub(4), relative_offset as i32);
+            code! {self;
+                if is_f64 { src.subsd(Xmm0) } else { src.subss(Xmm0) },
+                convert(src),
+                Rax.mov(i64::MIN),
+                dest.xor(Rax)
+            };
+            let relative_offset = self.p_current as usize - done as usize;
+            Compiler::write_i32(done.sub(4), relative_offset as i32);
+        } else {
+            code! {self;
+                convert(src)
+            };
+        }
+        if saturated.is_empty() {
+            return;
+        }
+        let mut done = Vec::new();
+        for (jumps, value) in saturated {
+            code! {self;
+                0_i32.jmp()
+            };
+            done.push(self.p_current);
+            for address in jumps {
+                let relative_offset = self.p_current as usize - address as usize;
+                Compiler::write_i32(address.sub(4), relative_offset as i32);
+            }
+            code! {self;
+                dest.mov(value)
+            };
+        }
+        for address in done {
+            let relative_offset = self.p_current as usize - address as usize;
+            Compiler::write_i32(address.sub(4), relative_offset as i32);
+        }
+    }
+
+    /// Returns the condition under which `cmp lhs, rhs` satisfies an integer comparison.
+    fn int_condition(op: &Operator) -> Condition {
+        match op {
+            Operator::I32Eq | Operator::I64Eq => Condition::E,
+            Operator::I32Ne | Operator::I64Ne => Condition::Ne,
+            Operator::I32LtS | Operator::I64LtS => Condition::L,
+            Operator::I32LtU | Operator::I64LtU => Condition::B,
+            Operator::I32GtS | Operator::I64GtS => Condition::G,
+            Operator::I32GtU | Operator::I64GtU => Condition::A,
+            Operator::I32LeS | Operator::I64LeS => Condition::Le,
+            Operator::I32LeU | Operator::I64LeU => Condition::Be,
+            Operator::I32GeS | Operator::I64GeS => Condition::Ge,
+            Operator::I32GeU | Operator::I64GeU => Condition::Ae,
+            _ => unreachable!("not a comparison operator: {:?}", op),
+        }
+    }
+
+    /// Evaluates an integer condition on constants given their signed and unsigned ordering.
+    fn fold_condition(condition: Condition, signed: Ordering, unsigned: Ordering) -> bool {
+        match condition {
+            Condition::E => signed.is_eq(),
+            Condition::Ne => signed.is_ne(),
+            Condition::L => signed.is_lt(),
+            Condition::G => signed.is_gt(),
+            Condition::Le => signed.is_le(),
+            Condition::Ge => signed.is_ge(),
+            Condition::B => unsigned.is_lt(),
+            Condition::A => unsigned.is_gt(),
+            Condition::Be => unsigned.is_le(),
+            Condition::Ae => unsigned.is_ge(),
+            Condition::P | Condition::Np => unreachable!("not an integer condition"),
+        }
+    }
+
+    /// Emits `dest = dest op src` for a two-operand integer instruction.
+    fn alu(op: &Operator, dest: Register64, src: Register64) -> Vec<u8> {
+        let (dest32, src32): (Register32, Register32) = (dest.into(), src.into());
+        match op {
+            Operator::I32Add => dest32.add(src32),
+            Operator::I64Add => dest.add(src),
+            Operator::I32Sub => dest32.sub(src32),
+            Operator::I64Sub => dest.sub(src),
+            Operator::I32Mul => dest32.imul(src32),
+            Operator::I64Mul => dest.imul(src),
+            Operator::I32And => dest32.and(src32),
+            Operator::I64And => dest.and(src),
+            Operator::I32Or => dest32.or(src32),
+            Operator::I64Or => dest.or(src),
+            Operator::I32Xor => dest32.xor(src32),
+            Operator::I64Xor => dest.xor(src),
+            _ => unreachable!("not an alu operator: {:?}", op),
+        }
+    }
+
+    /// Emits a shift or rotate of `dest` by `count`, either cl or an immediate.
+    fn shift<Count>(op: &Operator, dest: Register64, count: Count) -> Vec<u8>
+    where
+        Register32: Shl<Count> + Shr<Count> + Sar<Count> + Rol<Count> + Ror<Count>,
+        Register64: Shl<Count> + Shr<Count> + Sar<Count> + Rol<Count> + Ror<Count>,
+    {
+        let dest32: Register32 = dest.into();
+        match op {
+            Operator::I32Shl => dest32.shl(count),
+            Operator::I32ShrS => dest32.sar(count),
+            Operator::I32ShrU => dest32.shr(count),
+            Operator::I32Rotl => dest32.rol(count),
+            Operator::I32Rotr => dest32.ror(count),
+            Operator::I64Shl => dest.shl(count),
+            Operator::I64ShrS => dest.sar(count),
+            Operator::I64ShrU => dest.shr(count),
+            Operator::I64Rotl => dest.rol(count),
+            Operator::I64Rotr => dest.ror(count),
+            _ => unreachable!("not a shift operator: {:?}", op),
+        }
+    }
+
+    /// Evaluates an integer operator on constants. i32 results are sign-extended like
+    /// `i32.const`. Returns `None` if the operation traps.
+    fn fold_binary(op: &Operator, n: i64, m: i64) -> Option<i64> {
+        let (a, b) = (n as i32, m as i32);
+        let value = match op {
+            Operator::I32Add => a.wrapping_add(b) as i64,
+            Operator::I32Sub => a.wrapping_sub(b) as i64,
+            Operator::I32Mul => a.wrapping_mul(b) as i64,
+            Operator::I32DivS => a.checked_div(b)? as i64,
+            Operator::I32DivU => (a as u32).checked_div(b as u32)? as i32 as i64,
+            Operator::I32RemS => (b != 0).then(|| a.wrapping_rem(b))? as i64,
+            Operator::I32RemU => (a as u32).checked_rem(b as u32)? as i32 as i64,
+            Operator::I32And => (a & b) as i64,
+            Operator::I32Or => (a | b) as i64,
+            Operator::I32Xor => (a ^ b) as i64,
+            Operator::I32Shl => a.wrapping_shl(b as u32) as i64,
+            Operator::I32ShrS => a.wrapping_shr(b as u32) as i64,
+            Operator::I32ShrU => (a as u32).wrapping_shr(b as u32) as i32 as i64,
+            Operator::I32Rotl => a.rotate_left(b as u32) as i64,
+            Operator::I32Rotr => a.rotate_right(b as u32) as i64,
+            Operator::I64Add => n.wrapping_add(m),
+            Operator::I64Sub => n.wrapping_sub(m),
+            Operator::I64Mul => n.wrapping_mul(m),
+            Operator::I64DivS => n.checked_div(m)?,
+            Operator::I64DivU => (n as u64).checked_div(m as u64)? as i64,
+            Operator::I64RemS => (m != 0).then(|| n.wrapping_rem(m))?,
+            Operator::I64RemU => (n as u64).checked_rem(m as u64)? as i64,
+            Operator::I64And => n & m,
+            Operator::I64Or => n | m,
+            Operator::I64Xor => n ^ m,
+            Operator::I64Shl => n.wrapping_shl(m as u32),
+            Operator::I64ShrS => n.wrapping_shr(m as u32),
+            Operator::I64ShrU => (n as u64).wrapping_shr(m as u32) as i64,
+            Operator::I64Rotl => n.rotate_left(m as u32),
+            Operator::I64Rotr => n.rotate_right(m as u32),
+            _ => unreachable!("not a binary operator: {:?}", op),
+        };
+        Some(value)
+    }
+
     fn local_offset(local_index: u32) -> u32 {
         8 * (Self::LOCAL_BASE_COUNT + 1) + local_index * 8
     }
 
     const LOCAL_BASE_COUNT: u32 = 2;
+    /// Up to this many declared locals are zeroed without a loop.
+    const UNROLLED_LOCALS: usize = 8;
 
     unsafe fn compile(
         &mut self,
@@ -225,33 +1018,213 @@ impl Compiler {
         vartual_stack: &mut VartualStack,
         labels: &mut Vec<Label>,
     ) -> Result<()> {
+        // `Some(depth)` while skipping code that follows an unconditional branch,
+        // where `depth` counts the blocks opened inside the dead code
+        let mut unreachable_depth: Option<usize> = None;
         for instr in &func.body {
-            match instr {
-                Operator::Call { function_index } => {
-                    let func_type = store.get_func_type_from_func_index(*function_index)?;
-                    let args_num = func_type.params().len() as i32;
-                    vartual_stack.push_all(self);
-
+            if let Some(depth) = unreachable_depth.as_mut() {
+                match instr {
+                    Operator::Block { .. } | Operator::Loop { .. } | Operator::If { .. } => {
+                        *depth += 1;
+                        continue;
+                    }
+                    Operator::End if *depth != 0 => {
+                        *depth -= 1;
+                        continue;
+                    }
+                    Operator::Else | Operator::End if *depth == 0 => {}
+                    _ => continue,
+                }
+            }
+            self.record_op(|| {
+                let name = match instr {
+                    Operator::LocalGet { local_index }
+                    | Operator::LocalSet { local_index }
+                    | Operator::LocalTee { local_index } => {
+                        store.local_name(func_index, *local_index)
+                    }
+                    Operator::Call { function_index } => store.func_name(*function_index),
+                    _ => None,
+                };
+                match name {
+                    Some(name) => format!("{:?} ${}", instr, name),
+                    None => format!("{:?}", instr),
+                }
+            });
+            // only branches, select and eqz consume a comparison straight from the flags
+            if !matches!(
+                instr,
+                Operator::If { .. }
+                    | Operator::BrIf { .. }
+                    | Operator::Select
+                    | Operator::TypedSelect { .. }
+                    | Operator::I32Eqz
+            ) {
+                vartual_stack.push_cmp(self);
+            }
+            match instr {
+                Operator::Call { function_index } => {
+                    let func_type = store.get_func_type_from_func_index(*function_index)?;
+                    vartual_stack.push_all(self);
+                    code! {self;
+                        Edx.mov(*function_index as i32)
+                    };
+                    let target = if *function_index == func_index {
+                        self.p_func_start
+                    } else {
+                        Runtime::call_func_internal as *const u8
+                    };
+                    self.call_func(target, func_type, stack_count);
+                }
+                Operator::CallIndirect {
+                    type_index,
+                    table_index,
+                } => {
+                    let func_type = store.get_func_type(*type_index)?;
+                    let table = store.get_table(*table_index)?;
+                    let index = vartual_stack.pop_reg(self);
+                    *stack_count -= 1;
+                    vartual_stack.push_all(self);
+                    code! {self;
+                        Eax.mov(Register32::from(index)),
+                        Rax.cmp(table.size() as i32)
+                    };
+                    vartual_stack.unused_regs.push_back(index);
+                    self.trap_unless(0_i32.jb(), Trap::UndefinedElement);
+                    // rax = &elements[index], a null element has a zero type id
+                    code! {self;
+                        Rax.shl(3),
+                        Rdx.mov(table.base() as i64),
+                        Rax.add(Rdx),
+                        Edx.mov(Rax.with_offset(offset_of!(TableElement, type_id) as i32)),
+                        Edx.cmp(0)
+                    };
+                    self.trap_unless(0_i32.jne(), Trap::UninitializedElement);
+                    code! {self;
+                        Edx.cmp(store.canonical_types[*type_index as usize] as i32 + 1)
+                    };
+                    self.trap_unless(0_i32.je(), Trap::IndirectCallTypeMismatch);
+                    code! {self;
+                        Edx.mov(Rax.with_offset(offset_of!(TableElement, func_index) as i32))
+                    };
+                    self.call_func(
+                        Runtime::call_func_internal as *const u8,
+                        func_type,
+                        stack_count,
+                    );
+                }
+                Operator::Unreachable => {
+                    vartual_stack.push_all(self);
+                    self.trap(Trap::Unreachable);
+                    unreachable_depth = Some(0);
+                }
+                Operator::I32Load { memarg }
+                | Operator::I64Load { memarg }
+                | Operator::F32Load { memarg }
+                | Operator::F64Load { memarg }
+                | Operator::I32Load8S { memarg }
+                | Operator::I32Load8U { memarg }
+                | Operator::I32Load16S { memarg }
+                | Operator::I32Load16U { memarg }
+                | Operator::I64Load8S { memarg }
+                | Operator::I64Load8U { memarg }
+                | Operator::I64Load16S { memarg }
+                | Operator::I64Load16U { memarg }
+                | Operator::I64Load32S { memarg }
+                | Operator::I64Load32U { memarg } => {
+                    let reg = self.memory_address(store, vartual_stack, memarg)?;
+                    let reg32: Register32 = reg.into();
+                    let mem = reg.to_mem();
+                    code! {self;
+                        match instr {
+                            Operator::I64Load { .. } | Operator::F64Load { .. } => reg.mov(mem),
+                            Operator::I32Load8S { .. } => reg32.movsx(BytePtr(mem)),
+                            Operator::I32Load8U { .. } | Operator::I64Load8U { .. } => {
+                                reg32.movzx(BytePtr(mem))
+                            }
+                            Operator::I32Load16S { .. } => reg32.movsx(WordPtr(mem)),
+                            Operator::I32Load16U { .. } | Operator::I64Load16U { .. } => {
+                                reg32.movzx(WordPtr(mem))
+                            }
+                            Operator::I64Load8S { .. } => reg.movsx(BytePtr(mem)),
+                            Operator::I64Load16S { .. } => reg.movsx(WordPtr(mem)),
+                            Operator::I64Load32S { .. } => reg.movsxd(mem),
+                            _ => reg32.mov(mem),
+                        }
+                    };
+                    vartual_stack.stack.push_back(StackValue::Reg(reg));
+                }
+                Operator::I32Store { memarg }
+                | Operator::I64Store { memarg }
+                | Operator::F32Store { memarg }
+                | Operator::F64Store { memarg }
+                | Operator::I32Store8 { memarg }
+                | Operator::I32Store16 { memarg }
+                | Operator::I64Store8 { memarg }
+                | Operator::I64Store16 { memarg }
+                | Operator::I64Store32 { memarg } => {
+                    // rax is clobbered while computing the address, so immediates need a register
+                    let value = vartual_stack.pop_reg(self);
+                    let reg = self.memory_address(store, vartual_stack, memarg)?;
+                    let value32: Register32 = value.into();
+                    let mem = reg.to_mem();
+                    code! {self;
+                        match instr {
+                            Operator::I64Store { .. } | Operator::F64Store { .. } => mem.mov(value),
+                            Operator::I32Store8 { .. } | Operator::I64Store8 { .. } => {
+                                BytePtr(mem).mov(value32)
+                            }
+                            Operator::I32Store16 { .. } | Operator::I64Store16 { .. } => {
+                                WordPtr(mem).mov(value32)
+                            }
+                            _ => mem.mov(value32),
+                        }
+                    };
+                    vartual_stack.unused_regs.push_back(value);
+                    vartual_stack.unused_regs.push_back(reg);
+                    *stack_count -= 2;
+                }
+                Operator::MemorySize { mem } => {
+                    vartual_stack.push_all(self);
                     code! {self;
                         Rdi.mov(Rbp.with_offset(-8)),
-                        Rsi.mov(R11),
-                        Edx.mov(*function_index as i32),
-                        if *function_index == func_index {
-                            R10.mov(self.p_func_start as usize as i64)
-                        } else {
-                            R10.mov(Runtime::call_func_internal as usize as i64)
-                        },
-                        R11.push(),
-                        R10.call()
-                    }
-
-                    *stack_count -= args_num as usize;
-
+                        Esi.mov(*mem as i32)
+                    };
+                    self.call_helper(Runtime::memory_size_internal as *const ());
+                    let reg = vartual_stack.get_unused_reg(self);
                     code! {self;
-                        R11.pop(),
-                        R11.add(8 * (func_type.results().len() as i32 - args_num))
+                        reg.mov(Rax)
+                    };
+                    vartual_stack.stack.push_back(StackValue::Reg(reg));
+                    *stack_count += 1;
+                }
+                Operator::MemoryGrow { mem } => {
+                    let value = vartual_stack.pop_value(self);
+                    vartual_stack.push_all(self);
+                    match value {
+                        StackValue::Imm(n) => {
+                            code! {self;
+                                Edx.mov(n as i32)
+                            };
+                        }
+                        StackValue::Reg(reg) => {
+                            code! {self;
+                                Edx.mov(Register32::from(reg))
+                            };
+                            vartual_stack.unused_regs.push_back(reg);
+                        }
+                        StackValue::FReg(_) => unreachable!("float operand"),
                     }
-                    *stack_count += func_type.results().len();
+                    code! {self;
+                        Rdi.mov(Rbp.with_offset(-8)),
+                        Esi.mov(*mem as i32)
+                    };
+                    self.call_helper(Runtime::memory_grow_internal as *const ());
+                    let reg = vartual_stack.get_unused_reg(self);
+                    code! {self;
+                        reg.mov(Rax)
+                    };
+                    vartual_stack.stack.push_back(StackValue::Reg(reg));
                 }
                 Operator::LocalGet { local_index } => {
                     let offset = Compiler::local_offset(*local_index) as i32;
@@ -262,6 +1235,74 @@ impl Compiler {
                     vartual_stack.stack.push_back(StackValue::Reg(reg));
                     *stack_count += 1;
                 }
+                Operator::LocalSet { local_index } => {
+                    let offset = Compiler::local_offset(*local_index) as i32;
+                    let value = vartual_stack.pop_value(self);
+                    match value {
+                        StackValue::Imm(n) => {
+                            code! {self;
+                                Rax.mov(n),
+                                Rbp.with_offset(-offset).mov(Rax)
+                            };
+                        }
+                        StackValue::Reg(reg) => {
+                            code! {self;
+                                Rbp.with_offset(-offset).mov(reg)
+                            };
+                            vartual_stack.unused_regs.push_back(reg);
+                        }
+                        StackValue::FReg(freg) => {
+                            code! {self;
+                                Rbp.with_offset(-offset).movq(freg)
+                            };
+                            vartual_stack.unused_fregs.push_back(freg);
+                        }
+                    }
+                    *stack_count -= 1;
+                }
+                Operator::LocalTee { local_index } => {
+                    let offset = Compiler::local_offset(*local_index) as i32;
+                    let value = vartual_stack.pop_value(self);
+                    match value {
+                        StackValue::Imm(n) => {
+                            code! {self;
+                                Rax.mov(n),
+                                Rbp.with_offset(-offset).mov(Rax)
+                            };
+                        }
+                        StackValue::Reg(reg) => {
+                            code! {self;
+                                Rbp.with_offset(-offset).mov(reg)
+                            };
+                        }
+                        StackValue::FReg(freg) => {
+                            code! {self;
+                                Rbp.with_offset(-offset).movq(freg)
+                            };
+                        }
+                    }
+                    vartual_stack.stack.push_back(value);
+                }
+                Operator::GlobalGet { global_index } => {
+                    let global = store.get_global(*global_index)?.as_ptr();
+                    let reg = vartual_stack.get_unused_reg(self);
+                    code! {self;
+                        reg.mov(global as i64),
+                        reg.mov(reg.to_mem())
+                    };
+                    vartual_stack.stack.push_back(StackValue::Reg(reg));
+                    *stack_count += 1;
+                }
+                Operator::GlobalSet { global_index } => {
+                    let global = store.get_global(*global_index)?.as_ptr();
+                    let value = vartual_stack.pop_reg(self);
+                    code! {self;
+                        Rax.mov(global as i64),
+                        Rax.to_mem().mov(value)
+                    };
+                    vartual_stack.unused_regs.push_back(value);
+                    *stack_count -= 1;
+                }
                 Operator::I32Const { value } => {
                     vartual_stack
                         .stack
@@ -272,188 +1313,907 @@ impl Compiler {
                     vartual_stack.stack.push_back(StackValue::Imm(*value));
                     *stack_count += 1;
                 }
-                Operator::I32Add | Operator::I64Add => {
+                Operator::F32Const { value } => {
+                    vartual_stack
+                        .stack
+                        .push_back(StackValue::Imm(value.bits() as i64));
+                    *stack_count += 1;
+                }
+                Operator::F64Const { value } => {
+                    vartual_stack
+                        .stack
+                        .push_back(StackValue::Imm(value.bits() as i64));
+                    *stack_count += 1;
+                }
+                Operator::I32Add
+                | Operator::I64Add
+                | Operator::I32Sub
+                | Operator::I64Sub
+                | Operator::I32Mul
+                | Operator::I64Mul
+                | Operator::I32And
+                | Operator::I64And
+                | Operator::I32Or
+                | Operator::I64Or
+                | Operator::I32Xor
+                | Operator::I64Xor => {
                     let value2 = vartual_stack.pop_value(self);
                     let value1 = vartual_stack.pop_value(self);
                     match (value1, value2) {
                         (StackValue::Imm(n), StackValue::Imm(m)) => {
-                            vartual_stack.stack.push_back(StackValue::Imm(n + m));
+                            let value = Compiler::fold_binary(instr, n, m).unwrap();
+                            vartual_stack.stack.push_back(StackValue::Imm(value));
                         }
                         (StackValue::Reg(reg1), StackValue::Reg(reg2)) => {
-                            if instr == &Operator::I32Add {
-                                let reg1: Register32 = reg1.into();
-                                let reg2: Register32 = reg2.into();
-                                code! {self;
-                                    reg1.add(reg2)
-                                };
-                            } else {
-                                code! {self;
-                                    reg1.add(reg2)
-                                };
-                            }
+                            code! {self;
+                                Compiler::alu(instr, reg1, reg2)
+                            };
                             vartual_stack.stack.push_back(StackValue::Reg(reg1));
                             vartual_stack.unused_regs.push_back(reg2);
                         }
-                        (StackValue::Reg(reg), StackValue::Imm(n))
-                        | (StackValue::Imm(n), StackValue::Reg(reg)) => {
-                            if instr == &Operator::I32Add {
-                                let reg: Register32 = reg.into();
+                        (StackValue::Reg(reg), StackValue::Imm(n)) => {
+                            code! {self;
+                                Rax.mov(n),
+                                Compiler::alu(instr, reg, Rax)
+                            };
+                            vartual_stack.stack.push_back(StackValue::Reg(reg));
+                        }
+                        (StackValue::Imm(n), StackValue::Reg(reg)) => {
+                            if matches!(instr, Operator::I32Sub | Operator::I64Sub) {
                                 code! {self;
-                                    Eax.mov(n as i32),
-                                    reg.add(Eax)
+                                    Rax.mov(n),
+                                    Compiler::alu(instr, Rax, reg),
+                                    reg.mov(Rax)
                                 };
                             } else {
                                 code! {self;
                                     Rax.mov(n),
-                                    reg.add(Rax)
+                                    Compiler::alu(instr, reg, Rax)
                                 };
                             }
                             vartual_stack.stack.push_back(StackValue::Reg(reg));
                         }
+                        _ => unreachable!("float operand"),
                     }
                     *stack_count -= 1;
                 }
-                Operator::I32Sub | Operator::I64Sub => {
+                Operator::I32Eq
+                | Operator::I32Ne
+                | Operator::I32LtS
+                | Operator::I32LtU
+                | Operator::I32GtS
+                | Operator::I32GtU
+                | Operator::I32LeS
+                | Operator::I32LeU
+                | Operator::I32GeS
+                | Operator::I32GeU
+                | Operator::I64Eq
+                | Operator::I64Ne
+                | Operator::I64LtS
+                | Operator::I64LtU
+                | Operator::I64GtS
+                | Operator::I64GtU
+                | Operator::I64LeS
+                | Operator::I64LeU
+                | Operator::I64GeS
+                | Operator::I64GeU => {
                     let value2 = vartual_stack.pop_value(self);
                     let value1 = vartual_stack.pop_value(self);
+                    let is_64 = matches!(
+                        instr,
+                        Operator::I64Eq
+                            | Operator::I64Ne
+                            | Operator::I64LtS
+                            | Operator::I64LtU
+                            | Operator::I64GtS
+                            | Operator::I64GtU
+                            | Operator::I64LeS
+                            | Operator::I64LeU
+                            | Operator::I64GeS
+                            | Operator::I64GeU
+                    );
+                    let condition = Compiler::int_condition(instr);
+                    // the register is compared on the left, so a constant lhs swaps the condition
+                    let swapped = matches!(value1, StackValue::Imm(_));
                     match (value1, value2) {
                         (StackValue::Imm(n), StackValue::Imm(m)) => {
-                            vartual_stack.stack.push_back(StackValue::Imm(n - m));
+                            let result = if is_64 {
+                                Compiler::fold_condition(
+                                    condition,
+                                    n.cmp(&m),
+                                    (n as u64).cmp(&(m as u64)),
+                                )
+                            } else {
+                                let (n, m) = (n as i32, m as i32);
+                                Compiler::fold_condition(
+                                    condition,
+                                    n.cmp(&m),
+                                    (n as u32).cmp(&(m as u32)),
+                                )
+                            };
+                            vartual_stack
+                                .stack
+                                .push_back(StackValue::Imm(result as i64));
                         }
                         (StackValue::Reg(reg1), StackValue::Reg(reg2)) => {
-                            if instr == &Operator::I32Sub {
-                                let reg1: Register32 = reg1.into();
-                                let reg2: Register32 = reg2.into();
+                            if is_64 {
                                 code! {self;
-                                    reg1.sub(reg2)
+                                    reg1.cmp(reg2)
                                 };
                             } else {
+                                let reg1: Register32 = reg1.into();
+                                let reg2: Register32 = reg2.into();
                                 code! {self;
-                                    reg1.sub(reg2)
+                                    reg1.cmp(reg2)
                                 };
                             }
-                            vartual_stack.stack.push_back(StackValue::Reg(reg1));
+                            vartual_stack.has_cmp = Some(CmpOp::Cond(condition));
+                            vartual_stack.unused_regs.push_back(reg1);
                             vartual_stack.unused_regs.push_back(reg2);
                         }
-                        (value1 @ StackValue::Reg(reg), StackValue::Imm(n))
-                        | (value1 @ StackValue::Imm(n), StackValue::Reg(reg)) => {
-                            if matches!(value1, StackValue::Reg(_)) {
-                                if instr == &Operator::I32Sub {
-                                    let reg: Register32 = reg.into();
-                                    code! {self;
-                                        Eax.mov(n as i32),
-                                        reg.sub(Eax)
-                                    };
-                                } else {
-                                    code! {self;
-                                        Rax.mov(n),
-                                        reg.sub(Rax)
-                                    };
-                                }
-                            } else if instr == &Operator::I32Sub {
-                                let reg: Register32 = reg.into();
+                        (StackValue::Reg(reg), StackValue::Imm(n))
+                        | (StackValue::Imm(n), StackValue::Reg(reg)) => {
+                            let condition = if swapped { condition.swap() } else { condition };
+                            if is_64 {
                                 code! {self;
-                                    Eax.mov(n as i32),
-                                    Eax.sub(reg),
-                                    reg.mov(Eax)
+                                    Rax.mov(n),
+                                    reg.cmp(Rax)
                                 };
                             } else {
+                                let reg: Register32 = reg.into();
                                 code! {self;
-                                    Rax.mov(n),
-                                    Rax.sub(reg),
-                                    reg.mov(Rax)
+                                    reg.cmp(n as i32)
                                 };
                             }
-                            vartual_stack.stack.push_back(StackValue::Reg(reg));
+                            vartual_stack.has_cmp = Some(CmpOp::Cond(condition));
+                            vartual_stack.unused_regs.push_back(reg);
                         }
+                        _ => unreachable!("float operand"),
                     }
                     *stack_count -= 1;
                 }
-                Operator::I32Eq | Operator::I64Eq => {
+                Operator::I32DivS
+                | Operator::I32DivU
+                | Operator::I32RemS
+                | Operator::I32RemU
+                | Operator::I64DivS
+                | Operator::I64DivU
+                | Operator::I64RemS
+                | Operator::I64RemU => {
                     let value2 = vartual_stack.pop_value(self);
                     let value1 = vartual_stack.pop_value(self);
+                    *stack_count -= 1;
+                    if let (StackValue::Imm(n), StackValue::Imm(m)) = (&value1, &value2) {
+                        if let Some(value) = Compiler::fold_binary(instr, *n, *m) {
+                            vartual_stack.stack.push_back(StackValue::Imm(value));
+                            continue;
+                        }
+                    }
+                    let rhs = vartual_stack.load_reg(self, value2);
+                    let lhs = vartual_stack.load_reg(self, value1);
+                    // the dividend goes in rdx:rax, so rdx must not hold anything else
+                    let rhs = if rhs == Rdx {
+                        let reg = vartual_stack.get_unused_reg(self);
+                        code! {self;
+                            reg.mov(Rdx)
+                        };
+                        vartual_stack.unused_regs.push_back(Rdx);
+                        reg
+                    } else {
+                        rhs
+                    };
+                    if lhs != Rdx {
+                        vartual_stack.take_reg(self, Rdx);
+                    }
+                    let is_64 = matches!(
+                        instr,
+                        Operator::I64DivS
+                            | Operator::I64DivU
+                            | Operator::I64RemS
+                            | Operator::I64RemU
+                    );
+                    let is_signed = matches!(
+                        instr,
+                        Operator::I32DivS
+                            | Operator::I32RemS
+                            | Operator::I64DivS
+                            | Operator::I64RemS
+                    );
+                    let is_rem = matches!(
+                        instr,
+                        Operator::I32RemS
+                            | Operator::I32RemU
+                            | Operator::I64RemS
+                            | Operator::I64RemU
+                    );
+                    let (lhs32, rhs32): (Register32, Register32) = (lhs.into(), rhs.into());
+                    code! {self;
+                        if is_64 { rhs.cmp(0) } else { rhs32.cmp(0) }
+                    };
+                    self.trap_unless(0_i32.jne(), Trap::IntegerDivideByZero);
+                    let mut rem_done = None;
+                    if is_signed {
+                        // INT_MIN / -1 overflows and INT_MIN % -1 is 0, but idiv faults on both
+                        code! {self;
+                            if is_64 { rhs.cmp(-1) } else { rhs32.cmp(-1) },
+                            0_i32.jne()
+                        };
+                        let not_minus_one = self.p_current;
+                        if is_rem {
+                            code! {self;
+                                Edx.mov(0),
+                                0_i32.jmp()
+                            };
+                            rem_done = Some(self.p_current);
+                        } else {
+                            if is_64 {
+                                code! {self;
+                                    Rax.mov(i64::MIN),
+                                    lhs.cmp(Rax)
+                                };
+                            } else {
+                                code! {self;
+                                    lhs32.cmp(i32::MIN)
+                                };
+                            }
+                            self.trap_unless(0_i32.jne(), Trap::IntegerOverflow);
+                        }
+                        let relative_offset = self.p_current as usize - not_minus_one as usize;
+                        Compiler::write_i32(not_minus_one.sub(4), relative_offset as i32);
+                    }
+                    code! {self;
+                        Rax.mov(lhs),
+                        match (is_64, is_signed) {
+                            (true, true) => cqo(),
+                            (false, true) => cdq(),
+                            (_, false) => Edx.mov(0),
+                        },
+                        match (is_64, is_signed) {
+                            (true, true) => rhs.idiv(),
+                            (true, false) => rhs.div(),
+                            (false, true) => rhs32.idiv(),
+                            (false, false) => rhs32.div(),
+                        }
+                    };
+                    if let Some(address) = rem_done {
+                        let relative_offset = self.p_current as usize - address as usize;
+                        Compiler::write_i32(address.sub(4), relative_offset as i32);
+                    }
+                    code! {self;
+                        if is_rem { lhs.mov(Rdx) } else { lhs.mov(Rax) }
+                    };
+                    if lhs != Rdx {
+                        vartual_stack.unused_regs.push_back(Rdx);
+                    }
+                    vartual_stack.unused_regs.push_back(rhs);
+                    vartual_stack.stack.push_back(StackValue::Reg(lhs));
+                }
+                Operator::I32Shl
+                | Operator::I32ShrS
+                | Operator::I32ShrU
+                | Operator::I32Rotl
+                | Operator::I32Rotr
+                | Operator::I64Shl
+                | Operator::I64ShrS
+                | Operator::I64ShrU
+                | Operator::I64Rotl
+                | Operator::I64Rotr => {
+                    let value2 = vartual_stack.pop_value(self);
+                    let value1 = vartual_stack.pop_value(self);
+                    *stack_count -= 1;
                     match (value1, value2) {
                         (StackValue::Imm(n), StackValue::Imm(m)) => {
-                            vartual_stack.stack.push_back(StackValue::Imm(if n == m {
-                                1
-                            } else {
-                                0
-                            }));
+                            let value = Compiler::fold_binary(instr, n, m).unwrap();
+                            vartual_stack.stack.push_back(StackValue::Imm(value));
                         }
-                        (StackValue::Reg(reg1), StackValue::Reg(reg2)) => {
-                            if instr == &Operator::I32Add {
-                                let reg1: Register32 = reg1.into();
-                                let reg2: Register32 = reg2.into();
+                        // the count is masked by the instruction just like wasm requires
+                        (value, StackValue::Imm(m)) => {
+                            let reg = vartual_stack.load_reg(self, value);
+                            code! {self;
+                                Compiler::shift(instr, reg, m as u8)
+                            };
+                            vartual_stack.stack.push_back(StackValue::Reg(reg));
+                        }
+                        (value, count) => {
+                            let count = vartual_stack.load_reg(self, count);
+                            let reg = vartual_stack.load_reg(self, value);
+                            // variable counts have to be in cl
+                            let reg = if reg == Rcx {
+                                let other = vartual_stack.get_unused_reg(self);
                                 code! {self;
-                                    reg1.cmp(reg2)
+                                    other.mov(Rcx)
                                 };
+                                vartual_stack.unused_regs.push_back(Rcx);
+                                other
                             } else {
+                                reg
+                            };
+                            if count != Rcx {
+                                vartual_stack.take_reg(self, Rcx);
                                 code! {self;
-                                    reg1.cmp(reg2)
+                                    Rcx.mov(count)
                                 };
+                                vartual_stack.unused_regs.push_back(count);
                             }
-                            vartual_stack.has_cmp = Some(CmpOp::Eq);
-                            vartual_stack.unused_regs.push_back(reg1);
-                            vartual_stack.unused_regs.push_back(reg2);
+                            code! {self;
+                                Compiler::shift(instr, reg, Cl)
+                            };
+                            vartual_stack.unused_regs.push_back(Rcx);
+                            vartual_stack.stack.push_back(StackValue::Reg(reg));
                         }
-                        (StackValue::Reg(reg), StackValue::Imm(n))
-                        | (StackValue::Imm(n), StackValue::Reg(reg)) => {
-                            if instr == &Operator::I32Add {
-                                let reg: Register32 = reg.into();
+                    }
+                }
+                Operator::I32Clz
+                | Operator::I32Ctz
+                | Operator::I32Popcnt
+                | Operator::I64Clz
+                | Operator::I64Ctz
+                | Operator::I64Popcnt => {
+                    if let Some(StackValue::Imm(n)) = vartual_stack.stack.back() {
+                        let (n32, n) = (*n as u32, *n as u64);
+                        let count = match instr {
+                            Operator::I32Clz => n32.leading_zeros(),
+                            Operator::I32Ctz => n32.trailing_zeros(),
+                            Operator::I32Popcnt => n32.count_ones(),
+                            Operator::I64Clz => n.leading_zeros(),
+                            Operator::I64Ctz => n.trailing_zeros(),
+                            _ => n.count_ones(),
+                        };
+                        vartual_stack.stack.pop_back();
+                        vartual_stack.stack.push_back(StackValue::Imm(count as i64));
+                        continue;
+                    }
+                    // popcnt has its own CPUID bit and is not part of baseline x86-64
+                    if matches!(instr, Operator::I32Popcnt | Operator::I64Popcnt)
+                        && !is_x86_feature_detected!("popcnt")
+                    {
+                        bail!(CompileError::UnsupportedOperator(format!(
+                            "{:?} in {} without POPCNT",
+                            instr,
+                            store.func_symbol(func_index)
+                        )));
+                    }
+                    let reg = vartual_stack.pop_reg(self);
+                    let reg32: Register32 = reg.into();
+                    match instr {
+                        Operator::I32Popcnt => code! {self; reg32.popcnt(reg32)},
+                        Operator::I64Popcnt => code! {self; reg.popcnt(reg)},
+                        // clz is (bits - 1) - bsr, with bsr taken as -1 for zero
+                        Operator::I32Clz | Operator::I64Clz => {
+                            code! {self;
+                                if instr == &Operator::I32Clz { Eax.bsr(reg32) } else { Rax.bsr(reg) },
+                                0_i32.jne()
+                            };
+                            let non_zero = self.p_current;
+                            code! {self;
+                                Rax.mov(-1_i64)
+                            };
+                            let relative_offset = self.p_current as usize - non_zero as usize;
+                            Compiler::write_i32(non_zero.sub(4), relative_offset as i32);
+                            if instr == &Operator::I32Clz {
                                 code! {self;
-                                    Eax.mov(n as i32),
-                                    reg.cmp(Eax)
+                                    reg32.mov(31),
+                                    reg32.sub(Eax)
                                 };
                             } else {
                                 code! {self;
-                                    Rax.mov(n),
-                                    reg.cmp(Rax)
+                                    reg.mov(63_i64),
+                                    reg.sub(Rax)
                                 };
                             }
-                            vartual_stack.has_cmp = Some(CmpOp::Eq);
-                            vartual_stack.unused_regs.push_back(reg);
                         }
+                        // ctz is bsf, with the bit width for zero
+                        _ => {
+                            code! {self;
+                                if instr == &Operator::I32Ctz { Eax.bsf(reg32) } else { Rax.bsf(reg) },
+                                0_i32.jne()
+                            };
+                            let non_zero = self.p_current;
+                            code! {self;
+                                Eax.mov(if instr == &Operator::I32Ctz { 32 } else { 64 })
+                            };
+                            let relative_offset = self.p_current as usize - non_zero as usize;
+                            Compiler::write_i32(non_zero.sub(4), relative_offset as i32);
+                            code! {self;
+                                reg.mov(Rax)
+                            };
+                        }
+                    }
+                    vartual_stack.stack.push_back(StackValue::Reg(reg));
+                }
+                Operator::I32Eqz if vartual_stack.has_cmp.is_some() => {
+                    vartual_stack.has_cmp = vartual_stack.has_cmp.map(CmpOp::negate);
+                }
+                Operator::I32Eqz | Operator::I64Eqz => match vartual_stack.pop_value(self) {
+                    StackValue::Imm(n) => {
+                        let is_zero = if instr == &Operator::I32Eqz {
+                            n as i32 == 0
+                        } else {
+                            n == 0
+                        };
+                        vartual_stack
+                            .stack
+                            .push_back(StackValue::Imm(is_zero as i64));
+                    }
+                    StackValue::Reg(reg) => {
+                        if instr == &Operator::I32Eqz {
+                            let reg32: Register32 = reg.into();
+                            code! {self;
+                                reg32.cmp(0)
+                            };
+                        } else {
+                            code! {self;
+                                reg.cmp(0)
+                            };
+                        }
+                        vartual_stack.has_cmp = Some(CmpOp::Cond(Condition::E));
+                        vartual_stack.unused_regs.push_back(reg);
                     }
+                    StackValue::FReg(_) => unreachable!("float operand"),
+                },
+                Operator::Select | Operator::TypedSelect { .. } => {
+                    if vartual_stack.has_cmp.is_none() {
+                        if let Some(StackValue::Imm(n)) = vartual_stack.stack.back() {
+                            let pick_first = *n != 0;
+                            vartual_stack.stack.pop_back();
+                            let value2 = vartual_stack.pop_value(self);
+                            let value1 = vartual_stack.pop_value(self);
+                            let (value, unused) = if pick_first {
+                                (value1, value2)
+                            } else {
+                                (value2, value1)
+                            };
+                            match unused {
+                                StackValue::Imm(_) => {}
+                                StackValue::Reg(reg) => vartual_stack.unused_regs.push_back(reg),
+                                StackValue::FReg(freg) => {
+                                    vartual_stack.unused_fregs.push_back(freg)
+                                }
+                            }
+                            vartual_stack.stack.push_back(value);
+                            *stack_count -= 2;
+                            continue;
+                        }
+                    }
+                    // loading the operands only moves values, so the flags survive it.
+                    // floats are selected as bits in general purpose registers
+                    let cmp = vartual_stack.pop_cmp(self);
+                    let value2 = vartual_stack.pop_value(self);
+                    let value1 = vartual_stack.pop_value(self);
+                    let second = vartual_stack.load_reg(self, value2);
+                    let first = vartual_stack.load_reg(self, value1);
+                    let keep_first = self.jump_if(cmp, true);
+                    code! {self;
+                        first.mov(second)
+                    };
+                    for address in keep_first {
+                        let relative_offset = self.p_current as usize - address as usize;
+                        Compiler::write_i32(address.sub(4), relative_offset as i32);
+                    }
+                    vartual_stack.unused_regs.push_back(second);
+                    vartual_stack.stack.push_back(StackValue::Reg(first));
+                    *stack_count -= 2;
+                }
+                Operator::F32Add
+                | Operator::F32Sub
+                | Operator::F32Mul
+                | Operator::F32Div
+                | Operator::F64Add
+                | Operator::F64Sub
+                | Operator::F64Mul
+                | Operator::F64Div => {
+                    let rhs = vartual_stack.pop_freg(self);
+                    let lhs = vartual_stack.pop_freg(self);
+                    code! {self;
+                        match instr {
+                            Operator::F32Add => lhs.addss(rhs),
+                            Operator::F32Sub => lhs.subss(rhs),
+                            Operator::F32Mul => lhs.mulss(rhs),
+                            Operator::F32Div => lhs.divss(rhs),
+                            Operator::F64Add => lhs.addsd(rhs),
+                            Operator::F64Sub => lhs.subsd(rhs),
+                            Operator::F64Mul => lhs.mulsd(rhs),
+                            _ => lhs.divsd(rhs),
+                        }
+                    };
+                    vartual_stack.unused_fregs.push_back(rhs);
+                    vartual_stack.stack.push_back(StackValue::FReg(lhs));
                     *stack_count -= 1;
                 }
-                Operator::If { blockty } => {
-                    if let Some(CmpOp::Eq) = vartual_stack.has_cmp.take() {
+                Operator::F32Min | Operator::F32Max | Operator::F64Min | Operator::F64Max => {
+                    let rhs = vartual_stack.pop_freg(self);
+                    let lhs = vartual_stack.pop_freg(self);
+                    let is_f32 = matches!(instr, Operator::F32Min | Operator::F32Max);
+                    let is_min = matches!(instr, Operator::F32Min | Operator::F64Min);
+                    // minss/maxss return the second operand if either is NaN or both are zero,
+                    // so those cases are handled separately
+                    code! {self;
+                        if is_f32 { lhs.ucomiss(rhs) } else { lhs.ucomisd(rhs) },
+                        0_i32.jne()
+                    };
+                    let not_equal = self.p_current;
+                    code! {self;
+                        0_i32.jp()
+                    };
+                    let nan = self.p_current;
+                    // equal operands only differ in the sign of zero: -0 wins for min, +0 for max
+                    code! {self;
+                        if is_min { lhs.orps(rhs) } else { lhs.andps(rhs) },
+                        0_i32.jmp()
+                    };
+                    let zero_done = self.p_current;
+                    Compiler::write_i32(
+                        nan.sub(4),
+                        (self.p_current as usize - nan as usize) as i32,
+                    );
+                    code! {self;
+                        if is_f32 { lhs.addss(rhs) } else { lhs.addsd(rhs) },
+                        0_i32.jmp()
+                    };
+                    let nan_done = self.p_current;
+                    Compiler::write_i32(
+                        not_equal.sub(4),
+                        (self.p_current as usize - not_equal as usize) as i32,
+                    );
+                    code! {self;
+                        match (is_f32, is_min) {
+                            (true, true) => lhs.minss(rhs),
+                            (true, false) => lhs.maxss(rhs),
+                            (false, true) => lhs.minsd(rhs),
+                            (false, false) => lhs.maxsd(rhs),
+                        }
+                    };
+                    for address in [zero_done, nan_done] {
+                        let relative_offset = self.p_current as usize - address as usize;
+                        Compiler::write_i32(address.sub(4), relative_offset as i32);
+                    }
+                    vartual_stack.unused_fregs.push_back(rhs);
+                    vartual_stack.stack.push_back(StackValue::FReg(lhs));
+                    *stack_count -= 1;
+                }
+                Operator::F32Sqrt
+                | Operator::F32Ceil
+                | Operator::F32Floor
+                | Operator::F32Trunc
+                | Operator::F32Nearest
+                | Operator::F64Sqrt
+                | Operator::F64Ceil
+                | Operator::F64Floor
+                | Operator::F64Trunc
+                | Operator::F64Nearest => {
+                    // roundss and roundsd are SSE4.1, which is not part of baseline x86-64
+                    if !matches!(instr, Operator::F32Sqrt | Operator::F64Sqrt)
+                        && !is_x86_feature_detected!("sse4.1")
+                    {
+                        bail!(CompileError::UnsupportedOperator(format!(
+                            "{:?} in {} without SSE4.1",
+                            instr,
+                            store.func_symbol(func_index)
+                        )));
+                    }
+                    let freg = vartual_stack.pop_freg(self);
+                    code! {self;
+                        match instr {
+                            Operator::F32Sqrt => freg.sqrtss(freg),
+                            Operator::F32Ceil => freg.roundss(freg, RoundMode::Ceil),
+                            Operator::F32Floor => freg.roundss(freg, RoundMode::Floor),
+                            Operator::F32Trunc => freg.roundss(freg, RoundMode::Trunc),
+                            Operator::F32Nearest => freg.roundss(freg, RoundMode::Nearest),
+                            Operator::F64Sqrt => freg.sqrtsd(freg),
+                            Operator::F64Ceil => freg.roundsd(freg, RoundMode::Ceil),
+                            Operator::F64Floor => freg.roundsd(freg, RoundMode::Floor),
+                            Operator::F64Trunc => freg.roundsd(freg, RoundMode::Trunc),
+                            _ => freg.roundsd(freg, RoundMode::Nearest),
+                        }
+                    };
+                    vartual_stack.stack.push_back(StackValue::FReg(freg));
+                }
+                // abs, neg and copysign only touch the sign bit, which is simplest in a gpr
+                Operator::F32Abs | Operator::F32Neg | Operator::F64Abs | Operator::F64Neg => {
+                    let sign: i64 = if matches!(instr, Operator::F32Abs | Operator::F32Neg) {
+                        1 << 31
+                    } else {
+                        i64::MIN
+                    };
+                    let is_abs = matches!(instr, Operator::F32Abs | Operator::F64Abs);
+                    if let Some(StackValue::Imm(n)) = vartual_stack.stack.back() {
+                        let n = if is_abs { n & !sign } else { n ^ sign };
+                        vartual_stack.stack.pop_back();
+                        vartual_stack.stack.push_back(StackValue::Imm(n));
+                    } else {
+                        let reg = vartual_stack.pop_reg(self);
                         code! {self;
-                            0_i32.jne()
+                            if is_abs { Rax.mov(!sign) } else { Rax.mov(sign) },
+                            if is_abs { reg.and(Rax) } else { reg.xor(Rax) }
                         };
+                        vartual_stack.stack.push_back(StackValue::Reg(reg));
+                    }
+                }
+                Operator::F32Copysign | Operator::F64Copysign => {
+                    let sign: i64 = if instr == &Operator::F32Copysign {
+                        1 << 31
                     } else {
-                        let value = vartual_stack.pop_value(self);
-                        match value {
-                            StackValue::Imm(n) => {
+                        i64::MIN
+                    };
+                    let rhs = vartual_stack.pop_reg(self);
+                    let lhs = vartual_stack.pop_reg(self);
+                    code! {self;
+                        Rax.mov(!sign),
+                        lhs.and(Rax),
+                        Rax.mov(sign),
+                        rhs.and(Rax),
+                        lhs.or(rhs)
+                    };
+                    vartual_stack.unused_regs.push_back(rhs);
+                    vartual_stack.stack.push_back(StackValue::Reg(lhs));
+                    *stack_count -= 1;
+                }
+                Operator::F32Eq
+                | Operator::F32Ne
+                | Operator::F32Lt
+                | Operator::F32Gt
+                | Operator::F32Le
+                | Operator::F32Ge
+                | Operator::F64Eq
+                | Operator::F64Ne
+                | Operator::F64Lt
+                | Operator::F64Gt
+                | Operator::F64Le
+                | Operator::F64Ge => {
+                    let rhs = vartual_stack.pop_freg(self);
+                    let lhs = vartual_stack.pop_freg(self);
+                    // unordered operands set cf, so only "above" conditions are false for NaN;
+                    // lt and le are gt and ge with the operands swapped
+                    let (dest, src, cmp) = match instr {
+                        Operator::F32Eq | Operator::F64Eq => (lhs, rhs, CmpOp::FloatEq),
+                        Operator::F32Ne | Operator::F64Ne => (lhs, rhs, CmpOp::FloatNe),
+                        Operator::F32Gt | Operator::F64Gt => (lhs, rhs, CmpOp::Cond(Condition::A)),
+                        Operator::F32Ge | Operator::F64Ge => (lhs, rhs, CmpOp::Cond(Condition::Ae)),
+                        Operator::F32Lt | Operator::F64Lt => (rhs, lhs, CmpOp::Cond(Condition::A)),
+                        _ => (rhs, lhs, CmpOp::Cond(Condition::Ae)),
+                    };
+                    code! {self;
+                        if matches!(
+                            instr,
+                            Operator::F32Eq
+                                | Operator::F32Ne
+                                | Operator::F32Lt
+                                | Operator::F32Gt
+                                | Operator::F32Le
+                                | Operator::F32Ge
+                        ) {
+                            dest.ucomiss(src)
+                        } else {
+                            dest.ucomisd(src)
+                        }
+                    };
+                    vartual_stack.unused_fregs.push_back(lhs);
+                    vartual_stack.unused_fregs.push_back(rhs);
+                    vartual_stack.has_cmp = Some(cmp);
+                    *stack_count -= 1;
+                }
+                Operator::I32WrapI64 | Operator::I64ExtendI32U => {
+                    match vartual_stack.pop_value(self) {
+                        StackValue::Imm(n) => {
+                            let n = if instr == &Operator::I32WrapI64 {
+                                n as i32 as i64
+                            } else {
+                                n as u32 as i64
+                            };
+                            vartual_stack.stack.push_back(StackValue::Imm(n));
+                        }
+                        StackValue::Reg(reg) => {
+                            // writing a 32-bit register clears the upper half
+                            let reg32: Register32 = reg.into();
+                            code! {self;
+                                reg32.mov(reg32)
+                            };
+                            vartual_stack.stack.push_back(StackValue::Reg(reg));
+                        }
+                        StackValue::FReg(_) => unreachable!("float operand"),
+                    }
+                }
+                Operator::I64ExtendI32S
+                | Operator::I32Extend8S
+                | Operator::I32Extend16S
+                | Operator::I64Extend8S
+                | Operator::I64Extend16S
+                | Operator::I64Extend32S => {
+                    let bits = match instr {
+                        Operator::I32Extend8S | Operator::I64Extend8S => 8,
+                        Operator::I32Extend16S | Operator::I64Extend16S => 16,
+                        _ => 32,
+                    };
+                    match vartual_stack.pop_value(self) {
+                        StackValue::Imm(n) => {
+                            let n = (n << (64 - bits)) >> (64 - bits);
+                            vartual_stack.stack.push_back(StackValue::Imm(n));
+                        }
+                        // shift the sign bit to the top and back
+                        StackValue::Reg(reg) => {
+                            if matches!(instr, Operator::I32Extend8S | Operator::I32Extend16S) {
+                                let reg32: Register32 = reg.into();
                                 code! {self;
-                                    Eax.mov(n as i32),
-                                    Eax.cmp(0),
-                                    0_i32.je()
+                                    reg32.shl(32 - bits),
+                                    reg32.sar(32 - bits)
                                 };
-                            }
-                            StackValue::Reg(reg) => {
-                                let reg32: Register32 = reg.into();
+                            } else {
                                 code! {self;
-                                    reg32.cmp(0),
-                                    0_i32.je()
+                                    reg.shl(64 - bits),
+                                    reg.sar(64 - bits)
                                 };
-                                vartual_stack.unused_regs.push_back(reg);
                             }
+                            vartual_stack.stack.push_back(StackValue::Reg(reg));
                         }
+                        StackValue::FReg(_) => unreachable!("float operand"),
                     }
-                    let params_len = match blockty {
-                        BlockType::FuncType(n) => {
-                            let func_type = store.get_func_type(*n)?;
-                            func_type.params().len()
+                }
+                // floats may live in general purpose registers, so only the ints need moving
+                Operator::I32ReinterpretF32 | Operator::I64ReinterpretF64 => {
+                    match vartual_stack.pop_value(self) {
+                        StackValue::Imm(n) => {
+                            let n = if instr == &Operator::I32ReinterpretF32 {
+                                n as i32 as i64
+                            } else {
+                                n
+                            };
+                            vartual_stack.stack.push_back(StackValue::Imm(n));
+                        }
+                        value => {
+                            let reg = vartual_stack.load_reg(self, value);
+                            vartual_stack.stack.push_back(StackValue::Reg(reg));
+                        }
+                    }
+                }
+                Operator::F32ReinterpretI32 | Operator::F64ReinterpretI64 => {
+                    if instr == &Operator::F32ReinterpretI32 {
+                        if let Some(StackValue::Imm(n)) = vartual_stack.stack.back_mut() {
+                            *n = *n as u32 as i64;
+                        }
+                    }
+                }
+                Operator::F32DemoteF64 | Operator::F64PromoteF32 => {
+                    let freg = vartual_stack.pop_freg(self);
+                    code! {self;
+                        if instr == &Operator::F32DemoteF64 {
+                            freg.cvtsd2ss(freg)
+                        } else {
+                            freg.cvtss2sd(freg)
+                        }
+                    };
+                    vartual_stack.stack.push_back(StackValue::FReg(freg));
+                }
+                Operator::F32ConvertI32S
+                | Operator::F32ConvertI32U
+                | Operator::F32ConvertI64S
+                | Operator::F32ConvertI64U
+                | Operator::F64ConvertI32S
+                | Operator::F64ConvertI32U
+                | Operator::F64ConvertI64S
+                | Operator::F64ConvertI64U => {
+                    let is_f64 = matches!(
+                        instr,
+                        Operator::F64ConvertI32S
+                            | Operator::F64ConvertI32U
+                            | Operator::F64ConvertI64S
+                            | Operator::F64ConvertI64U
+                    );
+                    let reg = vartual_stack.pop_reg(self);
+                    let freg = vartual_stack.get_unused_freg(self);
+                    let convert = |src: Register64| {
+                        if is_f64 {
+                            freg.cvtsi2sd(src)
+                        } else {
+                            freg.cvtsi2ss(src)
                         }
-                        _ => 0,
                     };
+                    let reg32: Register32 = reg.into();
+                    match instr {
+                        Operator::F32ConvertI32S => code! {self; freg.cvtsi2ss(reg32)},
+                        Operator::F64ConvertI32S => code! {self; freg.cvtsi2sd(reg32)},
+                        // zero-extended, an unsigned i32 fits the signed 64-bit conversion
+                        Operator::F32ConvertI32U | Operator::F64ConvertI32U => {
+                            code! {self;
+                                reg32.mov(reg32),
+                                convert(reg)
+                            };
+                        }
+                        Operator::F32ConvertI64S | Operator::F64ConvertI64S => {
+                            code! {self; convert(reg)}
+                        }
+                        // values with the top bit set are halved, keeping the low bit so that
+                        // rounding is unchanged, then converted and doubled
+                        _ => {
+                            code! {self;
+                                reg.cmp(0),
+                                0_i32.jcc(Condition::L)
+                            };
+                            let big = self.p_current;
+                            code! {self;
+                                convert(reg),
+                                0_i32.jmp()
+                            };
+                            let done = self.p_current;
+                            let relative_offset = self.p_current as usize - big as usize;
+                            Compiler::write_i32(big.sub(4), relative_offset as i32);
+                            code! {self;
+                                Rax.mov(reg),
+                                Rax.shr(1),
+                                reg.and(1),
+                                Rax.or(reg),
+                                convert(Rax),
+                                if is_f64 { freg.addsd(freg) } else { freg.addss(freg) }
+                            };
+                            let relative_offset = self.p_current as usize - done as usize;
+                            Compiler::write_i32(done.sub(4), relative_offset as i32);
+                        }
+                    }
+                    vartual_stack.unused_regs.push_back(reg);
+                    vartual_stack.stack.push_back(StackValue::FReg(freg));
+                }
+                Operator::I32TruncF32S
+                | Operator::I32TruncF32U
+                | Operator::I32TruncF64S
+                | Operator::I32TruncF64U
+                | Operator::I64TruncF32S
+                | Operator::I64TruncF32U
+                | Operator::I64TruncF64S
+                | Operator::I64TruncF64U
+                | Operator::I32TruncSatF32S
+                | Operator::I32TruncSatF32U
+                | Operator::I32TruncSatF64S
+                | Operator::I32TruncSatF64U
+                | Operator::I64TruncSatF32S
+                | Operator::I64TruncSatF32U
+                | Operator::I64TruncSatF64S
+                | Operator::I64TruncSatF64U => {
+                    let freg = vartual_stack.pop_freg(self);
+                    let reg = vartual_stack.get_unused_reg(self);
+                    self.trunc(instr, reg, freg);
+                    vartual_stack.unused_fregs.push_back(freg);
+                    vartual_stack.stack.push_back(StackValue::Reg(reg));
+                }
+                Operator::Nop => {}
+                Operator::Drop => {
+                    match vartual_stack.pop_value(self) {
+                        StackValue::Imm(_) => {}
+                        StackValue::Reg(reg) => vartual_stack.unused_regs.push_back(reg),
+                        StackValue::FReg(freg) => vartual_stack.unused_fregs.push_back(freg),
+                    }
                     *stack_count -= 1;
+                }
+                Operator::Block { blockty } => {
+                    let (params_len, _) = Compiler::block_arity(store, blockty)?;
                     labels.push(Label::End {
-                        address_reserved: vec![self.p_current],
+                        address_reserved: Vec::new(),
+                        start_offset: *stack_count - params_len,
+                        block_type: *blockty,
+                        else_vartual_stack: None,
+                        else_reserved: Vec::new(),
+                    });
+                }
+                Operator::Loop { blockty } => {
+                    let (params_len, _) = Compiler::block_arity(store, blockty)?;
+                    // the loop header is a join point, so every value has to live in the data stack
+                    vartual_stack.push_all(self);
+                    labels.push(Label::LoopStart {
+                        start: self.p_current,
+                        start_offset: *stack_count - params_len,
+                        block_type: *blockty,
+                    });
+                }
+                Operator::If { blockty } => {
+                    let cmp = vartual_stack.pop_cmp(self);
+                    *stack_count -= 1;
+                    vartual_stack.push_all(self);
+                    let else_reserved = self.jump_if(cmp, false);
+                    let (params_len, _) = Compiler::block_arity(store, blockty)?;
+                    labels.push(Label::End {
+                        address_reserved: Vec::new(),
                         start_offset: *stack_count - params_len,
                         block_type: *blockty,
                         else_vartual_stack: Some(vartual_stack.clone()),
+                        else_reserved,
                     });
                 }
                 Operator::Else => {
@@ -461,56 +2221,69 @@ impl Compiler {
                     let Label::End {
                         address_reserved,
                         start_offset,
+                        block_type,
                         else_vartual_stack,
-                        ..
+                        else_reserved,
                     } = label
                     else {
                         unreachable!()
                     };
-                    vartual_stack.push_all(self);
+                    if unreachable_depth.take().is_none() {
+                        vartual_stack.push_all(self);
+                        code! {self;
+                            0_i32.jmp()
+                        };
+                        address_reserved.push(self.p_current);
+                    }
                     *vartual_stack = else_vartual_stack.take().unwrap();
-                    code! {self;
-                        0_i32.jmp()
-                    };
-                    address_reserved.push(self.p_current);
-                    let if_start = address_reserved[0];
-                    address_reserved.remove(0);
-                    let relative_offset = self.p_current as usize - if_start as usize;
-                    Compiler::write_i32(if_start.sub(4), relative_offset as i32);
-                    *stack_count = *start_offset;
+                    for address in else_reserved.drain(..) {
+                        let relative_offset = self.p_current as usize - address as usize;
+                        Compiler::write_i32(address.sub(4), relative_offset as i32);
+                    }
+                    let (params_len, _) = Compiler::block_arity(store, block_type)?;
+                    *stack_count = *start_offset + params_len;
                 }
                 Operator::End => {
                     let label = labels.pop().unwrap();
                     match label {
                         Label::End {
-                            address_reserved,
+                            mut address_reserved,
                             start_offset,
                             block_type,
+                            else_reserved,
                             ..
                         } => {
-                            vartual_stack.push_all(self);
+                            // an `if` without `else` skips to the end
+                            address_reserved.extend(else_reserved);
+                            if unreachable_depth.is_none() {
+                                vartual_stack.push_all(self);
+                            } else if address_reserved.is_empty() {
+                                // nothing branches here, so the code after the block is dead too
+                                continue;
+                            } else {
+                                unreachable_depth = None;
+                                *vartual_stack = VartualStack::new();
+                            }
                             for address in address_reserved {
                                 let relative_offset = self.p_current as usize - address as usize;
                                 Compiler::write_i32(address.sub(4), relative_offset as i32);
                             }
-                            let result_len = match block_type {
-                                BlockType::FuncType(n) => {
-                                    let func_type = store.get_func_type(n)?;
-                                    func_type.results().len()
-                                }
-                                BlockType::Type(_) => 1,
-                                BlockType::Empty => 0,
-                            };
-                            for _ in 0..result_len.min(7) {
+                            let (_, result_len) = Compiler::block_arity(store, &block_type)?;
+                            // the results are kept in registers, of which 7 are available
+                            if result_len > 7 {
+                                bail!(CompileError::TooManyResults(format!(
+                                    "block with {} results in {}",
+                                    result_len,
+                                    store.func_symbol(func_index)
+                                )));
+                            }
+                            for _ in 0..result_len {
                                 let reg = vartual_stack.get_unused_reg(self);
                                 code! {self;
                                     Compiler::pop_data(reg)
                                 };
                                 vartual_stack.stack.push_front(StackValue::Reg(reg));
                             }
-                            if result_len > 7 {
-                                unimplemented!();
-                            }
                             code! {self;
                                 R11.mov(Rbp.with_offset(-16)),
                                 R11.add(8 * start_offset as i32)
@@ -518,38 +2291,182 @@ impl Compiler {
                             *stack_count = start_offset + result_len;
                         }
                         Label::FuncEnd(address_reserved) => {
+                            if unreachable_depth.is_none() {
+                                vartual_stack.push_all(self);
+                                let func_type = store.get_func_type_from_func_index(func_index)?;
+                                self.move_results(*stack_count, 0, func_type.results().len());
+                            }
                             for address in address_reserved {
                                 let relative_offset = self.p_current as usize - address as usize;
                                 Compiler::write_i32(address.sub(4), relative_offset as i32);
                             }
                         }
-                        Label::LoopStart {
-                            start,
-                            start_offset,
-                            block_type,
-                        } => unimplemented!(),
+                        // a loop's end is only reached by falling through, so there is nothing to patch
+                        Label::LoopStart { .. } => {}
                     }
                 }
-                _ => unimplemented!("unimplemented instruction: {:?}", instr),
+                Operator::Br { relative_depth } => {
+                    vartual_stack.push_all(self);
+                    self.branch(store, func_index, labels, *relative_depth, *stack_count)?;
+                    unreachable_depth = Some(0);
+                }
+                Operator::BrIf { relative_depth } => {
+                    if vartual_stack.has_cmp.is_none() {
+                        if let Some(StackValue::Imm(n)) = vartual_stack.stack.back() {
+                            let taken = *n != 0;
+                            vartual_stack.stack.pop_back();
+                            *stack_count -= 1;
+                            if taken {
+                                vartual_stack.push_all(self);
+                                self.branch(
+                                    store,
+                                    func_index,
+                                    labels,
+                                    *relative_depth,
+                                    *stack_count,
+                                )?;
+                                unreachable_depth = Some(0);
+                            }
+                            continue;
+                        }
+                    }
+                    let cmp = vartual_stack.pop_cmp(self);
+                    *stack_count -= 1;
+                    vartual_stack.push_all(self);
+                    let index = labels.len() - 1 - *relative_depth as usize;
+                    let (start_offset, arity) =
+                        Compiler::label_arity(store, func_index, &labels[index])?;
+                    if start_offset + arity == *stack_count {
+                        // the branch values are already in place, so jump to the label directly
+                        for address in self.jump_if(cmp, true) {
+                            match &mut labels[index] {
+                                Label::LoopStart { start, .. } => {
+                                    let relative_offset = *start as isize - address as isize;
+                                    Compiler::write_i32(address.sub(4), relative_offset as i32);
+                                }
+                                Label::FuncEnd(address_reserved)
+                                | Label::End {
+                                    address_reserved, ..
+                                } => {
+                                    address_reserved.push(address);
+                                }
+                            }
+                        }
+                    } else {
+                        let not_taken = self.jump_if(cmp, false);
+                        self.branch(store, func_index, labels, *relative_depth, *stack_count)?;
+                        for address in not_taken {
+                            let relative_offset = self.p_current as usize - address as usize;
+                            Compiler::write_i32(address.sub(4), relative_offset as i32);
+                        }
+                    }
+                }
+                Operator::BrTable { targets } => {
+                    let value = vartual_stack.pop_value(self);
+                    *stack_count -= 1;
+                    vartual_stack.push_all(self);
+                    match value {
+                        StackValue::Imm(n) => {
+                            let relative_depth = targets
+                                .targets()
+                                .nth(n as u32 as usize)
+                                .transpose()?
+                                .unwrap_or(targets.default());
+                            self.branch(store, func_index, labels, relative_depth, *stack_count)?;
+                        }
+                        StackValue::Reg(reg) => {
+                            let reg32: Register32 = reg.into();
+                            for (i, relative_depth) in targets.targets().enumerate() {
+                                code! {self;
+                                    reg32.cmp(i as i32),
+                                    0_i32.jne()
+                                };
+                                let next = self.p_current;
+                                self.branch(
+                                    store,
+                                    func_index,
+                                    labels,
+                                    relative_depth?,
+                                    *stack_count,
+                                )?;
+                                let relative_offset = self.p_current as usize - next as usize;
+                                Compiler::write_i32(next.sub(4), relative_offset as i32);
+                            }
+                            self.branch(
+                                store,
+                                func_index,
+                                labels,
+                                targets.default(),
+                                *stack_count,
+                            )?;
+                            vartual_stack.unused_regs.push_back(reg);
+                        }
+                        StackValue::FReg(_) => unreachable!("float operand"),
+                    }
+                    unreachable_depth = Some(0);
+                }
+                Operator::Return => {
+                    vartual_stack.push_all(self);
+                    let relative_depth = labels.len() as u32 - 1;
+                    self.branch(store, func_index, labels, relative_depth, *stack_count)?;
+                    unreachable_depth = Some(0);
+                }
+                _ => bail!(CompileError::UnsupportedOperator(format!(
+                    "{:?} in {}",
+                    instr,
+                    store.func_symbol(func_index)
+                ))),
             }
         }
         Ok(())
     }
 
-    pub(crate) unsafe fn extract_func(&mut self, index: u32) -> JITFunc {
+    pub(crate) unsafe fn extract_func(&mut self, index: u32, store: &Store<'_>) -> JITFunc {
         let func_pointer = self.p_func_start as *const ();
         self.func_cache.insert(index, func_pointer);
-        let func = std::mem::transmute::<*const (), JITFunc>(func_pointer);
+        let size = self.p_func_end as usize - self.p_func_start as usize;
         self.p_func_start = self.p_current;
-        func
+        let code = std::slice::from_raw_parts(func_pointer as *const u8, size);
+        let symbol = store.func_symbol(index);
+        #[cfg(feature = "gdb-jit")]
+        if let Some(registrations) = &mut self.gdb_registrations {
+            registrations.push(gdb_jit::Registration::new(&symbol, code));
+        }
+        perf::register(self.perf, &symbol, code);
+        std::mem::transmute::<*const (), JITFunc>(func_pointer)
     }
 
     pub(crate) unsafe fn compile_func(&mut self, func_index: u32, store: &Store<'_>) -> Result<()> {
         let func = store.get_code(func_index)?;
         let func_type = store.get_func_type_from_func_index(func_index)?;
+        let locals = func.locals.iter().map(|(_, ty)| ty);
+        for ty in func_type
+            .params()
+            .iter()
+            .chain(func_type.results())
+            .chain(locals)
+        {
+            Self::check_value_type(*ty, || store.func_symbol(func_index))?;
+        }
+        let locals_len: usize = func.locals.iter().map(|(count, _)| *count as usize).sum();
+        let frame_size =
+            8 * (Self::LOCAL_BASE_COUNT as usize + 2 + func_type.params().len() + locals_len);
+        // functions are packed at 16-byte boundaries, usually into the last sealed page
+        self.p_current = self.p_current.add(self.p_current.align_offset(FUNC_ALIGN));
+        self.p_func_start = self.p_current;
+        self.code.commit(self.p_current.add(MIN_FUNC_SPACE))?;
+        self.code.unseal(self.p_func_start)?;
+        self.listing_ops.clear();
+        self.record_op(|| "prologue".to_string());
         code! {self;
             Rbp.push(),
             Rbp.mov(Rsp),
+            // rdi holds the runtime until it is saved below
+            Rax.lea(Rsp.with_offset(-(frame_size as i32))),
+            Rax.cmp(Rdi.with_offset(offset_of!(Runtime, native_stack_limit) as i32))
+        };
+        self.trap_unless(0_i32.jae(), Trap::StackOverflow);
+        code! {self;
             Rdi.push(),
             // R11 is used as a data stack pointer
             R11.mov(Rsi)
@@ -565,46 +2482,81 @@ impl Compiler {
             };
         }
 
-        // 16byte align
-        if func_type.params().len() % 2 == 0 {
+        // declared locals are zero-initialized and live right after the params. A few are
+        // pushed one by one, more in a loop so the prologue does not grow with their number.
+        if locals_len != 0 {
             code! {self;
-                Rsp.add(-8)
+                Rax.mov(0)
+            };
+        }
+        if locals_len <= Self::UNROLLED_LOCALS {
+            for _ in 0..locals_len {
+                code! {self;
+                    Rax.push()
+                };
+            }
+        } else {
+            code! {self;
+                Rcx.mov(locals_len as i64)
             };
+            let loop_start = self.p_current;
+            code! {self;
+                Rax.push(),
+                Rcx.add(-1),
+                0_i32.jne()
+            };
+            let relative_offset = loop_start as isize - self.p_current as isize;
+            Compiler::write_i32(self.p_current.sub(4), relative_offset as i32);
         }
 
-        if !func.locals.is_empty() {
-            unimplemented!("local variables are not supported yet");
+        // 16byte align
+        if (func_type.params().len() + locals_len).is_multiple_of(2) {
+            code! {self;
+                Rsp.add(-8)
+            };
         }
         let mut stack_count = 0;
         let mut labels = vec![Label::FuncEnd(Vec::new())];
         let mut vartual_stack = VartualStack::new();
-        self.compile(
+        let result = self.compile(
             func,
             func_index,
             store,
             &mut stack_count,
             &mut vartual_stack,
             &mut labels,
-        )?;
-        vartual_stack.push_all(self);
-        let result_len = func_type.results().len();
-        if result_len != 0 && result_len != stack_count {
-            code! {self;
-                R11.add(-8 * stack_count as i32)
-            };
-            for _ in 0..result_len {
-                code! {self;
-                    Rax.mov(R11.with_offset(8 * (stack_count - result_len) as i32)),
-                    Self::push_data(Rax)
-                };
-            }
-        }
+        );
+        self.record_op(|| "epilogue".to_string());
         code! {self;
             Rax.mov(0),
             Rsp.mov(Rbp),
             Rbp.pop(),
             ret()
         }
+        // a function that failed to compile is discarded so the space can be reused, and the
+        // functions before it in the same page become executable again
+        if let Some(err) = self.code_error.take() {
+            self.p_current = self.p_func_start;
+            self.code.seal(self.p_current)?;
+            return Err(err);
+        }
+        if result.is_err() {
+            self.p_current = self.p_func_start;
+            self.code.seal(self.p_current)?;
+            return result;
+        }
+        if let Some(listings) = self.listings.as_mut() {
+            let size = self.p_current as usize - self.p_func_start as usize;
+            listings.push(FuncListing {
+                func_index,
+                name: store.func_name(func_index),
+                address: self.p_func_start as usize,
+                code: std::slice::from_raw_parts(self.p_func_start, size).to_vec(),
+                ops: std::mem::take(&mut self.listing_ops),
+            });
+        }
+        self.p_func_end = self.p_current;
+        self.code.seal(self.p_current)?;
         Ok(())
     }
 }
@@ -0,0 +1,162 @@
+use std::{ffi::c_void, ops::Range, ptr};
+
+use anyhow::{bail, Result};
+use libc::{
+    MAP_ANONYMOUS, MAP_FAILED, MAP_NORESERVE, MAP_PRIVATE, PROT_EXEC, PROT_NONE, PROT_READ,
+    PROT_WRITE,
+};
+
+use super::error::CompileError;
+
+/// Address space reserved for the code of one compiler. It is also the largest code size limit.
+pub const RESERVED_SIZE: usize = 1 << 30;
+/// Granularity in which the reservation is made accessible.
+const CHUNK_SIZE: usize = 64 * 1024;
+const PAGE_SIZE: usize = 4096;
+
+/// Executable memory backed by a `PROT_NONE` reservation that is committed in chunks as code
+/// is emitted. The chunks are contiguous, so a function never straddles two mappings and all
+/// code stays in one range that the signal handler can check.
+///
+/// Pages are never writable and executable at the same time: code is emitted into read-write
+/// pages, which `seal` turns read-only and executable once the code is complete. Functions are
+/// packed, so the last sealed page usually has room left. `unseal` makes it writable again for
+/// the next function. That is safe because no JIT code runs while a function is compiled.
+#[derive(Debug)]
+pub(crate) struct CodeMemory {
+    start: *mut u8,
+    /// Bytes from `start` that are accessible.
+    committed: usize,
+    /// Bytes from `start` that are executable, always whole pages.
+    sealed: usize,
+    /// Bytes from `start` that may be committed.
+    limit: usize,
+}
+
+impl CodeMemory {
+    pub(crate) fn new() -> Result<CodeMemory> {
+        let start = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                RESERVED_SIZE,
+                PROT_NONE,
+                MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        if start == MAP_FAILED {
+            bail!(CompileError::CodeAreaExhausted(format!(
+                "failed to reserve code memory: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        Ok(CodeMemory {
+            start: start as *mut u8,
+            committed: 0,
+            sealed: 0,
+            limit: RESERVED_SIZE,
+        })
+    }
+
+    pub(crate) fn start(&self) -> *mut u8 {
+        self.start
+    }
+
+    /// Limits the total code size to `limit` bytes, capped at `RESERVED_SIZE`.
+    pub(crate) fn set_limit(&mut self, limit: usize) {
+        self.limit = limit.min(RESERVED_SIZE);
+    }
+
+    /// Makes everything below `end` writable. Fails if that exceeds the limit.
+    pub(crate) fn commit(&mut self, end: *const u8) -> Result<()> {
+        let size = end as usize - self.start as usize;
+        if size <= self.committed {
+            return Ok(());
+        }
+        if size > self.limit {
+            bail!(CompileError::CodeAreaExhausted(format!(
+                "{} bytes needed, limit is {}",
+                size, self.limit
+            )));
+        }
+        let new_committed = size.next_multiple_of(CHUNK_SIZE).min(RESERVED_SIZE);
+        let r = unsafe {
+            libc::mprotect(
+                self.start.add(self.committed) as *mut c_void,
+                new_committed - self.committed,
+                PROT_READ | PROT_WRITE,
+            )
+        };
+        if r != 0 {
+            bail!(CompileError::CodeAreaExhausted(format!(
+                "failed to commit code memory: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        self.committed = new_committed;
+        Ok(())
+    }
+
+    /// Makes the sealed page that contains `address` writable again, so that code can be
+    /// emitted from there on. The code before `address` in that page is not executable until
+    /// the next `seal`.
+    pub(crate) fn unseal(&mut self, address: *mut u8) -> Result<()> {
+        let page = (address as usize - self.start as usize) / PAGE_SIZE * PAGE_SIZE;
+        if page >= self.sealed {
+            return Ok(());
+        }
+        let r = unsafe {
+            libc::mprotect(
+                self.start.add(page) as *mut c_void,
+                self.sealed - page,
+                PROT_READ | PROT_WRITE,
+            )
+        };
+        if r != 0 {
+            bail!(CompileError::CodeAreaExhausted(format!(
+                "failed to make code writable: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        self.sealed = page;
+        Ok(())
+    }
+
+    /// Makes the pages written since the last `seal`, up to `end`, executable and no longer
+    /// writable.
+    pub(crate) fn seal(&mut self, end: *mut u8) -> Result<()> {
+        let sealed = (end as usize - self.start as usize).next_multiple_of(PAGE_SIZE);
+        if sealed <= self.sealed {
+            return Ok(());
+        }
+        let r = unsafe {
+            libc::mprotect(
+                self.start.add(self.sealed) as *mut c_void,
+                sealed - self.sealed,
+                PROT_READ | PROT_EXEC,
+            )
+        };
+        if r != 0 {
+            bail!(CompileError::CodeAreaExhausted(format!(
+                "failed to make code executable: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        self.sealed = sealed;
+        Ok(())
+    }
+
+    /// The whole reservation, including the inaccessible pages.
+    pub(crate) fn reserved_range(&self) -> Range<usize> {
+        self.start as usize..self.start as usize + RESERVED_SIZE
+    }
+}
+
+impl Drop for CodeMemory {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.start as *mut c_void, RESERVED_SIZE);
+        }
+    }
+}
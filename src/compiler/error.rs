@@ -0,0 +1,14 @@
+use thiserror::Error;
+
+/// Errors raised while compiling a function. The function is discarded and calls to it fail.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    #[error("Unsupported operator: {0}")]
+    UnsupportedOperator(String),
+    #[error("Unsupported value type: {0}")]
+    UnsupportedValueType(String),
+    #[error("Too many results: {0}")]
+    TooManyResults(String),
+    #[error("Code area exhausted: {0}")]
+    CodeAreaExhausted(String),
+}
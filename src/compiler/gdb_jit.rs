@@ -0,0 +1,230 @@
+//! The GDB JIT interface, which lldb implements as well. Every compiled function is described
+//! by a small in-memory ELF object with a symbol for its code, which the debugger reads when it
+//! stops in `__jit_debug_register_code`. Breakpoints can then be set on the function name from
+//! the name section, or `wasm_func_<index>` for unnamed functions.
+//! Backtraces unwind through JIT frames by their `push rbp; mov rbp, rsp` prologue.
+//!
+//! Only built with the `gdb-jit` feature, since the two symbols the debugger looks for may only
+//! be defined once per process, and only used by runtimes that call `enable_gdb_jit`.
+//! `tests/gdb_jit.rs` checks the descriptor list and the ELF objects by parsing them itself.
+//! Loading them into gdb or lldb is not covered by the tests.
+
+use std::{ptr, sync::Mutex};
+
+const JIT_NOACTION: u32 = 0;
+const JIT_REGISTER_FN: u32 = 1;
+const JIT_UNREGISTER_FN: u32 = 2;
+
+#[repr(C)]
+pub struct JitCodeEntry {
+    next_entry: *mut JitCodeEntry,
+    prev_entry: *mut JitCodeEntry,
+    symfile_addr: *const u8,
+    symfile_size: u64,
+}
+
+#[repr(C)]
+pub struct JitDescriptor {
+    version: u32,
+    action_flag: u32,
+    relevant_entry: *mut JitCodeEntry,
+    first_entry: *mut JitCodeEntry,
+}
+
+/// Read by the debugger, which finds it by name.
+#[no_mangle]
+pub static mut __jit_debug_descriptor: JitDescriptor = JitDescriptor {
+    version: 1,
+    action_flag: JIT_NOACTION,
+    relevant_entry: ptr::null_mut(),
+    first_entry: ptr::null_mut(),
+};
+
+/// The debugger sets a breakpoint here to learn about changes to the list of entries.
+#[no_mangle]
+#[inline(never)]
+pub extern "C" fn __jit_debug_register_code() {
+    // keeps the call from being optimized away
+    unsafe { ptr::read_volatile(&0_u8) };
+}
+
+/// Serializes changes to `__jit_debug_descriptor`, which all runtimes share.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// A function announced to the debugger until it is dropped.
+pub(crate) struct Registration {
+    entry: *mut JitCodeEntry,
+    _object: Vec<u8>,
+}
+
+impl Registration {
+    pub(crate) fn new(name: &str, code: &[u8]) -> Registration {
+        let object = elf_object(name, code.as_ptr() as u64, code.len() as u64);
+        let entry = Box::into_raw(Box::new(JitCodeEntry {
+            next_entry: ptr::null_mut(),
+            prev_entry: ptr::null_mut(),
+            symfile_addr: object.as_ptr(),
+            symfile_size: object.len() as u64,
+        }));
+        let _lock = LOCK.lock().unwrap();
+        unsafe {
+            let descriptor = ptr::addr_of_mut!(__jit_debug_descriptor);
+            (*entry).next_entry = (*descriptor).first_entry;
+            if let Some(next) = (*entry).next_entry.as_mut() {
+                next.prev_entry = entry;
+            }
+            (*descriptor).first_entry = entry;
+            (*descriptor).relevant_entry = entry;
+            (*descriptor).action_flag = JIT_REGISTER_FN;
+            __jit_debug_register_code();
+        }
+        Registration {
+            entry,
+            _object: object,
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let _lock = LOCK.lock().unwrap();
+        unsafe {
+            let descriptor = ptr::addr_of_mut!(__jit_debug_descriptor);
+            let entry = &mut *self.entry;
+            if let Some(prev) = entry.prev_entry.as_mut() {
+                prev.next_entry = entry.next_entry;
+            } else {
+                (*descriptor).first_entry = entry.next_entry;
+            }
+            if let Some(next) = entry.next_entry.as_mut() {
+                next.prev_entry = entry.prev_entry;
+            }
+            (*descriptor).relevant_entry = self.entry;
+            (*descriptor).action_flag = JIT_UNREGISTER_FN;
+            __jit_debug_register_code();
+            drop(Box::from_raw(self.entry));
+        }
+    }
+}
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_NOBITS: u32 = 8;
+const SHF_ALLOC: u64 = 2;
+const SHF_EXECINSTR: u64 = 4;
+const STB_GLOBAL_STT_FUNC: u8 = 0x12;
+const ELF_HEADER_SIZE: usize = 64;
+const SECTION_HEADER_SIZE: usize = 64;
+const SYMBOL_SIZE: usize = 24;
+const SHSTRTAB: &[u8] = b"\0.text\0.symtab\0.strtab\0.shstrtab\0";
+
+#[derive(Default)]
+struct SectionHeader {
+    /// Offset into `SHSTRTAB`.
+    name: u32,
+    ty: u32,
+    flags: u64,
+    addr: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    entsize: u64,
+}
+
+impl SectionHeader {
+    fn write(&self, elf: &mut Vec<u8>) {
+        elf.extend_from_slice(&self.name.to_le_bytes());
+        elf.extend_from_slice(&self.ty.to_le_bytes());
+        elf.extend_from_slice(&self.flags.to_le_bytes());
+        elf.extend_from_slice(&self.addr.to_le_bytes());
+        elf.extend_from_slice(&self.offset.to_le_bytes());
+        elf.extend_from_slice(&self.size.to_le_bytes());
+        elf.extend_from_slice(&self.link.to_le_bytes());
+        elf.extend_from_slice(&self.info.to_le_bytes());
+        elf.extend_from_slice(&1_u64.to_le_bytes());
+        elf.extend_from_slice(&self.entsize.to_le_bytes());
+    }
+}
+
+/// Builds a relocatable x86-64 ELF object with a `.text` section at `address` that holds no
+/// data and a function symbol `name` covering it, the way debuggers expect JIT code.
+fn elf_object(name: &str, address: u64, size: u64) -> Vec<u8> {
+    let symtab_offset = ELF_HEADER_SIZE;
+    let strtab_offset = symtab_offset + 2 * SYMBOL_SIZE;
+    let strtab_size = name.len() + 2;
+    let shstrtab_offset = strtab_offset + strtab_size;
+    let section_headers_offset = (shstrtab_offset + SHSTRTAB.len()).next_multiple_of(8);
+
+    let mut elf = Vec::with_capacity(section_headers_offset + 5 * SECTION_HEADER_SIZE);
+    elf.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    elf.extend_from_slice(&[0; 8]);
+    elf.extend_from_slice(&1_u16.to_le_bytes()); // ET_REL
+    elf.extend_from_slice(&62_u16.to_le_bytes()); // EM_X86_64
+    elf.extend_from_slice(&1_u32.to_le_bytes());
+    elf.extend_from_slice(&0_u64.to_le_bytes());
+    elf.extend_from_slice(&0_u64.to_le_bytes());
+    elf.extend_from_slice(&(section_headers_offset as u64).to_le_bytes());
+    elf.extend_from_slice(&0_u32.to_le_bytes());
+    elf.extend_from_slice(&(ELF_HEADER_SIZE as u16).to_le_bytes());
+    elf.extend_from_slice(&0_u16.to_le_bytes());
+    elf.extend_from_slice(&0_u16.to_le_bytes());
+    elf.extend_from_slice(&(SECTION_HEADER_SIZE as u16).to_le_bytes());
+    elf.extend_from_slice(&5_u16.to_le_bytes());
+    elf.extend_from_slice(&4_u16.to_le_bytes());
+
+    // the null symbol, then the function at the start of .text
+    elf.extend_from_slice(&[0; SYMBOL_SIZE]);
+    elf.extend_from_slice(&1_u32.to_le_bytes());
+    elf.push(STB_GLOBAL_STT_FUNC);
+    elf.push(0);
+    elf.extend_from_slice(&1_u16.to_le_bytes());
+    elf.extend_from_slice(&0_u64.to_le_bytes());
+    elf.extend_from_slice(&size.to_le_bytes());
+
+    elf.push(0);
+    elf.extend_from_slice(name.as_bytes());
+    elf.push(0);
+    elf.extend_from_slice(SHSTRTAB);
+    elf.resize(section_headers_offset, 0);
+
+    let sections = [
+        SectionHeader::default(),
+        SectionHeader {
+            name: 1,
+            ty: SHT_NOBITS,
+            flags: SHF_ALLOC | SHF_EXECINSTR,
+            addr: address,
+            size,
+            ..Default::default()
+        },
+        SectionHeader {
+            name: 7,
+            ty: SHT_SYMTAB,
+            offset: symtab_offset as u64,
+            size: 2 * SYMBOL_SIZE as u64,
+            link: 3,
+            info: 1,
+            entsize: SYMBOL_SIZE as u64,
+            ..Default::default()
+        },
+        SectionHeader {
+            name: 15,
+            ty: SHT_STRTAB,
+            offset: strtab_offset as u64,
+            size: strtab_size as u64,
+            ..Default::default()
+        },
+        SectionHeader {
+            name: 23,
+            ty: SHT_STRTAB,
+            offset: shstrtab_offset as u64,
+            size: SHSTRTAB.len() as u64,
+            ..Default::default()
+        },
+    ];
+    for section in sections {
+        section.write(&mut elf);
+    }
+    elf
+}
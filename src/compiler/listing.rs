@@ -0,0 +1,48 @@
+use std::fmt;
+
+use crate::disassembler::disassemble;
+
+/// The machine code of a compiled function, annotated with the operators it was emitted for.
+#[derive(Debug, Clone)]
+pub struct FuncListing {
+    pub func_index: u32,
+    /// The name of the function in the name section.
+    pub name: Option<String>,
+    /// Address of the first instruction, the `p_func_start` the function was compiled at.
+    pub address: usize,
+    /// The code from `p_func_start` to `p_current` after the function was completed.
+    pub code: Vec<u8>,
+    /// Where the code for each operator starts, as an offset into `code`, with the operator.
+    /// The prologue and epilogue are marked as `prologue` and `epilogue`.
+    pub ops: Vec<(usize, String)>,
+}
+
+impl fmt::Display for FuncListing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "func {}", self.func_index)?;
+        if let Some(name) = &self.name {
+            write!(f, " ${}", name)?;
+        }
+        writeln!(f, " at {:#x} ({} bytes):", self.address, self.code.len())?;
+        let mut ops = self.ops.iter().peekable();
+        for instruction in disassemble(&self.code) {
+            // operators that emitted nothing share the offset of the next one
+            while let Some((_, op)) = ops.next_if(|(offset, _)| *offset <= instruction.offset) {
+                writeln!(f, "  ; {}", op)?;
+            }
+            let bytes = &self.code[instruction.offset..instruction.offset + instruction.len];
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            writeln!(
+                f,
+                "  {:04x}: {:<30} {}",
+                instruction.offset,
+                hex.join(" "),
+                instruction.text
+            )?;
+        }
+        for (_, op) in ops {
+            writeln!(f, "  ; {}", op)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+//! Announces compiled functions to Linux `perf`, so that samples in JIT code are attributed to
+//! wasm functions instead of anonymous addresses.
+//!
+//! A perf map is a text file `/tmp/perf-<pid>.map` that `perf report` reads directly. A jitdump
+//! file `jit-<pid>.dump` also contains the code, so `perf annotate` can show it. It has to be
+//! merged into a recording with `perf inject --jit` after `perf record -k mono`.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    os::fd::AsRawFd,
+    path::Path,
+    ptr,
+    sync::Mutex,
+};
+
+use anyhow::{bail, Context, Result};
+
+/// The files are per process, so they are shared by all runtimes.
+static PERF_MAP: Mutex<Option<File>> = Mutex::new(None);
+static JITDUMP: Mutex<Option<JitDump>> = Mutex::new(None);
+
+const JITDUMP_MAGIC: u32 = 0x4a695444;
+const JITDUMP_VERSION: u32 = 1;
+const EM_X86_64: u32 = 62;
+const JIT_CODE_LOAD: u32 = 0;
+
+/// Creates `/tmp/perf-<pid>.map` unless it is already open.
+pub(crate) fn open_perf_map() -> Result<()> {
+    let mut perf_map = PERF_MAP.lock().unwrap();
+    if perf_map.is_none() {
+        let path = format!("/tmp/perf-{}.map", std::process::id());
+        let file = File::create(&path).with_context(|| format!("failed to create {}", path))?;
+        *perf_map = Some(file);
+    }
+    Ok(())
+}
+
+/// Creates `jit-<pid>.dump` in `dir` unless a jitdump file is already open.
+pub(crate) fn open_jitdump(dir: &Path) -> Result<()> {
+    let mut jitdump = JITDUMP.lock().unwrap();
+    if jitdump.is_none() {
+        let path = dir.join(format!("jit-{}.dump", std::process::id()));
+        let dump = JitDump::create(&path)
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        *jitdump = Some(dump);
+    }
+    Ok(())
+}
+
+/// The process-wide files a compiler writes its functions to. Each runtime only writes to the
+/// ones it enabled, even if another runtime opened the others.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Sinks {
+    pub(crate) perf_map: bool,
+    pub(crate) jitdump: bool,
+}
+
+/// Adds the function at `code` to the files in `sinks`. Profiling is best-effort, so a write
+/// error does not fail compilation. The file is closed instead, as a partly written record
+/// would make the rest of it unreadable.
+pub(crate) fn register(sinks: Sinks, name: &str, code: &[u8]) {
+    if sinks.perf_map {
+        let mut perf_map = PERF_MAP.lock().unwrap();
+        if let Some(file) = perf_map.as_mut() {
+            let line = format!("{:x} {:x} {}\n", code.as_ptr() as usize, code.len(), name);
+            if file.write_all(line.as_bytes()).is_err() {
+                *perf_map = None;
+            }
+        }
+    }
+    if sinks.jitdump {
+        let mut jitdump = JITDUMP.lock().unwrap();
+        if let Some(dump) = jitdump.as_mut() {
+            if dump.code_load(name, code).is_err() {
+                *jitdump = None;
+            }
+        }
+    }
+}
+
+struct JitDump {
+    file: File,
+    next_code_index: u64,
+}
+
+impl JitDump {
+    fn create(path: &Path) -> Result<JitDump> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let mut header = Vec::with_capacity(40);
+        header.extend_from_slice(&JITDUMP_MAGIC.to_ne_bytes());
+        header.extend_from_slice(&JITDUMP_VERSION.to_ne_bytes());
+        header.extend_from_slice(&40_u32.to_ne_bytes());
+        header.extend_from_slice(&EM_X86_64.to_ne_bytes());
+        header.extend_from_slice(&0_u32.to_ne_bytes());
+        header.extend_from_slice(&std::process::id().to_ne_bytes());
+        header.extend_from_slice(&timestamp().to_ne_bytes());
+        header.extend_from_slice(&0_u64.to_ne_bytes());
+        file.write_all(&header)?;
+        // perf finds the file through this executable mapping, which is never unmapped
+        let map = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                header.len(),
+                libc::PROT_READ | libc::PROT_EXEC,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if map == libc::MAP_FAILED {
+            bail!(std::io::Error::last_os_error());
+        }
+        Ok(JitDump {
+            file,
+            next_code_index: 0,
+        })
+    }
+
+    fn code_load(&mut self, name: &str, code: &[u8]) -> Result<()> {
+        let address = code.as_ptr() as u64;
+        let total_size = 16 + 40 + name.len() + 1 + code.len();
+        let mut record = Vec::with_capacity(total_size);
+        record.extend_from_slice(&JIT_CODE_LOAD.to_ne_bytes());
+        record.extend_from_slice(&(total_size as u32).to_ne_bytes());
+        record.extend_from_slice(&timestamp().to_ne_bytes());
+        record.extend_from_slice(&std::process::id().to_ne_bytes());
+        record.extend_from_slice(&unsafe { libc::gettid() }.to_ne_bytes());
+        record.extend_from_slice(&address.to_ne_bytes());
+        record.extend_from_slice(&address.to_ne_bytes());
+        record.extend_from_slice(&(code.len() as u64).to_ne_bytes());
+        record.extend_from_slice(&self.next_code_index.to_ne_bytes());
+        record.extend_from_slice(name.as_bytes());
+        record.push(0);
+        record.extend_from_slice(code);
+        self.file.write_all(&record)?;
+        self.next_code_index += 1;
+        Ok(())
+    }
+}
+
+/// The `CLOCK_MONOTONIC` time in nanoseconds, the clock that `perf record -k mono` uses.
+fn timestamp() -> u64 {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) };
+    time.tv_sec as u64 * 1_000_000_000 + time.tv_nsec as u64
+}
@@ -0,0 +1,527 @@
+//! A decoder for the x86-64 instructions that `assembler` emits, used for listings of
+//! compiled code. Other encodings are shown as `db` bytes.
+
+/// A decoded instruction. `offset` is relative to the start of the decoded code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub offset: usize,
+    pub len: usize,
+    /// Intel syntax, e.g. `mov rax, qword ptr [r11+0x8]`.
+    pub text: String,
+}
+
+/// Decodes `code` from its start. Jump targets are printed as offsets into `code`.
+pub fn disassemble(code: &[u8]) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < code.len() {
+        let (len, text) = Decoder::new(code, offset)
+            .decode()
+            .unwrap_or_else(|| (1, format!("db {:#04x}", code[offset])));
+        instructions.push(Instruction { offset, len, text });
+        offset += len;
+    }
+    instructions
+}
+
+const REG64: [&str; 16] = [
+    "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15",
+];
+const REG32: [&str; 16] = [
+    "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d", "r11d", "r12d",
+    "r13d", "r14d", "r15d",
+];
+const REG16: [&str; 16] = [
+    "ax", "cx", "dx", "bx", "sp", "bp", "si", "di", "r8w", "r9w", "r10w", "r11w", "r12w", "r13w",
+    "r14w", "r15w",
+];
+/// Byte registers with a REX prefix. Without one, 4 to 7 are ah, ch, dh and bh.
+const REG8: [&str; 16] = [
+    "al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil", "r8b", "r9b", "r10b", "r11b", "r12b",
+    "r13b", "r14b", "r15b",
+];
+const REG8_LEGACY: [&str; 8] = ["al", "cl", "dl", "bl", "ah", "ch", "dh", "bh"];
+/// Condition code suffixes, indexed like `assembler::Condition`.
+const CONDITIONS: [&str; 16] = [
+    "o", "no", "b", "ae", "e", "ne", "be", "a", "s", "ns", "p", "np", "l", "ge", "le", "g",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Size {
+    Byte,
+    Word,
+    Dword,
+    Qword,
+}
+
+impl Size {
+    fn ptr(self) -> &'static str {
+        match self {
+            Size::Byte => "byte ptr ",
+            Size::Word => "word ptr ",
+            Size::Dword => "dword ptr ",
+            Size::Qword => "qword ptr ",
+        }
+    }
+}
+
+/// The r/m operand of a ModRM byte.
+enum Rm {
+    Reg(u8),
+    Mem(String),
+}
+
+struct Decoder<'a> {
+    code: &'a [u8],
+    start: usize,
+    pos: usize,
+    /// 0x66, 0xf2 or 0xf3
+    prefix: Option<u8>,
+    rex: Option<u8>,
+}
+
+impl Decoder<'_> {
+    fn new(code: &[u8], start: usize) -> Decoder<'_> {
+        Decoder {
+            code,
+            start,
+            pos: start,
+            prefix: None,
+            rex: None,
+        }
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        let byte = *self.code.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn imm8(&mut self) -> Option<i8> {
+        self.byte().map(|b| b as i8)
+    }
+
+    fn imm32(&mut self) -> Option<i32> {
+        let bytes = self.code.get(self.pos..self.pos + 4)?;
+        self.pos += 4;
+        Some(i32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn imm64(&mut self) -> Option<i64> {
+        let bytes = self.code.get(self.pos..self.pos + 8)?;
+        self.pos += 8;
+        Some(i64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn rex_bit(&self, bit: u8) -> u8 {
+        self.rex.map_or(0, |rex| (rex >> bit) & 1)
+    }
+
+    fn w(&self) -> bool {
+        self.rex_bit(3) == 1
+    }
+
+    fn operand_size(&self) -> Size {
+        if self.w() {
+            Size::Qword
+        } else if self.prefix == Some(0x66) {
+            Size::Word
+        } else {
+            Size::Dword
+        }
+    }
+
+    fn gpr(&self, number: u8, size: Size) -> &'static str {
+        match size {
+            Size::Qword => REG64[number as usize],
+            Size::Dword => REG32[number as usize],
+            Size::Word => REG16[number as usize],
+            Size::Byte if self.rex.is_none() => REG8_LEGACY[number as usize & 7],
+            Size::Byte => REG8[number as usize],
+        }
+    }
+
+    /// Decodes a ModRM byte with its SIB byte and displacement. Returns the reg field and the
+    /// r/m operand, both extended by REX.
+    fn mod_rm(&mut self) -> Option<(u8, Rm)> {
+        let mod_rm = self.byte()?;
+        let mod_ = mod_rm >> 6;
+        let reg = (mod_rm >> 3) & 7 | self.rex_bit(2) << 3;
+        let rm = mod_rm & 7;
+        if mod_ == 3 {
+            return Some((reg, Rm::Reg(rm | self.rex_bit(0) << 3)));
+        }
+        let mut address = String::new();
+        if rm == 4 {
+            let sib = self.byte()?;
+            let index = (sib >> 3) & 7 | self.rex_bit(1) << 3;
+            let base = sib & 7;
+            if base == 5 && mod_ == 0 {
+                let disp = self.imm32()?;
+                address.push_str(&signed_hex(disp as i64));
+            } else {
+                address.push_str(REG64[(base | self.rex_bit(0) << 3) as usize]);
+            }
+            if index != 4 {
+                address.push_str(&format!("+{}*{}", REG64[index as usize], 1 << (sib >> 6)));
+            }
+        } else if rm == 5 && mod_ == 0 {
+            let disp = self.imm32()?;
+            return Some((reg, Rm::Mem(format!("[rip{}]", displacement(disp as i64)))));
+        } else {
+            address.push_str(REG64[(rm | self.rex_bit(0) << 3) as usize]);
+        }
+        let disp = match mod_ {
+            1 => self.imm8()? as i64,
+            2 => self.imm32()? as i64,
+            _ => 0,
+        };
+        address.push_str(&displacement(disp));
+        Some((reg, Rm::Mem(format!("[{}]", address))))
+    }
+
+    fn rm_text(&self, rm: &Rm, size: Size) -> String {
+        match rm {
+            Rm::Reg(number) => self.gpr(*number, size).to_string(),
+            Rm::Mem(address) => format!("{}{}", size.ptr(), address),
+        }
+    }
+
+    fn xmm_rm_text(rm: &Rm, size: Size) -> String {
+        match rm {
+            Rm::Reg(number) => format!("xmm{}", number),
+            Rm::Mem(address) => format!("{}{}", size.ptr(), address),
+        }
+    }
+
+    /// Returns the jump target of a rel32 operand as an offset into the code.
+    fn rel32(&mut self) -> Option<String> {
+        let rel = self.imm32()?;
+        Some(format!("{:#x}", self.pos as i64 + rel as i64))
+    }
+
+    fn decode(mut self) -> Option<(usize, String)> {
+        if let Some(prefix @ (0x66 | 0xf2 | 0xf3)) = self.code.get(self.pos) {
+            self.prefix = Some(*prefix);
+            self.pos += 1;
+        }
+        if let Some(rex @ 0x40..=0x4f) = self.code.get(self.pos) {
+            self.rex = Some(*rex);
+            self.pos += 1;
+        }
+        let text = match self.byte()? {
+            0x0f => self.decode_0f()?,
+            opcode @ (0x01 | 0x09 | 0x21 | 0x29 | 0x31 | 0x39 | 0x89) => {
+                let size = self.operand_size();
+                let (reg, rm) = self.mod_rm()?;
+                format!(
+                    "{} {}, {}",
+                    alu_name(opcode >> 3).unwrap_or("mov"),
+                    self.rm_text(&rm, size),
+                    self.gpr(reg, size)
+                )
+            }
+            0x88 => {
+                let (reg, rm) = self.mod_rm()?;
+                format!(
+                    "mov {}, {}",
+                    self.rm_text(&rm, Size::Byte),
+                    self.gpr(reg, Size::Byte)
+                )
+            }
+            opcode @ (0x3b | 0x8b) => {
+                let size = self.operand_size();
+                let (reg, rm) = self.mod_rm()?;
+                let name = if opcode == 0x3b { "cmp" } else { "mov" };
+                format!(
+                    "{} {}, {}",
+                    name,
+                    self.gpr(reg, size),
+                    self.rm_text(&rm, size)
+                )
+            }
+            0x8d => {
+                let (reg, rm) = self.mod_rm()?;
+                let Rm::Mem(address) = rm else { return None };
+                format!("lea {}, {}", self.gpr(reg, self.operand_size()), address)
+            }
+            0x63 if self.w() => {
+                let (reg, rm) = self.mod_rm()?;
+                format!(
+                    "movsxd {}, {}",
+                    self.gpr(reg, Size::Qword),
+                    self.rm_text(&rm, Size::Dword)
+                )
+            }
+            opcode @ 0x50..=0x5f => {
+                let number = (opcode & 7) | self.rex_bit(0) << 3;
+                let name = if opcode < 0x58 { "push" } else { "pop" };
+                format!("{} {}", name, REG64[number as usize])
+            }
+            0x68 => format!("push {}", signed_hex(self.imm32()? as i64)),
+            0x6a => format!("push {}", signed_hex(self.imm8()? as i64)),
+            0x81 => {
+                let size = self.operand_size();
+                let (ext, rm) = self.mod_rm()?;
+                let imm = self.imm32()?;
+                format!(
+                    "{} {}, {}",
+                    alu_name(ext & 7)?,
+                    self.rm_text(&rm, size),
+                    signed_hex(imm as i64)
+                )
+            }
+            0x99 if self.w() => "cqo".to_string(),
+            0x99 => "cdq".to_string(),
+            opcode @ 0xb8..=0xbf => {
+                let number = (opcode & 7) | self.rex_bit(0) << 3;
+                if self.w() {
+                    // mostly addresses and float bit patterns, so shown unsigned
+                    let imm = self.imm64()?;
+                    format!("mov {}, {:#x}", REG64[number as usize], imm as u64)
+                } else {
+                    let imm = self.imm32()?;
+                    format!("mov {}, {}", REG32[number as usize], signed_hex(imm as i64))
+                }
+            }
+            opcode @ (0xc1 | 0xd3) => {
+                let size = self.operand_size();
+                let (ext, rm) = self.mod_rm()?;
+                let name =
+                    ["rol", "ror", "rcl", "rcr", "shl", "shr", "sal", "sar"][ext as usize & 7];
+                let count = if opcode == 0xc1 {
+                    format!("{}", self.byte()?)
+                } else {
+                    "cl".to_string()
+                };
+                format!("{} {}, {}", name, self.rm_text(&rm, size), count)
+            }
+            0xc3 => "ret".to_string(),
+            0xe9 => format!("jmp {}", self.rel32()?),
+            0xf7 => {
+                let size = self.operand_size();
+                let (ext, rm) = self.mod_rm()?;
+                let name = match ext & 7 {
+                    6 => "div",
+                    7 => "idiv",
+                    _ => return None,
+                };
+                format!("{} {}", name, self.rm_text(&rm, size))
+            }
+            0xff => {
+                let (ext, rm) = self.mod_rm()?;
+                match (ext & 7, &rm) {
+                    (2, Rm::Reg(_)) => format!("call {}", self.rm_text(&rm, Size::Qword)),
+                    (4, Rm::Reg(_)) => format!("jmp {}", self.rm_text(&rm, Size::Qword)),
+                    (6, _) => format!("push {}", self.rm_text(&rm, Size::Qword)),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        Some((self.pos - self.start, text))
+    }
+
+    /// Decodes the two and three-byte opcodes that start with 0x0f.
+    fn decode_0f(&mut self) -> Option<String> {
+        let opcode = self.byte()?;
+        let text = match (self.prefix, opcode) {
+            (_, 0x80..=0x8f) => format!("j{} {}", CONDITIONS[opcode as usize & 0xf], self.rel32()?),
+            (_, 0x90..=0x9f) => {
+                let (_, rm) = self.mod_rm()?;
+                format!(
+                    "set{} {}",
+                    CONDITIONS[opcode as usize & 0xf],
+                    self.rm_text(&rm, Size::Byte)
+                )
+            }
+            (None, 0xaf) => {
+                let size = self.operand_size();
+                let (reg, rm) = self.mod_rm()?;
+                format!("imul {}, {}", self.gpr(reg, size), self.rm_text(&rm, size))
+            }
+            (None, 0xb6 | 0xb7 | 0xbe | 0xbf) => {
+                let size = self.operand_size();
+                let (reg, rm) = self.mod_rm()?;
+                let name = if opcode < 0xbe { "movzx" } else { "movsx" };
+                let src_size = if opcode & 1 == 0 {
+                    Size::Byte
+                } else {
+                    Size::Word
+                };
+                format!(
+                    "{} {}, {}",
+                    name,
+                    self.gpr(reg, size),
+                    self.rm_text(&rm, src_size)
+                )
+            }
+            (None, 0xbc | 0xbd) | (Some(0xf3), 0xb8) => {
+                let size = self.operand_size();
+                let (reg, rm) = self.mod_rm()?;
+                let name = match opcode {
+                    0xbc => "bsf",
+                    0xbd => "bsr",
+                    _ => "popcnt",
+                };
+                format!(
+                    "{} {}, {}",
+                    name,
+                    self.gpr(reg, size),
+                    self.rm_text(&rm, size)
+                )
+            }
+            (Some(prefix @ (0xf2 | 0xf3)), 0x51 | 0x58..=0x5f) => {
+                let name = match opcode {
+                    0x51 => "sqrt",
+                    0x58 => "add",
+                    0x59 => "mul",
+                    0x5a => "cvt",
+                    0x5c => "sub",
+                    0x5d => "min",
+                    0x5e => "div",
+                    0x5f => "max",
+                    _ => return None,
+                };
+                let suffix = match (prefix, opcode) {
+                    (0xf3, 0x5a) => "ss2sd",
+                    (_, 0x5a) => "sd2ss",
+                    (0xf3, _) => "ss",
+                    _ => "sd",
+                };
+                let (reg, rm) = self.mod_rm()?;
+                format!(
+                    "{}{} xmm{}, {}",
+                    name,
+                    suffix,
+                    reg,
+                    Self::xmm_rm_text(&rm, Size::Qword)
+                )
+            }
+            (None | Some(0x66), 0x2e) => {
+                let suffix = if self.prefix.is_none() { "ss" } else { "sd" };
+                let (reg, rm) = self.mod_rm()?;
+                format!(
+                    "ucomi{} xmm{}, {}",
+                    suffix,
+                    reg,
+                    Self::xmm_rm_text(&rm, Size::Qword)
+                )
+            }
+            (None, 0x54 | 0x56) => {
+                let name = if opcode == 0x54 { "andps" } else { "orps" };
+                let (reg, rm) = self.mod_rm()?;
+                format!(
+                    "{} xmm{}, {}",
+                    name,
+                    reg,
+                    Self::xmm_rm_text(&rm, Size::Qword)
+                )
+            }
+            (Some(0x66), 0x6e) => {
+                let size = self.operand_size_ignoring_prefix();
+                let (reg, rm) = self.mod_rm()?;
+                let name = if self.w() { "movq" } else { "movd" };
+                format!("{} xmm{}, {}", name, reg, self.rm_text(&rm, size))
+            }
+            (Some(0x66), 0x7e) => {
+                let size = self.operand_size_ignoring_prefix();
+                let (reg, rm) = self.mod_rm()?;
+                let name = if self.w() { "movq" } else { "movd" };
+                format!("{} {}, xmm{}", name, self.rm_text(&rm, size), reg)
+            }
+            (Some(0x66), 0xd6) => {
+                let (reg, rm) = self.mod_rm()?;
+                format!("movq {}, xmm{}", Self::xmm_rm_text(&rm, Size::Qword), reg)
+            }
+            (Some(prefix @ (0xf2 | 0xf3)), 0xc2) => {
+                let suffix = if prefix == 0xf3 { "ss" } else { "sd" };
+                let (reg, rm) = self.mod_rm()?;
+                let predicate = self.byte()?;
+                format!(
+                    "cmp{} xmm{}, {}, {}",
+                    suffix,
+                    reg,
+                    Self::xmm_rm_text(&rm, Size::Qword),
+                    predicate
+                )
+            }
+            (Some(prefix @ (0xf2 | 0xf3)), 0x2a) => {
+                let suffix = if prefix == 0xf3 { "ss" } else { "sd" };
+                let size = self.operand_size_ignoring_prefix();
+                let (reg, rm) = self.mod_rm()?;
+                format!("cvtsi2{} xmm{}, {}", suffix, reg, self.rm_text(&rm, size))
+            }
+            (Some(prefix @ (0xf2 | 0xf3)), 0x2c) => {
+                let suffix = if prefix == 0xf3 { "ss" } else { "sd" };
+                let size = self.operand_size_ignoring_prefix();
+                let (reg, rm) = self.mod_rm()?;
+                format!(
+                    "cvtt{}2si {}, {}",
+                    suffix,
+                    self.gpr(reg, size),
+                    Self::xmm_rm_text(&rm, Size::Qword)
+                )
+            }
+            (Some(0x66), 0x3a) => {
+                let name = match self.byte()? {
+                    0x0a => "roundss",
+                    0x0b => "roundsd",
+                    _ => return None,
+                };
+                let (reg, rm) = self.mod_rm()?;
+                let mode = self.byte()?;
+                format!(
+                    "{} xmm{}, {}, {}",
+                    name,
+                    reg,
+                    Self::xmm_rm_text(&rm, Size::Qword),
+                    mode
+                )
+            }
+            _ => return None,
+        };
+        Some(text)
+    }
+
+    /// The size of a general purpose operand of an SSE instruction, whose 0x66 prefix is
+    /// mandatory rather than an operand size override.
+    fn operand_size_ignoring_prefix(&self) -> Size {
+        if self.w() {
+            Size::Qword
+        } else {
+            Size::Dword
+        }
+    }
+}
+
+/// Names the ALU operation of a group 1 extension or of `opcode >> 3` for the r/m, reg forms.
+fn alu_name(ext: u8) -> Option<&'static str> {
+    match ext {
+        0 => Some("add"),
+        1 => Some("or"),
+        4 => Some("and"),
+        5 => Some("sub"),
+        6 => Some("xor"),
+        7 => Some("cmp"),
+        _ => None,
+    }
+}
+
+/// Formats a displacement to append to a base register, which is empty if it is zero.
+fn displacement(disp: i64) -> String {
+    match disp {
+        0 => String::new(),
+        1.. => format!("+{:#x}", disp),
+        _ => signed_hex(disp),
+    }
+}
+
+fn signed_hex(value: i64) -> String {
+    if value < 0 {
+        format!("-{:#x}", value.unsigned_abs())
+    } else {
+        format!("{:#x}", value)
+    }
+}
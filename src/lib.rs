@@ -1,5 +1,7 @@
 pub mod assembler;
 pub mod compiler;
+pub mod disassembler;
 pub mod parser;
 pub mod runtime;
+pub mod wasi;
 pub mod wasm;
@@ -1,19 +1,175 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use wasm_jit::{
     parser,
-    runtime::{Runtime, Value},
+    runtime::{linker::Linker, Runtime, Value},
+    wasi::{self, Wasi},
 };
+use wasmparser::ValType;
+
+const USAGE: &str = "usage: wasm_jit run [options] <module> [--invoke <function>] [args...]
+
+Runs the `_start` function of a WASI command, passing it args, or with
+`--invoke` calls an exported function with args parsed as its params and
+prints its results. <module> is a .wasm or .wat file.
+
+options:
+  --listing                  print the machine code of every compiled function
+                             to stderr afterwards, interleaved with the wasm
+                             operators
+  --profile=perfmap|jitdump  announce compiled functions to `perf`, in
+                             /tmp/perf-<pid>.map or in ./jit-<pid>.dump
+  --gdb-jit                  register compiled functions with gdb and lldb,
+                             if built with the `gdb-jit` feature";
+
+#[derive(Default)]
+struct Options {
+    listing: bool,
+    profile: Option<Profile>,
+    gdb_jit: bool,
+}
+
+enum Profile {
+    PerfMap,
+    JitDump,
+}
 
 fn main() -> Result<()> {
-    let bytes = include_bytes!("../tests/wasm/fib.wasm");
+    let mut args = std::env::args().skip(1).peekable();
+    // `run` may be left out for compatibility with `wasm_jit <module> [args...]`
+    if args.peek().map(String::as_str) == Some("run") {
+        args.next();
+    }
+    let mut options = Options::default();
+    while let Some(option) =
+        args.next_if(|arg| arg == "--listing" || arg == "--gdb-jit" || arg.starts_with("--profile"))
+    {
+        match option.as_str() {
+            "--listing" => options.listing = true,
+            "--gdb-jit" => options.gdb_jit = true,
+            "--profile=perfmap" => options.profile = Some(Profile::PerfMap),
+            "--profile=jitdump" => options.profile = Some(Profile::JitDump),
+            _ => bail!("unknown option {}\n\n{}", option, USAGE),
+        }
+    }
+    let Some(path) = args.next() else {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    };
+    if path == "--help" || path == "-h" {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let mut args: Vec<String> = args.collect();
+    if args.first().map(String::as_str) == Some("--invoke") {
+        if args.len() < 2 {
+            bail!("--invoke needs a function name\n\n{}", USAGE);
+        }
+        let name = args[1].clone();
+        args.drain(..2);
+        return invoke(&path, &name, &args, &options);
+    }
+    args.insert(0, path.clone());
+    let code = run_wasi(&path, &args, &options)?;
+    std::process::exit(code);
+}
+
+/// Instantiates a module with WASI available to it, with `args` as its arguments.
+fn instantiate<'a>(bytes: &'a [u8], args: &[String], options: &Options) -> Result<Runtime<'a>> {
     let modules = parser::parse(bytes)?;
-    let mut runtime = Runtime::init(modules);
-    let result = runtime.call_func_by_name("fib", &[Value::I64(10)])?;
-    println!("{:?}", result);
-    let start = std::time::Instant::now();
-    let result = runtime.call_func_by_name("fib", &[Value::I64(30)])?;
-    println!("Elapsed: {:?}", start.elapsed());
-    println!("{:?}", result);
+    let mut linker = Linker::new();
+    Wasi::new()
+        .args(args)
+        .env(std::env::vars())
+        .add_to_linker(&mut linker);
+    let mut runtime = Runtime::instantiate(modules, &linker)?;
+    if options.listing {
+        runtime.enable_listing();
+    }
+    match options.profile {
+        Some(Profile::PerfMap) => runtime.enable_perf_map()?,
+        Some(Profile::JitDump) => runtime.enable_jitdump(".")?,
+        None => {}
+    }
+    if options.gdb_jit {
+        #[cfg(feature = "gdb-jit")]
+        runtime.enable_gdb_jit();
+        #[cfg(not(feature = "gdb-jit"))]
+        bail!("--gdb-jit needs wasm_jit built with the `gdb-jit` feature");
+    }
+    Ok(runtime)
+}
+
+fn print_listings(runtime: &Runtime<'_>) {
+    for listing in runtime.listings() {
+        eprintln!("{}", listing);
+    }
+}
 
+/// Runs the `_start` function of a WASI command, given as `.wasm` or `.wat`, and returns its
+/// exit code.
+fn run_wasi(path: &str, args: &[String], options: &Options) -> Result<i32> {
+    let bytes = parser::read_module(path)?;
+    let mut runtime = instantiate(&bytes, args, options)?;
+    let result = runtime.call_func_by_name("_start", &[]);
+    print_listings(&runtime);
+    match result {
+        Ok(_) => Ok(0),
+        Err(err) => match err.downcast_ref::<wasi::Exit>() {
+            Some(wasi::Exit(code)) => Ok(*code),
+            None => Err(err),
+        },
+    }
+}
+
+/// Calls the exported function `name` with `args` and prints each result on its own line.
+fn invoke(path: &str, name: &str, args: &[String], options: &Options) -> Result<()> {
+    let bytes = parser::read_module(path)?;
+    let mut runtime = instantiate(&bytes, &[path.to_string()], options)?;
+    let func_type = runtime.get_func_type(name)?;
+    if func_type.params().len() != args.len() {
+        bail!(
+            "{} takes {} arguments, got {}",
+            name,
+            func_type.params().len(),
+            args.len()
+        );
+    }
+    let args = func_type
+        .params()
+        .iter()
+        .zip(args)
+        .map(|(ty, arg)| parse_value(ty, arg))
+        .collect::<Result<Vec<_>>>()?;
+    let results = runtime.call_func_by_name(name, &args);
+    print_listings(&runtime);
+    for result in results? {
+        match result {
+            Value::I32(v) => println!("{}", v),
+            Value::I64(v) => println!("{}", v),
+            Value::F32(v) => println!("{}", v),
+            Value::F64(v) => println!("{}", v),
+        }
+    }
     Ok(())
 }
+
+/// Parses an argument as a value of type `ty`. Integers may also be given in their unsigned
+/// range, e.g. `4294967295` for an i32 of -1.
+fn parse_value(ty: &ValType, arg: &str) -> Result<Value> {
+    let value = match ty {
+        ValType::I32 => arg
+            .parse::<i32>()
+            .or_else(|_| arg.parse::<u32>().map(|v| v as i32))
+            .map(Value::I32)
+            .ok(),
+        ValType::I64 => arg
+            .parse::<i64>()
+            .or_else(|_| arg.parse::<u64>().map(|v| v as i64))
+            .map(Value::I64)
+            .ok(),
+        ValType::F32 => arg.parse().map(Value::F32).ok(),
+        ValType::F64 => arg.parse().map(Value::F64).ok(),
+        _ => bail!("unsupported parameter type {:?}", ty),
+    };
+    value.with_context(|| format!("invalid {:?} argument: {}", ty, arg))
+}
@@ -1,25 +1,76 @@
+use std::{borrow::Cow, path::Path};
+
 use anyhow::Result;
-use wasmparser::{Parser, Payload::*};
+use thiserror::Error;
+use wasmparser::{
+    BinaryReaderError, FuncValidatorAllocations, KnownCustom, Name, NameSectionReader, Parser,
+    Payload::*, ValidPayload, Validator,
+};
+
+use crate::wasm::{Func, Names, WasmModule};
+
+/// A module that is malformed or fails validation. `offset` is the byte offset in the binary.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("invalid module at offset {offset:#x}: {message}")]
+    Module { offset: usize, message: String },
+    #[error("invalid function {func_index} at offset {offset:#x}: {message}")]
+    Func {
+        func_index: u32,
+        offset: usize,
+        message: String,
+    },
+}
+
+impl ParseError {
+    fn module(err: BinaryReaderError) -> ParseError {
+        ParseError::Module {
+            offset: err.offset(),
+            message: err.message().to_string(),
+        }
+    }
 
-use crate::wasm::{Func, WasmModule};
+    fn func(func_index: u32, err: BinaryReaderError) -> ParseError {
+        ParseError::Func {
+            func_index,
+            offset: err.offset(),
+            message: err.message().to_string(),
+        }
+    }
+}
 
+/// Parses and validates a binary module. The compiler relies on the module being valid.
 pub fn parse(buf: &[u8]) -> Result<WasmModule<'_>> {
     let parser = Parser::new(0);
+    let mut validator = Validator::new();
     let mut module = WasmModule::default();
 
     for payload in parser.parse_all(buf) {
-        match payload? {
+        let payload = payload.map_err(ParseError::module)?;
+        let valid = validator.payload(&payload).map_err(ParseError::module)?;
+        match payload {
             TypeSection(types) => {
                 for ty in types.into_iter_err_on_gc_types() {
                     module.types.push(ty?);
                 }
             }
+            ImportSection(imports) => {
+                for import in imports {
+                    module.imports.push(import?);
+                }
+            }
             FunctionSection(funcs) => {
                 for func in funcs {
                     module.funcs.push(func?);
                 }
             }
             CodeSectionEntry(body) => {
+                if let ValidPayload::Func(func, _) = valid {
+                    let func_index = func.index;
+                    func.into_validator(FuncValidatorAllocations::default())
+                        .validate(&body)
+                        .map_err(|err| ParseError::func(func_index, err))?;
+                }
                 let local_reader = body.get_locals_reader()?;
                 let mut locals = Vec::new();
                 for local in local_reader {
@@ -40,8 +91,77 @@ pub fn parse(buf: &[u8]) -> Result<WasmModule<'_>> {
                     module.exports.push(export?);
                 }
             }
+            TableSection(tables) => {
+                for table in tables {
+                    module.tables.push(table?);
+                }
+            }
+            MemorySection(memories) => {
+                for memory in memories {
+                    module.memories.push(memory?);
+                }
+            }
+            GlobalSection(globals) => {
+                for global in globals {
+                    module.globals.push(global?);
+                }
+            }
+            ElementSection(elements) => {
+                for element in elements {
+                    module.elements.push(element?);
+                }
+            }
+            DataSection(data) => {
+                for data in data {
+                    module.data.push(data?);
+                }
+            }
+            CustomSection(section) => {
+                if let KnownCustom::Name(reader) = section.as_known() {
+                    // a malformed name section is ignored like other custom sections,
+                    // keeping the names read up to the error
+                    let _ = parse_names(reader, &mut module.names);
+                }
+            }
             _ => {}
         }
     }
     Ok(module)
 }
+
+fn parse_names<'a>(reader: NameSectionReader<'a>, names: &mut Names<'a>) -> Result<()> {
+    for subsection in reader {
+        match subsection? {
+            Name::Module { name, .. } => names.module = Some(name),
+            Name::Function(map) => {
+                for naming in map {
+                    let naming = naming?;
+                    names.funcs.insert(naming.index, naming.name);
+                }
+            }
+            Name::Local(map) => {
+                for func in map {
+                    let func = func?;
+                    let locals = names.locals.entry(func.index).or_default();
+                    for naming in func.names {
+                        let naming = naming?;
+                        locals.insert(naming.index, naming.name);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Translates a module in the WebAssembly text format to the binary format that `parse` takes.
+/// Binary input is returned as is. Errors point at the line and column of the offending token.
+pub fn wat_to_wasm(source: &[u8]) -> Result<Cow<'_, [u8]>> {
+    Ok(wat::parse_bytes(source)?)
+}
+
+/// Reads a module in either the text or the binary format from `path`.
+pub fn read_module(path: impl AsRef<Path>) -> Result<Vec<u8>> {
+    Ok(wat::parse_file(path)?)
+}
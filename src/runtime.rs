@@ -1,26 +1,46 @@
 pub mod error;
+pub mod global;
+pub mod linker;
+pub mod memory;
+mod signal;
 pub mod store;
+pub mod table;
+pub mod typed_func;
 
 use std::{
     alloc::Layout,
-    collections::HashMap,
     ffi::{c_int, c_void},
+    panic::AssertUnwindSafe,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
 };
 
 use crate::{
-    compiler::{Compiler, JITFunc},
+    compiler::{error::CompileError, listing::FuncListing, Compiler, JITFunc},
     wasm::WasmModule,
 };
 use anyhow::{bail, Error, Result};
-use error::RuntimeError;
+use error::{HostPanic, RuntimeError, Trap, WasmBacktrace};
+use global::Global;
 use libc::size_t;
+use linker::{Caller, HostFunc, Linker};
+use signal::{ActiveRegions, TrapRegions};
 use store::Store;
-use wasmparser::{Export, ExternalKind, ValType};
+use typed_func::{TypedFunc, WasmParams, WasmResults};
+use wasmparser::{Export, ExternalKind, FuncType, ValType};
 
 pub struct Runtime<'a> {
+    /// Identifies the runtime that a `TypedFunc` belongs to.
+    id: u64,
     store: Store<'a>,
     compiler: Compiler,
     stack_base: *mut u64,
+    trap_regions: TrapRegions,
+    /// Lowest address the native stack may grow to, checked in every function prologue.
+    pub(crate) native_stack_limit: usize,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -32,7 +52,7 @@ pub enum Value {
 }
 
 impl Value {
-    fn to_u64(&self) -> u64 {
+    pub(crate) fn to_u64(&self) -> u64 {
         match self {
             Value::I32(v) => *v as u64,
             Value::I64(v) => *v as u64,
@@ -41,17 +61,31 @@ impl Value {
         }
     }
 
-    fn from_u64(bytes: u64, value_type: &ValType) -> Value {
-        match value_type {
+    pub fn ty(&self) -> ValType {
+        match self {
+            Value::I32(_) => ValType::I32,
+            Value::I64(_) => ValType::I64,
+            Value::F32(_) => ValType::F32,
+            Value::F64(_) => ValType::F64,
+        }
+    }
+
+    pub(crate) fn from_u64(bytes: u64, value_type: &ValType) -> Result<Value> {
+        Ok(match value_type {
             ValType::I32 => Value::I32(bytes as i32),
             ValType::I64 => Value::I64(bytes as i64),
             ValType::F32 => Value::F32(f32::from_bits(bytes as u32)),
             ValType::F64 => Value::F64(f64::from_bits(bytes)),
-            _ => unimplemented!("Unsupported value type: {:?}", value_type),
-        }
+            _ => bail!(CompileError::UnsupportedValueType(format!(
+                "{:?}",
+                value_type
+            ))),
+        })
     }
 }
 
+static NEXT_RUNTIME_ID: AtomicU64 = AtomicU64::new(0);
+
 const PAGE_SIZE: usize = 4096;
 const STACK_SIZE: usize = PAGE_SIZE * 4;
 
@@ -60,29 +94,61 @@ extern "C" {
 }
 
 impl<'a> Runtime<'a> {
-    pub fn init(modules: WasmModule<'a>) -> Runtime<'a> {
-        let store = Store::new(modules);
-        let stack_layout = Layout::from_size_align(STACK_SIZE + PAGE_SIZE, PAGE_SIZE).unwrap();
-        let sp = unsafe { std::alloc::alloc(stack_layout) as *mut u64 };
-        unsafe {
-            mprotect(
-                sp.add(STACK_SIZE) as *const c_void,
-                PAGE_SIZE,
-                libc::PROT_NONE,
-            );
+    pub fn init(modules: WasmModule<'a>) -> Result<Runtime<'a>> {
+        Self::instantiate(modules, &Linker::new())
+    }
+
+    /// Instantiates a module, resolving its imports against `linker`.
+    pub fn instantiate(modules: WasmModule<'a>, linker: &Linker) -> Result<Runtime<'a>> {
+        let store = Store::new(modules, linker)?;
+        let compiler = unsafe { Compiler::new()? };
+        let sp = unsafe { std::alloc::alloc(Self::stack_layout()) as *mut u64 };
+        if sp.is_null() {
+            std::alloc::handle_alloc_error(Self::stack_layout());
+        }
+        let stack_guard = unsafe { (sp as *mut u8).add(STACK_SIZE) };
+        let r = unsafe { mprotect(stack_guard as *const c_void, PAGE_SIZE, libc::PROT_NONE) };
+        assert!(r == 0);
+        let mut guards = vec![(
+            stack_guard as usize..stack_guard as usize + PAGE_SIZE,
+            Trap::StackOverflow,
+        )];
+        for memory in &store.memories {
+            guards.push((memory.reserved_range(), Trap::MemoryOutOfBounds));
         }
-        Runtime {
+        let trap_regions = TrapRegions {
+            trap_stub: compiler.trap_stub as usize,
+            code: compiler.code_range(),
+            guards,
+        };
+        signal::install_handler();
+        Ok(Runtime {
+            id: NEXT_RUNTIME_ID.fetch_add(1, Ordering::Relaxed),
             store,
-            compiler: unsafe { Compiler::new() },
+            compiler,
             stack_base: sp,
-        }
+            trap_regions,
+            native_stack_limit: 0,
+        })
     }
 
+    /// Calls the exported function `name`. Fails with `RuntimeError::FunctionTypeMismatch` if
+    /// `args` don't match its parameters.
     pub fn call_func_by_name(&mut self, name: &str, args: &[Value]) -> Result<Vec<Value>> {
-        let Export { name, kind, index } = self.store.get_export(name)?;
-        let index = *index;
-        if *kind != ExternalKind::Func {
-            bail!("Export kind is not a function: {}", name);
+        let index = self.get_exported_func(name)?;
+        let func_type = self.store.get_func_type_from_func_index(index)?;
+        // JIT code trusts its arguments, so they are checked before anything is written
+        if !args
+            .iter()
+            .map(Value::ty)
+            .eq(func_type.params().iter().copied())
+        {
+            bail!(RuntimeError::FunctionTypeMismatch(format!(
+                "{} takes {:?}, called with {:?}",
+                name,
+                func_type.params(),
+                args.iter().map(Value::ty).collect::<Vec<_>>()
+            )));
         }
         for (i, arg) in args.iter().enumerate() {
             unsafe {
@@ -90,41 +156,248 @@ impl<'a> Runtime<'a> {
             }
         }
         unsafe {
-            self.call_func_by_index(self.stack_base.add(args.len()), index)?;
+            self.call_func_with_args(index, args.len())?;
             let func_type = self.store.get_func_type_from_func_index(index)?;
             let mut result = Vec::new();
             for i in 0..func_type.results().len() {
                 result.push(Value::from_u64(
                     *self.stack_base.add(i),
                     &func_type.results()[i],
-                ));
+                )?);
             }
 
             Ok(result)
         }
     }
 
+    /// Returns a handle to the exported function `name` that is called with `Params` and
+    /// returns `Results`, such as `get_typed_func::<(i64, i64), i64>("add")`. Fails if the
+    /// signature of the function differs.
+    pub fn get_typed_func<Params: WasmParams, Results: WasmResults>(
+        &self,
+        name: &str,
+    ) -> Result<TypedFunc<Params, Results>> {
+        let index = self.get_exported_func(name)?;
+        let func_type = self.store.get_func_type_from_func_index(index)?;
+        if func_type.params() != Params::TYPES || func_type.results() != Results::TYPES {
+            bail!(RuntimeError::FunctionTypeMismatch(format!(
+                "{} has type {:?} -> {:?}, expected {:?} -> {:?}",
+                name,
+                func_type.params(),
+                func_type.results(),
+                Params::TYPES,
+                Results::TYPES
+            )));
+        }
+        Ok(TypedFunc::new(index, self.id))
+    }
+
+    /// Returns the type of the exported function `name`.
+    pub fn get_func_type(&self, name: &str) -> Result<&FuncType> {
+        let index = self.get_exported_func(name)?;
+        self.store.get_func_type_from_func_index(index)
+    }
+
+    fn get_exported_func(&self, name: &str) -> Result<u32> {
+        let Export { name, kind, index } = self.store.get_export(name)?;
+        if *kind != ExternalKind::Func {
+            bail!("Export kind is not a function: {}", name);
+        }
+        Ok(*index)
+    }
+
+    /// Calls function `index` with the `arg_count` args at the bottom of the data stack. The
+    /// results are written in their place.
+    pub(crate) unsafe fn call_func_with_args(
+        &mut self,
+        index: u32,
+        arg_count: usize,
+    ) -> Result<()> {
+        let _active = ActiveRegions::enter(&self.trap_regions);
+        self.native_stack_limit = signal::native_stack_limit();
+        let result = self.call_func_by_index(self.stack_base.add(arg_count), index);
+        match result.map_err(Error::downcast::<HostPanic>) {
+            Ok(()) => Ok(()),
+            Err(Ok(HostPanic(payload))) => {
+                std::panic::resume_unwind(payload.into_inner().unwrap_or_else(|e| e.into_inner()))
+            }
+            Err(Err(err)) => Err(err),
+        }
+    }
+
+    /// Limits the total size of the machine code compiled for this instance to `limit` bytes,
+    /// at most `MAX_CODE_SIZE`. Calls that need to compile more fail with
+    /// `CompileError::CodeAreaExhausted`.
+    pub fn set_code_limit(&mut self, limit: usize) {
+        self.compiler.set_code_limit(limit);
+    }
+
+    /// Keeps a listing of the machine code of every function compiled from now on. Functions
+    /// are compiled when they are first called.
+    pub fn enable_listing(&mut self) {
+        self.compiler.enable_listing();
+    }
+
+    /// Listings of the functions compiled since `enable_listing`, in the order they were
+    /// compiled. Printing one interleaves the operators with the instructions they produced.
+    pub fn listings(&self) -> &[FuncListing] {
+        self.compiler.listings()
+    }
+
+    /// Adds every function compiled from now on to `/tmp/perf-<pid>.map`, so that `perf report`
+    /// attributes samples in JIT code to wasm functions.
+    pub fn enable_perf_map(&mut self) -> Result<()> {
+        self.compiler.enable_perf_map()
+    }
+
+    /// Writes every function compiled from now on, including its code, to `jit-<pid>.dump` in
+    /// `dir` for `perf inject --jit`. The file is shared by all runtimes of the process and
+    /// stays in the directory it was first created in.
+    pub fn enable_jitdump(&mut self, dir: impl AsRef<Path>) -> Result<()> {
+        self.compiler.enable_jitdump(dir.as_ref())
+    }
+
+    /// Registers every function compiled from now on with gdb and lldb through the GDB JIT
+    /// interface, so they appear in backtraces and can have breakpoints set on them.
+    #[cfg(feature = "gdb-jit")]
+    pub fn enable_gdb_jit(&mut self) {
+        self.compiler.enable_gdb_jit();
+    }
+
+    /// Returns the value of the exported global `name`.
+    pub fn get_global(&self, name: &str) -> Result<Value> {
+        Ok(self.get_exported_global(name)?.get())
+    }
+
+    /// Sets the value of the exported mutable global `name`.
+    pub fn set_global(&mut self, name: &str, value: Value) -> Result<()> {
+        self.get_exported_global(name)?.set(value)
+    }
+
+    /// Returns the exported global `name`. It shares its value with the instance, so it can be
+    /// defined in a `Linker` to let other instances import the same global.
+    pub fn get_exported_global(&self, name: &str) -> Result<&Global> {
+        let Export { name, kind, index } = self.store.get_export(name)?;
+        if *kind != ExternalKind::Global {
+            bail!("Export kind is not a global: {}", name);
+        }
+        self.store.get_global(*index)
+    }
+
+    /// The data stack followed by its guard page.
+    fn stack_layout() -> Layout {
+        Layout::from_size_align(STACK_SIZE + PAGE_SIZE, PAGE_SIZE).unwrap()
+    }
+
     unsafe fn call_func_by_index(&mut self, sp: *mut u64, index: u32) -> Result<()> {
+        if let Some(host_func) = self.store.get_imported_func(index) {
+            let host_func = host_func.clone();
+            return self.call_host_func(sp, &host_func);
+        }
         let code: JITFunc = if let Some(code) = self.compiler.func_cache.get(&index) {
             std::mem::transmute::<*const (), JITFunc>(*code)
         } else {
             self.compiler.compile_func(index, &self.store)?;
-            let code = self.compiler.extract_func(index);
-            code
+            self.compiler.extract_func(index, &self.store)
         };
         let result = code(self, sp);
         if result != 0 {
             let error = std::mem::transmute::<u64, Error>(result);
-            return Err(error);
+            return Err(self.add_backtrace_frame(error, index));
         }
         Ok(())
     }
 
-    pub(crate) unsafe fn call_func_internal(&mut self, sp: *mut u64, index: u32) -> u64 {
+    /// Records that a trap unwound through the function at `index`. Other errors, such as those
+    /// of host functions, are passed on unchanged.
+    fn add_backtrace_frame(&self, mut error: Error, index: u32) -> Error {
+        let frame = match self.store.module_name() {
+            Some(module) => format!("{}!{}", module, self.store.func_symbol(index)),
+            None => self.store.func_symbol(index),
+        };
+        if let Some(backtrace) = error.downcast_mut::<WasmBacktrace>() {
+            backtrace.frames.push(frame);
+            return error;
+        }
+        if matches!(
+            error.downcast_ref::<RuntimeError>(),
+            Some(RuntimeError::Trap(_))
+        ) {
+            return error.context(WasmBacktrace {
+                frames: vec![frame],
+            });
+        }
+        error
+    }
+
+    /// Calls a host function with the arguments below `sp` and writes its results in their place.
+    unsafe fn call_host_func(&mut self, sp: *mut u64, host_func: &HostFunc) -> Result<()> {
+        let params = host_func.ty.params();
+        let base = sp.sub(params.len());
+        let args: Vec<Value> = params
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| Value::from_u64(*base.add(i), ty))
+            .collect::<Result<_>>()?;
+        let mut caller = Caller {
+            memory: self.store.memories.first_mut(),
+        };
+        // a panic must not unwind into the JIT frames below
+        let results =
+            std::panic::catch_unwind(AssertUnwindSafe(|| (host_func.func)(&mut caller, &args)))
+                .map_err(|payload| HostPanic(Mutex::new(payload)))??;
+        let result_types = host_func.ty.results();
+        if results.len() != result_types.len()
+            || results
+                .iter()
+                .zip(result_types)
+                .any(|(v, ty)| v.ty() != *ty)
+        {
+            bail!(RuntimeError::HostFuncResultMismatch(format!(
+                "expected {:?}, got {:?}",
+                result_types, results
+            )));
+        }
+        for (i, result) in results.iter().enumerate() {
+            *base.add(i) = result.to_u64();
+        }
+        Ok(())
+    }
+
+    pub(crate) unsafe extern "C" fn call_func_internal(&mut self, sp: *mut u64, index: u32) -> u64 {
         let result = self.call_func_by_index(sp, index);
         match result {
             Ok(_) => 0,
             Err(err) => std::mem::transmute::<Error, u64>(err),
         }
     }
+
+    pub(crate) extern "C" fn trap_internal(code: u32) -> u64 {
+        let err = Error::from(RuntimeError::Trap(Trap::from_code(code)));
+        unsafe { std::mem::transmute::<Error, u64>(err) }
+    }
+
+    pub(crate) unsafe extern "C" fn memory_size_internal(&mut self, index: u32) -> u32 {
+        self.store.memories[index as usize].size()
+    }
+
+    pub(crate) unsafe extern "C" fn memory_grow_internal(&mut self, index: u32, delta: u32) -> i32 {
+        self.store.memories[index as usize].grow(delta)
+    }
+}
+
+impl Drop for Runtime<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            // the allocator may hand the guard page out again, so it has to be accessible
+            let stack_guard = (self.stack_base as *mut u8).add(STACK_SIZE);
+            let r = mprotect(
+                stack_guard as *const c_void,
+                PAGE_SIZE,
+                libc::PROT_READ | libc::PROT_WRITE,
+            );
+            assert!(r == 0);
+            std::alloc::dealloc(self.stack_base as *mut u8, Self::stack_layout());
+        }
+    }
 }
@@ -1,3 +1,5 @@
+use std::{any::Any, fmt, sync::Mutex};
+
 use thiserror::Error;
 
 #[derive(Error, Debug, Clone)]
@@ -8,4 +10,101 @@ pub enum RuntimeError {
     FunctionNotFound(String),
     #[error("Function type not found: {0}")]
     FunctionTypeNotFound(String),
+    #[error("Function type mismatch: {0}")]
+    FunctionTypeMismatch(String),
+    #[error("Function belongs to a different runtime: {0}")]
+    ForeignFunc(String),
+    #[error("Global not found: {0}")]
+    GlobalNotFound(String),
+    #[error("Global is immutable: {0}")]
+    ImmutableGlobal(String),
+    #[error("Global type mismatch: {0}")]
+    GlobalTypeMismatch(String),
+    #[error("Memory not found: {0}")]
+    MemoryNotFound(String),
+    #[error("Unsupported memory: {0}")]
+    UnsupportedMemory(String),
+    #[error("Unsupported table: {0}")]
+    UnsupportedTable(String),
+    #[error("Table not found: {0}")]
+    TableNotFound(String),
+    #[error("Unsupported constant expression: {0}")]
+    UnsupportedConstExpr(String),
+    #[error("Unknown import: {0}")]
+    UnknownImport(String),
+    #[error("Incompatible import type: {0}")]
+    IncompatibleImportType(String),
+    #[error("Host function returned wrong results: {0}")]
+    HostFuncResultMismatch(String),
+    #[error("Trap: {0}")]
+    Trap(Trap),
+}
+
+/// A panic caught in a host function. JIT frames have no unwind info, so the payload is
+/// returned through them as an error and the panic resumes once they are gone.
+#[derive(Error, Debug)]
+#[error("host function panicked")]
+pub(crate) struct HostPanic(pub(crate) Mutex<Box<dyn Any + Send>>);
+
+/// Errors raised while executing JIT code. The discriminant is the code passed to the trap stub.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Trap {
+    #[error("unreachable")]
+    Unreachable,
+    #[error("out of bounds memory access")]
+    MemoryOutOfBounds,
+    #[error("call stack exhausted")]
+    StackOverflow,
+    #[error("out of bounds table access")]
+    TableOutOfBounds,
+    #[error("undefined element")]
+    UndefinedElement,
+    #[error("uninitialized element")]
+    UninitializedElement,
+    #[error("indirect call type mismatch")]
+    IndirectCallTypeMismatch,
+    #[error("integer divide by zero")]
+    IntegerDivideByZero,
+    #[error("integer overflow")]
+    IntegerOverflow,
+    #[error("invalid conversion to integer")]
+    InvalidConversionToInteger,
+}
+
+impl Trap {
+    pub(crate) fn from_code(code: u32) -> Trap {
+        match code {
+            0 => Trap::Unreachable,
+            1 => Trap::MemoryOutOfBounds,
+            2 => Trap::StackOverflow,
+            3 => Trap::TableOutOfBounds,
+            4 => Trap::UndefinedElement,
+            5 => Trap::UninitializedElement,
+            6 => Trap::IndirectCallTypeMismatch,
+            7 => Trap::IntegerDivideByZero,
+            8 => Trap::IntegerOverflow,
+            9 => Trap::InvalidConversionToInteger,
+            _ => unreachable!("unknown trap code: {}", code),
+        }
+    }
+}
+
+/// The wasm functions a trap unwound through, innermost first. It is attached to the trap as
+/// context, so the trap itself is still found with `downcast_ref::<RuntimeError>()`.
+/// A function that calls itself directly is listed once for the whole recursion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WasmBacktrace {
+    /// The function names, as `module!func` if the module is named.
+    pub frames: Vec<String>,
+}
+
+impl fmt::Display for WasmBacktrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "wasm backtrace:")?;
+        for (i, frame) in self.frames.iter().enumerate() {
+            write!(f, "\n  {}: {}", i, frame)?;
+        }
+        Ok(())
+    }
 }
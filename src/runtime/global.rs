@@ -0,0 +1,57 @@
+use std::{cell::Cell, rc::Rc};
+
+use anyhow::{bail, Result};
+use wasmparser::GlobalType;
+
+use super::{error::RuntimeError, Value};
+
+/// A global variable. The value lives in a shared cell whose address is embedded in JIT code,
+/// so clones of a `Global` observe each other's writes.
+#[derive(Debug, Clone)]
+pub struct Global {
+    ty: GlobalType,
+    value: Rc<Cell<u64>>,
+}
+
+impl Global {
+    pub fn new(ty: GlobalType, value: Value) -> Result<Global> {
+        if value.ty() != ty.content_type {
+            bail!(RuntimeError::GlobalTypeMismatch(format!(
+                "expected {:?}, got {:?}",
+                ty.content_type, value
+            )));
+        }
+        Ok(Global {
+            ty,
+            value: Rc::new(Cell::new(value.to_u64())),
+        })
+    }
+
+    pub fn ty(&self) -> GlobalType {
+        self.ty
+    }
+
+    pub fn get(&self) -> Value {
+        // `new` only accepts values, so the type is always one `Value` can hold
+        Value::from_u64(self.value.get(), &self.ty.content_type).expect("numeric global type")
+    }
+
+    /// Sets the value of a mutable global.
+    pub fn set(&self, value: Value) -> Result<()> {
+        if !self.ty.mutable {
+            bail!(RuntimeError::ImmutableGlobal(format!("{:?}", self.ty)));
+        }
+        if value.ty() != self.ty.content_type {
+            bail!(RuntimeError::GlobalTypeMismatch(format!(
+                "expected {:?}, got {:?}",
+                self.ty.content_type, value
+            )));
+        }
+        self.value.set(value.to_u64());
+        Ok(())
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut u64 {
+        self.value.as_ptr()
+    }
+}
@@ -0,0 +1,80 @@
+use std::{collections::HashMap, fmt, rc::Rc};
+
+use anyhow::Result;
+use wasmparser::FuncType;
+
+use super::{global::Global, memory::Memory, Value};
+
+/// The signature of a host function body.
+pub type HostFuncBody = dyn Fn(&mut Caller<'_>, &[Value]) -> Result<Vec<Value>>;
+
+/// A function implemented by the embedder that a module can import.
+#[derive(Clone)]
+pub struct HostFunc {
+    pub ty: FuncType,
+    pub func: Rc<HostFuncBody>,
+}
+
+impl fmt::Debug for HostFunc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HostFunc").field("ty", &self.ty).finish()
+    }
+}
+
+/// State of the calling instance that host functions can access.
+pub struct Caller<'s> {
+    pub(crate) memory: Option<&'s mut Memory>,
+}
+
+impl Caller<'_> {
+    /// Returns the default memory of the calling instance.
+    pub fn memory(&mut self) -> Option<&mut Memory> {
+        self.memory.as_deref_mut()
+    }
+}
+
+/// Host definitions that imports are resolved against when a module is instantiated.
+#[derive(Debug, Default, Clone)]
+pub struct Linker {
+    funcs: HashMap<(String, String), HostFunc>,
+    globals: HashMap<(String, String), Global>,
+}
+
+impl Linker {
+    pub fn new() -> Linker {
+        Linker::default()
+    }
+
+    /// Defines `module.name` as a host function of type `ty`.
+    pub fn func(
+        &mut self,
+        module: &str,
+        name: &str,
+        ty: FuncType,
+        func: impl Fn(&mut Caller<'_>, &[Value]) -> Result<Vec<Value>> + 'static,
+    ) -> &mut Linker {
+        self.funcs.insert(
+            (module.to_string(), name.to_string()),
+            HostFunc {
+                ty,
+                func: Rc::new(func),
+            },
+        );
+        self
+    }
+
+    /// Defines `module.name` as a global. Importing instances share its value with `global`.
+    pub fn global(&mut self, module: &str, name: &str, global: Global) -> &mut Linker {
+        self.globals
+            .insert((module.to_string(), name.to_string()), global);
+        self
+    }
+
+    pub fn get_func(&self, module: &str, name: &str) -> Option<&HostFunc> {
+        self.funcs.get(&(module.to_string(), name.to_string()))
+    }
+
+    pub fn get_global(&self, module: &str, name: &str) -> Option<&Global> {
+        self.globals.get(&(module.to_string(), name.to_string()))
+    }
+}
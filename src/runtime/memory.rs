@@ -0,0 +1,124 @@
+use std::{ffi::c_void, ops::Range, ptr};
+
+use anyhow::{bail, Result};
+use libc::{
+    MAP_ANONYMOUS, MAP_FAILED, MAP_NORESERVE, MAP_PRIVATE, PROT_NONE, PROT_READ, PROT_WRITE,
+};
+use wasmparser::MemoryType;
+
+use super::error::{RuntimeError, Trap};
+
+pub const WASM_PAGE_SIZE: usize = 0x10000;
+const MAX_PAGES: u64 = 0x10000;
+/// 4GiB for the addressable range plus 4GiB of guard region, so that any 32-bit address
+/// plus a 32-bit static offset lands inside the reservation.
+const RESERVED_SIZE: usize = 8 << 30;
+
+/// A linear memory backed by a `PROT_NONE` reservation. Only the pages below the current
+/// size are accessible, so out of bounds accesses fault instead of being checked explicitly.
+#[derive(Debug)]
+pub struct Memory {
+    base: *mut u8,
+    pages: u32,
+    maximum: u32,
+}
+
+impl Memory {
+    pub fn new(ty: &MemoryType) -> Result<Memory> {
+        if ty.memory64 || ty.shared {
+            bail!(RuntimeError::UnsupportedMemory(format!("{:?}", ty)));
+        }
+        let maximum = ty.maximum.unwrap_or(MAX_PAGES).min(MAX_PAGES) as u32;
+        let base = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                RESERVED_SIZE,
+                PROT_NONE,
+                MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        if base == MAP_FAILED {
+            bail!(RuntimeError::UnsupportedMemory(format!(
+                "failed to reserve linear memory: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        let mut memory = Memory {
+            base: base as *mut u8,
+            pages: 0,
+            maximum,
+        };
+        if memory.grow(ty.initial as u32) == -1 {
+            bail!(RuntimeError::UnsupportedMemory(format!("{:?}", ty)));
+        }
+        Ok(memory)
+    }
+
+    pub fn base(&self) -> *mut u8 {
+        self.base
+    }
+
+    /// Returns the current size in wasm pages.
+    pub fn size(&self) -> u32 {
+        self.pages
+    }
+
+    /// Grows the memory by `delta` pages and returns the previous size, or -1 on failure.
+    pub fn grow(&mut self, delta: u32) -> i32 {
+        let old_pages = self.pages;
+        let Some(new_pages) = old_pages.checked_add(delta) else {
+            return -1;
+        };
+        if new_pages > self.maximum {
+            return -1;
+        }
+        if delta != 0 {
+            let r = unsafe {
+                libc::mprotect(
+                    self.base.add(old_pages as usize * WASM_PAGE_SIZE) as *mut c_void,
+                    delta as usize * WASM_PAGE_SIZE,
+                    PROT_READ | PROT_WRITE,
+                )
+            };
+            if r != 0 {
+                return -1;
+            }
+        }
+        self.pages = new_pages;
+        old_pages as i32
+    }
+
+    pub fn data(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.base, self.pages as usize * WASM_PAGE_SIZE) }
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.base, self.pages as usize * WASM_PAGE_SIZE) }
+    }
+
+    pub fn write(&mut self, offset: usize, bytes: &[u8]) -> Result<()> {
+        let Some(dest) = offset
+            .checked_add(bytes.len())
+            .and_then(|end| self.data_mut().get_mut(offset..end))
+        else {
+            bail!(RuntimeError::Trap(Trap::MemoryOutOfBounds));
+        };
+        dest.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// The whole reservation, including the inaccessible pages.
+    pub(crate) fn reserved_range(&self) -> Range<usize> {
+        self.base as usize..self.base as usize + RESERVED_SIZE
+    }
+}
+
+impl Drop for Memory {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.base as *mut c_void, RESERVED_SIZE);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::{
+    cell::Cell,
+    ffi::{c_int, c_void},
+    mem,
+    ops::Range,
+    ptr,
+    sync::{Once, OnceLock},
+};
+
+use libc::{
+    sigaction, siginfo_t, ucontext_t, SA_ONSTACK, SA_SIGINFO, SIGBUS, SIGSEGV, SIG_DFL, SIG_IGN,
+};
+
+use super::error::Trap;
+
+/// Stack kept free below the native stack limit for the Rust code that JIT code calls into.
+const NATIVE_STACK_MARGIN: usize = 128 * 1024;
+
+/// Address ranges that the signal handler turns into traps while JIT code is running.
+#[derive(Debug, Default)]
+pub(crate) struct TrapRegions {
+    /// Entry of the stub that raises the trap whose code is passed in `edi`.
+    pub(crate) trap_stub: usize,
+    /// Only faults raised by instructions in this range are handled.
+    pub(crate) code: Range<usize>,
+    pub(crate) guards: Vec<(Range<usize>, Trap)>,
+}
+
+impl TrapRegions {
+    fn find(&self, pc: usize, address: usize) -> Option<Trap> {
+        if !self.code.contains(&pc) {
+            return None;
+        }
+        self.guards
+            .iter()
+            .find(|(range, _)| range.contains(&address))
+            .map(|(_, trap)| *trap)
+    }
+}
+
+thread_local! {
+    static ACTIVE_REGIONS: Cell<*const TrapRegions> = const { Cell::new(ptr::null()) };
+    static NATIVE_STACK_LIMIT: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+static PREVIOUS_SIGSEGV: OnceLock<sigaction> = OnceLock::new();
+static PREVIOUS_SIGBUS: OnceLock<sigaction> = OnceLock::new();
+
+/// Marks `regions` as the ones of the code running on this thread until dropped.
+pub(crate) struct ActiveRegions {
+    previous: *const TrapRegions,
+}
+
+impl ActiveRegions {
+    pub(crate) fn enter(regions: &TrapRegions) -> ActiveRegions {
+        let previous = ACTIVE_REGIONS.with(|active| active.replace(regions));
+        ActiveRegions { previous }
+    }
+}
+
+impl Drop for ActiveRegions {
+    fn drop(&mut self) {
+        ACTIVE_REGIONS.with(|active| active.set(self.previous));
+    }
+}
+
+/// Returns the address below which JIT code on this thread reports a stack overflow.
+pub(crate) fn native_stack_limit() -> usize {
+    NATIVE_STACK_LIMIT.with(|limit| {
+        if let Some(limit) = limit.get() {
+            return limit;
+        }
+        let value = unsafe {
+            let mut attr: libc::pthread_attr_t = mem::zeroed();
+            let mut stack_addr = ptr::null_mut();
+            let mut stack_size = 0;
+            if libc::pthread_getattr_np(libc::pthread_self(), &mut attr) != 0 {
+                0
+            } else {
+                let r = libc::pthread_attr_getstack(&attr, &mut stack_addr, &mut stack_size);
+                libc::pthread_attr_destroy(&mut attr);
+                if r != 0 {
+                    0
+                } else {
+                    stack_addr as usize + NATIVE_STACK_MARGIN.min(stack_size)
+                }
+            }
+        };
+        limit.set(Some(value));
+        value
+    })
+}
+
+pub(crate) fn install_handler() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| unsafe {
+        for (signum, previous) in [(SIGSEGV, &PREVIOUS_SIGSEGV), (SIGBUS, &PREVIOUS_SIGBUS)] {
+            let mut action: sigaction = mem::zeroed();
+            action.sa_sigaction = handler as *const () as usize;
+            action.sa_flags = SA_SIGINFO | SA_ONSTACK;
+            libc::sigemptyset(&mut action.sa_mask);
+            let mut old: sigaction = mem::zeroed();
+            let r = libc::sigaction(signum, &action, &mut old);
+            assert!(r == 0);
+            let _ = previous.set(old);
+        }
+    });
+}
+
+unsafe extern "C" fn handler(signum: c_int, info: *mut siginfo_t, context: *mut c_void) {
+    let context = context as *mut ucontext_t;
+    let regions = ACTIVE_REGIONS.with(|active| active.get());
+    if !regions.is_null() {
+        let gregs = &mut (*context).uc_mcontext.gregs;
+        let pc = gregs[libc::REG_RIP as usize] as usize;
+        if let Some(trap) = (*regions).find(pc, (*info).si_addr() as usize) {
+            // resume in the trap stub, which unwinds the faulting frame
+            gregs[libc::REG_RIP as usize] = (*regions).trap_stub as i64;
+            gregs[libc::REG_RDI as usize] = trap as u32 as i64;
+            return;
+        }
+    }
+    let previous = if signum == SIGBUS {
+        PREVIOUS_SIGBUS.get()
+    } else {
+        PREVIOUS_SIGSEGV.get()
+    };
+    let Some(previous) = previous else {
+        return;
+    };
+    match previous.sa_sigaction {
+        SIG_DFL | SIG_IGN => {
+            // restore the previous disposition and let the faulting instruction run again
+            libc::sigaction(signum, previous, ptr::null_mut());
+        }
+        f if previous.sa_flags & SA_SIGINFO != 0 => {
+            let f: extern "C" fn(c_int, *mut siginfo_t, *mut c_void) = mem::transmute(f);
+            f(signum, info, context as *mut c_void);
+        }
+        f => {
+            let f: extern "C" fn(c_int) = mem::transmute(f);
+            f(signum);
+        }
+    }
+}
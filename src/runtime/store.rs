@@ -1,32 +1,193 @@
 use std::collections::HashMap;
 
-use crate::wasm::{Func, WasmModule};
-use anyhow::{Context as _, Result};
-use wasmparser::{Export, FuncType};
+use crate::wasm::{Func, Names, WasmModule};
+use anyhow::{bail, Context as _, Result};
+use wasmparser::{
+    ConstExpr, DataKind, ElementItems, ElementKind, Export, FuncType, Operator, TableInit, TypeRef,
+};
 
-use super::error::RuntimeError;
+use super::{
+    error::RuntimeError,
+    global::Global,
+    linker::{HostFunc, Linker},
+    memory::Memory,
+    table::{Table, TableElement},
+    Value,
+};
 type Exports<'a> = HashMap<&'a str, Export<'a>>;
 
 #[derive(Debug)]
 pub struct Store<'a> {
     pub types: Vec<FuncType>,
+    /// For every type, the index of the first structurally equal type.
+    pub canonical_types: Vec<u32>,
+    /// Type indices of every function, imported ones first.
     pub funcs: Vec<u32>,
+    pub imported_funcs: Vec<HostFunc>,
     pub code: Vec<Func<'a>>,
     pub exports: Exports<'a>,
+    pub tables: Vec<Table>,
+    pub memories: Vec<Memory>,
+    /// Every global, imported ones first.
+    pub globals: Vec<Global>,
+    pub names: Names<'a>,
 }
 
 impl<'a> Store<'a> {
-    pub fn new(modules: WasmModule<'a>) -> Store<'a> {
-        Store {
+    pub fn new(modules: WasmModule<'a>, linker: &Linker) -> Result<Store<'a>> {
+        let mut funcs = Vec::new();
+        let mut imported_funcs = Vec::new();
+        let mut globals = Vec::new();
+        for import in &modules.imports {
+            let name = format!("{}.{}", import.module, import.name);
+            match import.ty {
+                TypeRef::Func(type_index) => {
+                    let host_func = linker
+                        .get_func(import.module, import.name)
+                        .with_context(|| RuntimeError::UnknownImport(name.clone()))?;
+                    let func_type = modules
+                        .types
+                        .get(type_index as usize)
+                        .with_context(|| RuntimeError::FunctionTypeNotFound(name.clone()))?;
+                    if host_func.ty != *func_type {
+                        bail!(RuntimeError::IncompatibleImportType(name));
+                    }
+                    funcs.push(type_index);
+                    imported_funcs.push(host_func.clone());
+                }
+                TypeRef::Global(global_type) => {
+                    let global = linker
+                        .get_global(import.module, import.name)
+                        .with_context(|| RuntimeError::UnknownImport(name.clone()))?;
+                    if global.ty() != global_type {
+                        bail!(RuntimeError::IncompatibleImportType(name));
+                    }
+                    globals.push(global.clone());
+                }
+                _ => bail!(RuntimeError::UnknownImport(name)),
+            }
+        }
+        funcs.extend(modules.funcs);
+        let canonical_types = modules
+            .types
+            .iter()
+            .map(|ty| modules.types.iter().position(|other| other == ty).unwrap() as u32)
+            .collect();
+        let mut store = Store {
             types: modules.types,
-            funcs: modules.funcs,
+            canonical_types,
+            funcs,
+            imported_funcs,
             code: modules.code,
             exports: modules
                 .exports
                 .into_iter()
                 .map(|export| (export.name, export))
                 .collect(),
+            tables: Vec::new(),
+            memories: modules
+                .memories
+                .iter()
+                .map(Memory::new)
+                .collect::<Result<_>>()?,
+            globals,
+            names: modules.names,
+        };
+        for global in modules.globals {
+            let value = store.eval_const_expr(&global.init_expr)?;
+            store.globals.push(Global::new(global.ty, value)?);
+        }
+        for table in &modules.tables {
+            let mut new_table = Table::new(&table.ty)?;
+            if let TableInit::Expr(expr) = &table.init {
+                let element = store.eval_ref_expr(expr)?;
+                new_table.init(0, &vec![element; table.ty.initial as usize])?;
+            }
+            store.tables.push(new_table);
+        }
+        for element in modules.elements {
+            let ElementKind::Active {
+                table_index,
+                offset_expr,
+            } = element.kind
+            else {
+                continue;
+            };
+            let offset = match store.eval_const_expr(&offset_expr)? {
+                Value::I32(offset) => offset as u32 as usize,
+                value => bail!(RuntimeError::UnsupportedConstExpr(format!("{:?}", value))),
+            };
+            let elements = match element.items {
+                ElementItems::Functions(funcs) => funcs
+                    .into_iter()
+                    .map(|func_index| store.func_element(func_index?))
+                    .collect::<Result<Vec<_>>>()?,
+                ElementItems::Expressions(_, exprs) => exprs
+                    .into_iter()
+                    .map(|expr| store.eval_ref_expr(&expr?))
+                    .collect::<Result<Vec<_>>>()?,
+            };
+            store
+                .get_table_mut(table_index.unwrap_or(0))?
+                .init(offset, &elements)?;
+        }
+        for data in modules.data {
+            if let DataKind::Active {
+                memory_index,
+                offset_expr,
+            } = data.kind
+            {
+                let offset = match store.eval_const_expr(&offset_expr)? {
+                    Value::I32(offset) => offset as u32 as usize,
+                    value => bail!(RuntimeError::UnsupportedConstExpr(format!("{:?}", value))),
+                };
+                store
+                    .get_memory_mut(memory_index)?
+                    .write(offset, data.data)?;
+            }
         }
+        Ok(store)
+    }
+
+    pub fn eval_const_expr(&self, expr: &ConstExpr<'_>) -> Result<Value> {
+        let mut reader = expr.get_operators_reader();
+        let value = match reader.read()? {
+            Operator::I32Const { value } => Value::I32(value),
+            Operator::I64Const { value } => Value::I64(value),
+            Operator::F32Const { value } => Value::F32(f32::from_bits(value.bits())),
+            Operator::F64Const { value } => Value::F64(f64::from_bits(value.bits())),
+            Operator::GlobalGet { global_index } => self.get_global(global_index)?.get(),
+            op => bail!(RuntimeError::UnsupportedConstExpr(format!("{:?}", op))),
+        };
+        match reader.read()? {
+            Operator::End => Ok(value),
+            op => bail!(RuntimeError::UnsupportedConstExpr(format!("{:?}", op))),
+        }
+    }
+
+    /// Evaluates a `ref.func` or `ref.null` expression to a table element.
+    fn eval_ref_expr(&self, expr: &ConstExpr<'_>) -> Result<TableElement> {
+        let mut reader = expr.get_operators_reader();
+        let element = match reader.read()? {
+            Operator::RefFunc { function_index } => self.func_element(function_index)?,
+            Operator::RefNull { .. } => TableElement::default(),
+            op => bail!(RuntimeError::UnsupportedConstExpr(format!("{:?}", op))),
+        };
+        match reader.read()? {
+            Operator::End => Ok(element),
+            op => bail!(RuntimeError::UnsupportedConstExpr(format!("{:?}", op))),
+        }
+    }
+
+    fn func_element(&self, func_index: u32) -> Result<TableElement> {
+        let type_index = self
+            .funcs
+            .get(func_index as usize)
+            .with_context(|| RuntimeError::FunctionNotFound(self.func_symbol(func_index)))?;
+        Ok(TableElement {
+            type_id: self.canonical_types[*type_index as usize] + 1,
+            func_index,
+        })
     }
 
     pub fn get_export(&self, name: &str) -> Result<&Export<'a>> {
@@ -39,7 +200,7 @@ impl<'a> Store<'a> {
         let func_index = self
             .funcs
             .get(index as usize)
-            .with_context(|| RuntimeError::FunctionNotFound(index.to_string()))?;
+            .with_context(|| RuntimeError::FunctionNotFound(self.func_symbol(index)))?;
         let func_type = self
             .types
             .get(*func_index as usize)
@@ -54,8 +215,74 @@ impl<'a> Store<'a> {
     }
 
     pub fn get_code(&self, index: u32) -> Result<&Func<'a>> {
-        self.code
+        (index as usize)
+            .checked_sub(self.imported_funcs.len())
+            .and_then(|index| self.code.get(index))
+            .with_context(|| RuntimeError::FunctionNotFound(self.func_symbol(index)))
+    }
+
+    /// The name of the module in the name section.
+    pub fn module_name(&self) -> Option<String> {
+        self.names.module.map(symbol_name)
+    }
+
+    /// The name of a function in the name section.
+    pub fn func_name(&self, index: u32) -> Option<String> {
+        self.names.funcs.get(&index).copied().map(symbol_name)
+    }
+
+    /// Names a function for diagnostics and tools: its name in the name section, or
+    /// `wasm_func_<index>` if it has none.
+    pub fn func_symbol(&self, index: u32) -> String {
+        self.func_name(index)
+            .unwrap_or_else(|| format!("wasm_func_{}", index))
+    }
+
+    /// The name of a param or local in the name section.
+    pub fn local_name(&self, func_index: u32, local_index: u32) -> Option<String> {
+        let name = self.names.locals.get(&func_index)?.get(&local_index)?;
+        Some(symbol_name(name))
+    }
+
+    pub fn get_imported_func(&self, index: u32) -> Option<&HostFunc> {
+        self.imported_funcs.get(index as usize)
+    }
+
+    pub fn get_global(&self, index: u32) -> Result<&Global> {
+        self.globals
+            .get(index as usize)
+            .with_context(|| RuntimeError::GlobalNotFound(index.to_string()))
+    }
+
+    pub fn get_table(&self, index: u32) -> Result<&Table> {
+        self.tables
             .get(index as usize)
-            .with_context(|| RuntimeError::FunctionNotFound(index.to_string()))
+            .with_context(|| RuntimeError::TableNotFound(index.to_string()))
     }
+
+    pub fn get_table_mut(&mut self, index: u32) -> Result<&mut Table> {
+        self.tables
+            .get_mut(index as usize)
+            .with_context(|| RuntimeError::TableNotFound(index.to_string()))
+    }
+
+    pub fn get_memory(&self, index: u32) -> Result<&Memory> {
+        self.memories
+            .get(index as usize)
+            .with_context(|| RuntimeError::MemoryNotFound(index.to_string()))
+    }
+
+    pub fn get_memory_mut(&mut self, index: u32) -> Result<&mut Memory> {
+        self.memories
+            .get_mut(index as usize)
+            .with_context(|| RuntimeError::MemoryNotFound(index.to_string()))
+    }
+}
+
+/// Any string is a valid name, but names end up in line-based files like the perf map and in
+/// NUL-terminated ELF strings, so control characters are replaced with `_`.
+fn symbol_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_control() { '_' } else { c })
+        .collect()
 }
@@ -0,0 +1,52 @@
+use anyhow::{bail, Result};
+use wasmparser::TableType;
+
+use super::error::{RuntimeError, Trap};
+
+/// A table entry in the layout `call_indirect` reads. `type_id` is the canonical type index of
+/// the function plus one, so that a null entry is all zeros.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub(crate) struct TableElement {
+    pub type_id: u32,
+    pub func_index: u32,
+}
+
+/// A table of function references.
+#[derive(Debug)]
+pub struct Table {
+    elements: Vec<TableElement>,
+}
+
+impl Table {
+    pub fn new(ty: &TableType) -> Result<Table> {
+        if !ty.element_type.is_func_ref() || ty.table64 || ty.shared {
+            bail!(RuntimeError::UnsupportedTable(format!("{:?}", ty)));
+        }
+        Ok(Table {
+            elements: vec![TableElement::default(); ty.initial as usize],
+        })
+    }
+
+    pub fn size(&self) -> u32 {
+        self.elements.len() as u32
+    }
+
+    /// Returns the function index stored at `index`, or `None` for a null entry.
+    pub fn get(&self, index: u32) -> Option<Option<u32>> {
+        let element = self.elements.get(index as usize)?;
+        Some((element.type_id != 0).then_some(element.func_index))
+    }
+
+    pub(crate) fn init(&mut self, offset: usize, elements: &[TableElement]) -> Result<()> {
+        let Some(dest) = self.elements.get_mut(offset..offset + elements.len()) else {
+            bail!(RuntimeError::Trap(Trap::TableOutOfBounds));
+        };
+        dest.copy_from_slice(elements);
+        Ok(())
+    }
+
+    pub(crate) fn base(&self) -> *const TableElement {
+        self.elements.as_ptr()
+    }
+}
@@ -0,0 +1,169 @@
+use std::marker::PhantomData;
+
+use anyhow::{bail, Result};
+use wasmparser::ValType;
+
+use super::{error::RuntimeError, Runtime};
+
+/// A Rust type that corresponds to a wasm value type.
+pub trait WasmTy: Copy {
+    const TYPE: ValType;
+    fn into_raw(self) -> u64;
+    fn from_raw(raw: u64) -> Self;
+}
+
+impl WasmTy for i32 {
+    const TYPE: ValType = ValType::I32;
+    fn into_raw(self) -> u64 {
+        self as u64
+    }
+    fn from_raw(raw: u64) -> Self {
+        raw as i32
+    }
+}
+
+impl WasmTy for i64 {
+    const TYPE: ValType = ValType::I64;
+    fn into_raw(self) -> u64 {
+        self as u64
+    }
+    fn from_raw(raw: u64) -> Self {
+        raw as i64
+    }
+}
+
+impl WasmTy for f32 {
+    const TYPE: ValType = ValType::F32;
+    fn into_raw(self) -> u64 {
+        self.to_bits() as u64
+    }
+    fn from_raw(raw: u64) -> Self {
+        f32::from_bits(raw as u32)
+    }
+}
+
+impl WasmTy for f64 {
+    const TYPE: ValType = ValType::F64;
+    fn into_raw(self) -> u64 {
+        self.to_bits()
+    }
+    fn from_raw(raw: u64) -> Self {
+        f64::from_bits(raw)
+    }
+}
+
+/// The params of a typed function: a single `WasmTy` or a tuple of them.
+pub trait WasmParams {
+    const TYPES: &'static [ValType];
+    /// Writes the params to consecutive data stack slots starting at `sp`.
+    ///
+    /// # Safety
+    /// `sp` must be valid for `TYPES.len()` writes.
+    unsafe fn write(self, sp: *mut u64);
+}
+
+/// The results of a typed function: a single `WasmTy` or a tuple of them.
+pub trait WasmResults {
+    const TYPES: &'static [ValType];
+    /// Reads the results from consecutive data stack slots starting at `sp`.
+    ///
+    /// # Safety
+    /// `sp` must be valid for `TYPES.len()` reads.
+    unsafe fn read(sp: *const u64) -> Self;
+}
+
+impl<T: WasmTy> WasmParams for T {
+    const TYPES: &'static [ValType] = &[T::TYPE];
+    unsafe fn write(self, sp: *mut u64) {
+        *sp = self.into_raw();
+    }
+}
+
+impl<T: WasmTy> WasmResults for T {
+    const TYPES: &'static [ValType] = &[T::TYPE];
+    unsafe fn read(sp: *const u64) -> Self {
+        T::from_raw(*sp)
+    }
+}
+
+macro_rules! impl_wasm_tuple {
+    ($($t:ident)*) => {
+        #[allow(non_snake_case, unused_assignments, unused_mut, unused_variables)]
+        impl<$($t: WasmTy),*> WasmParams for ($($t,)*) {
+            const TYPES: &'static [ValType] = &[$($t::TYPE),*];
+            unsafe fn write(self, sp: *mut u64) {
+                let ($($t,)*) = self;
+                let mut i = 0;
+                $(
+                    *sp.add(i) = $t.into_raw();
+                    i += 1;
+                )*
+            }
+        }
+
+        #[allow(unused_assignments, unused_mut, unused_variables, clippy::unused_unit)]
+        impl<$($t: WasmTy),*> WasmResults for ($($t,)*) {
+            const TYPES: &'static [ValType] = &[$($t::TYPE),*];
+            unsafe fn read(sp: *const u64) -> Self {
+                let mut i = 0;
+                ($({
+                    let value = $t::from_raw(*sp.add(i));
+                    i += 1;
+                    value
+                },)*)
+            }
+        }
+    };
+}
+
+impl_wasm_tuple!();
+impl_wasm_tuple!(A);
+impl_wasm_tuple!(A B);
+impl_wasm_tuple!(A B C);
+impl_wasm_tuple!(A B C D);
+impl_wasm_tuple!(A B C D E);
+impl_wasm_tuple!(A B C D E F);
+impl_wasm_tuple!(A B C D E F G);
+impl_wasm_tuple!(A B C D E F G H);
+
+/// An exported function whose signature was checked once by `Runtime::get_typed_func`, so
+/// calls pass params and results through the data stack without converting to `Value`s.
+#[derive(Debug)]
+pub struct TypedFunc<Params, Results> {
+    index: u32,
+    runtime_id: u64,
+    _signature: PhantomData<fn(Params) -> Results>,
+}
+
+impl<Params, Results> Clone for TypedFunc<Params, Results> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Params, Results> Copy for TypedFunc<Params, Results> {}
+
+impl<Params: WasmParams, Results: WasmResults> TypedFunc<Params, Results> {
+    pub(crate) fn new(index: u32, runtime_id: u64) -> TypedFunc<Params, Results> {
+        TypedFunc {
+            index,
+            runtime_id,
+            _signature: PhantomData,
+        }
+    }
+
+    /// Calls the function in `runtime`, which has to be the runtime it was obtained from.
+    pub fn call(&self, runtime: &mut Runtime<'_>, params: Params) -> Result<Results> {
+        if runtime.id != self.runtime_id {
+            bail!(RuntimeError::ForeignFunc(format!(
+                "function {}",
+                self.index
+            )));
+        }
+        unsafe {
+            params.write(runtime.stack_base);
+            runtime.call_func_with_args(self.index, Params::TYPES.len())?;
+            Ok(Results::read(runtime.stack_base))
+        }
+    }
+}
@@ -0,0 +1,371 @@
+//! A subset of `wasi_snapshot_preview1` implemented against the instance's linear memory.
+
+use std::{
+    cell::RefCell,
+    io::{Read, Write},
+    rc::Rc,
+};
+
+use anyhow::Result;
+use thiserror::Error;
+use wasmparser::{FuncType, ValType};
+
+use crate::runtime::{
+    linker::{Caller, Linker},
+    memory::Memory,
+    Value,
+};
+
+pub const MODULE_NAME: &str = "wasi_snapshot_preview1";
+
+type Errno = i32;
+const ERRNO_SUCCESS: Errno = 0;
+const ERRNO_BADF: Errno = 8;
+const ERRNO_FAULT: Errno = 21;
+const ERRNO_INVAL: Errno = 28;
+const ERRNO_IO: Errno = 29;
+const ERRNO_OVERFLOW: Errno = 61;
+const ERRNO_SPIPE: Errno = 70;
+
+const FILETYPE_CHARACTER_DEVICE: u8 = 2;
+
+/// Raised by `proc_exit` to unwind the running program.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("wasi program exited with code {0}")]
+pub struct Exit(pub i32);
+
+/// The state of a WASI program: its arguments, environment and standard streams.
+pub struct Wasi {
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    stdin: Box<dyn Read>,
+    stdout: Box<dyn Write>,
+    stderr: Box<dyn Write>,
+}
+
+impl Default for Wasi {
+    fn default() -> Self {
+        Wasi {
+            args: Vec::new(),
+            env: Vec::new(),
+            stdin: Box::new(std::io::stdin()),
+            stdout: Box::new(std::io::stdout()),
+            stderr: Box::new(std::io::stderr()),
+        }
+    }
+}
+
+impl Wasi {
+    pub fn new() -> Wasi {
+        Wasi::default()
+    }
+
+    pub fn args(mut self, args: impl IntoIterator<Item = impl Into<String>>) -> Wasi {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn env(
+        mut self,
+        env: impl IntoIterator<Item = (impl Into<String>, impl Into<String>)>,
+    ) -> Wasi {
+        self.env = env
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+        self
+    }
+
+    pub fn stdin(mut self, stdin: impl Read + 'static) -> Wasi {
+        self.stdin = Box::new(stdin);
+        self
+    }
+
+    pub fn stdout(mut self, stdout: impl Write + 'static) -> Wasi {
+        self.stdout = Box::new(stdout);
+        self
+    }
+
+    pub fn stderr(mut self, stderr: impl Write + 'static) -> Wasi {
+        self.stderr = Box::new(stderr);
+        self
+    }
+
+    /// Defines the `wasi_snapshot_preview1` functions in `linker`.
+    pub fn add_to_linker(self, linker: &mut Linker) {
+        let wasi = Rc::new(RefCell::new(self));
+        define(linker, &wasi, "fd_write", 4, |wasi, memory, args| {
+            let [fd, iovs, iovs_len, nwritten] = [args[0], args[1], args[2], args[3]];
+            let out: &mut dyn Write = match fd {
+                1 => &mut wasi.stdout,
+                2 => &mut wasi.stderr,
+                _ => return Ok(ERRNO_BADF),
+            };
+            let mut written = 0_u32;
+            for i in 0..iovs_len {
+                let (buf, len) = read_iovec(memory, iovs, i)?;
+                written = written.checked_add(len).ok_or(ERRNO_OVERFLOW)?;
+                let bytes = slice(memory, buf, len)?;
+                if out.write_all(bytes).is_err() {
+                    return Ok(ERRNO_IO);
+                }
+            }
+            let _ = out.flush();
+            write_bytes(memory, nwritten, &written.to_le_bytes())?;
+            Ok(ERRNO_SUCCESS)
+        });
+        define(linker, &wasi, "fd_read", 4, |wasi, memory, args| {
+            let [fd, iovs, iovs_len, nread] = [args[0], args[1], args[2], args[3]];
+            if fd != 0 {
+                return Ok(ERRNO_BADF);
+            }
+            let mut read = 0_u32;
+            for i in 0..iovs_len {
+                let (buf, len) = read_iovec(memory, iovs, i)?;
+                let bytes = slice_mut(memory, buf, len)?;
+                let Ok(n) = wasi.stdin.read(bytes) else {
+                    return Ok(ERRNO_IO);
+                };
+                // n is at most len, which fits in a u32
+                read = read.checked_add(n as u32).ok_or(ERRNO_OVERFLOW)?;
+                if n < len as usize {
+                    break;
+                }
+            }
+            write_bytes(memory, nread, &read.to_le_bytes())?;
+            Ok(ERRNO_SUCCESS)
+        });
+        define(linker, &wasi, "fd_close", 1, |_, _, args| {
+            Ok(if args[0] <= 2 {
+                ERRNO_SUCCESS
+            } else {
+                ERRNO_BADF
+            })
+        });
+        define(linker, &wasi, "fd_fdstat_get", 2, |_, memory, args| {
+            let [fd, stat] = [args[0], args[1]];
+            if fd > 2 {
+                return Ok(ERRNO_BADF);
+            }
+            let mut fdstat = [0; 24];
+            fdstat[0] = FILETYPE_CHARACTER_DEVICE;
+            fdstat[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+            write_bytes(memory, stat, &fdstat)?;
+            Ok(ERRNO_SUCCESS)
+        });
+        define(linker, &wasi, "fd_prestat_get", 2, |_, _, _| Ok(ERRNO_BADF));
+        define(linker, &wasi, "fd_prestat_dir_name", 3, |_, _, _| {
+            Ok(ERRNO_BADF)
+        });
+        linker.func(
+            MODULE_NAME,
+            "fd_seek",
+            FuncType::new(
+                [ValType::I32, ValType::I64, ValType::I32, ValType::I32],
+                [ValType::I32],
+            ),
+            |_, args| {
+                let errno = match args[0] {
+                    Value::I32(0..=2) => ERRNO_SPIPE,
+                    _ => ERRNO_BADF,
+                };
+                Ok(vec![Value::I32(errno)])
+            },
+        );
+        linker.func(
+            MODULE_NAME,
+            "proc_exit",
+            FuncType::new([ValType::I32], []),
+            |_, args| match args {
+                [Value::I32(code)] => Err(Exit(*code).into()),
+                _ => unreachable!(),
+            },
+        );
+        define(linker, &wasi, "args_sizes_get", 2, |wasi, memory, args| {
+            let strings: Vec<&str> = wasi.args.iter().map(String::as_str).collect();
+            sizes_get(memory, &strings, args[0], args[1])
+        });
+        define(linker, &wasi, "args_get", 2, |wasi, memory, args| {
+            let strings: Vec<&str> = wasi.args.iter().map(String::as_str).collect();
+            strings_get(memory, &strings, args[0], args[1])
+        });
+        define(
+            linker,
+            &wasi,
+            "environ_sizes_get",
+            2,
+            |wasi, memory, args| {
+                let strings = wasi.environ();
+                let strings: Vec<&str> = strings.iter().map(String::as_str).collect();
+                sizes_get(memory, &strings, args[0], args[1])
+            },
+        );
+        define(linker, &wasi, "environ_get", 2, |wasi, memory, args| {
+            let strings = wasi.environ();
+            let strings: Vec<&str> = strings.iter().map(String::as_str).collect();
+            strings_get(memory, &strings, args[0], args[1])
+        });
+        linker.func(
+            MODULE_NAME,
+            "clock_time_get",
+            FuncType::new([ValType::I32, ValType::I64, ValType::I32], [ValType::I32]),
+            |caller, args| {
+                let [Value::I32(id), _, Value::I32(time)] = args else {
+                    unreachable!()
+                };
+                let clock = match id {
+                    0 => libc::CLOCK_REALTIME,
+                    1 => libc::CLOCK_MONOTONIC,
+                    2 => libc::CLOCK_PROCESS_CPUTIME_ID,
+                    3 => libc::CLOCK_THREAD_CPUTIME_ID,
+                    _ => return Ok(vec![Value::I32(ERRNO_INVAL)]),
+                };
+                let mut ts = libc::timespec {
+                    tv_sec: 0,
+                    tv_nsec: 0,
+                };
+                if unsafe { libc::clock_gettime(clock, &mut ts) } != 0 {
+                    return Ok(vec![Value::I32(ERRNO_INVAL)]);
+                }
+                let nanos = ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64;
+                let errno = with_memory(caller, |memory| {
+                    write_bytes(memory, *time as u32, &nanos.to_le_bytes())?;
+                    Ok(ERRNO_SUCCESS)
+                });
+                Ok(vec![Value::I32(errno)])
+            },
+        );
+        define(linker, &wasi, "random_get", 2, |_, memory, args| {
+            let bytes = slice_mut(memory, args[0], args[1])?;
+            let mut filled = 0;
+            while filled < bytes.len() {
+                let n = unsafe {
+                    libc::getrandom(
+                        bytes[filled..].as_mut_ptr() as *mut libc::c_void,
+                        bytes.len() - filled,
+                        0,
+                    )
+                };
+                if n < 0 {
+                    return Ok(ERRNO_IO);
+                }
+                filled += n as usize;
+            }
+            Ok(ERRNO_SUCCESS)
+        });
+    }
+
+    fn environ(&self) -> Vec<String> {
+        self.env
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect()
+    }
+}
+
+/// Defines a function that takes `params` i32 arguments and returns an errno.
+fn define(
+    linker: &mut Linker,
+    wasi: &Rc<RefCell<Wasi>>,
+    name: &str,
+    params: usize,
+    body: impl Fn(&mut Wasi, &mut Memory, &[u32]) -> Result<Errno, Errno> + 'static,
+) {
+    let wasi = wasi.clone();
+    linker.func(
+        MODULE_NAME,
+        name,
+        FuncType::new(vec![ValType::I32; params], [ValType::I32]),
+        move |caller, args| {
+            let args: Vec<u32> = args
+                .iter()
+                .map(|arg| match arg {
+                    Value::I32(n) => *n as u32,
+                    _ => unreachable!(),
+                })
+                .collect();
+            let errno = with_memory(caller, |memory| body(&mut wasi.borrow_mut(), memory, &args));
+            Ok(vec![Value::I32(errno)])
+        },
+    );
+}
+
+fn with_memory(
+    caller: &mut Caller<'_>,
+    f: impl FnOnce(&mut Memory) -> Result<Errno, Errno>,
+) -> Errno {
+    match caller.memory() {
+        Some(memory) => f(memory).unwrap_or_else(|errno| errno),
+        None => ERRNO_FAULT,
+    }
+}
+
+fn slice(memory: &Memory, ptr: u32, len: u32) -> Result<&[u8], Errno> {
+    memory
+        .data()
+        .get(ptr as usize..ptr as usize + len as usize)
+        .ok_or(ERRNO_FAULT)
+}
+
+fn slice_mut(memory: &mut Memory, ptr: u32, len: u32) -> Result<&mut [u8], Errno> {
+    memory
+        .data_mut()
+        .get_mut(ptr as usize..ptr as usize + len as usize)
+        .ok_or(ERRNO_FAULT)
+}
+
+/// The address of element `i` of an array of `size`-byte elements at `ptr`. Guest addresses
+/// that do not fit in 32 bits are faults rather than wrapping around.
+fn element(ptr: u32, i: u32, size: u32) -> Result<u32, Errno> {
+    i.checked_mul(size)
+        .and_then(|offset| ptr.checked_add(offset))
+        .ok_or(ERRNO_FAULT)
+}
+
+/// Reads the buffer address and length of entry `i` of the iovec array at `iovs`.
+fn read_iovec(memory: &Memory, iovs: u32, i: u32) -> Result<(u32, u32), Errno> {
+    let iov = element(iovs, i, 8)?;
+    Ok((
+        read_u32(memory, iov)?,
+        read_u32(memory, element(iov, 1, 4)?)?,
+    ))
+}
+
+fn read_u32(memory: &Memory, ptr: u32) -> Result<u32, Errno> {
+    let bytes = slice(memory, ptr, 4)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn write_bytes(memory: &mut Memory, ptr: u32, bytes: &[u8]) -> Result<(), Errno> {
+    memory
+        .data_mut()
+        .get_mut(ptr as usize..ptr as usize + bytes.len())
+        .ok_or(ERRNO_FAULT)?
+        .copy_from_slice(bytes);
+    Ok(())
+}
+
+fn sizes_get(
+    memory: &mut Memory,
+    strings: &[&str],
+    count: u32,
+    buf_size: u32,
+) -> Result<Errno, Errno> {
+    let size: usize = strings.iter().map(|s| s.len() + 1).sum();
+    write_bytes(memory, count, &(strings.len() as u32).to_le_bytes())?;
+    write_bytes(memory, buf_size, &(size as u32).to_le_bytes())?;
+    Ok(ERRNO_SUCCESS)
+}
+
+/// Writes NUL-terminated `strings` to `buf` and pointers to them to `ptrs`.
+fn strings_get(memory: &mut Memory, strings: &[&str], ptrs: u32, buf: u32) -> Result<Errno, Errno> {
+    let mut offset = buf;
+    for (i, s) in strings.iter().enumerate() {
+        write_bytes(memory, element(ptrs, i as u32, 4)?, &offset.to_le_bytes())?;
+        write_bytes(memory, offset, s.as_bytes())?;
+        let end = element(offset, s.len() as u32, 1)?;
+        write_bytes(memory, end, &[0])?;
+        offset = element(end, 1, 1)?;
+    }
+    Ok(ERRNO_SUCCESS)
+}
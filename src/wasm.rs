@@ -1,11 +1,43 @@
-use wasmparser::{Export, FuncType, Operator, ValType};
+use std::fmt;
 
-#[derive(Debug, Default)]
+use fxhash::FxHashMap;
+
+use wasmparser::{
+    Data, Element, Export, FuncType, Global, Import, MemoryType, Operator, Table, ValType,
+};
+
+#[derive(Default)]
 pub struct WasmModule<'a> {
     pub types: Vec<FuncType>,
+    pub imports: Vec<Import<'a>>,
     pub funcs: Vec<u32>,
+    pub tables: Vec<Table<'a>>,
     pub code: Vec<Func<'a>>,
     pub exports: Vec<Export<'a>>,
+    pub memories: Vec<MemoryType>,
+    pub globals: Vec<Global<'a>>,
+    pub elements: Vec<Element<'a>>,
+    pub data: Vec<Data<'a>>,
+    pub names: Names<'a>,
+}
+
+// `Element` does not implement `Debug`, so segments are only counted.
+impl fmt::Debug for WasmModule<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WasmModule")
+            .field("types", &self.types)
+            .field("imports", &self.imports)
+            .field("funcs", &self.funcs)
+            .field("tables", &self.tables)
+            .field("code", &self.code)
+            .field("exports", &self.exports)
+            .field("memories", &self.memories)
+            .field("globals", &self.globals)
+            .field("elements", &self.elements.len())
+            .field("data", &self.data)
+            .field("names", &self.names)
+            .finish()
+    }
 }
 
 #[derive(Debug)]
@@ -13,3 +45,12 @@ pub struct Func<'a> {
     pub locals: Vec<(u32, ValType)>,
     pub body: Vec<Operator<'a>>,
 }
+
+/// Names from the `name` custom section. Functions are indexed like calls, imports first.
+#[derive(Debug, Default)]
+pub struct Names<'a> {
+    pub module: Option<&'a str>,
+    pub funcs: FxHashMap<u32, &'a str>,
+    /// Names of params and locals, by function index and then local index.
+    pub locals: FxHashMap<u32, FxHashMap<u32, &'a str>>,
+}
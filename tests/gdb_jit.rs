@@ -0,0 +1,134 @@
+//! Reads the GDB JIT interface like a debugger would. It is process-wide state, so this runs
+//! as its own test binary where no other test compiles functions concurrently. It needs the
+//! `gdb-jit` feature.
+
+use std::ffi::CStr;
+
+use anyhow::Result;
+use wasm_jit::{parser, runtime::Runtime};
+
+#[repr(C)]
+struct JitCodeEntry {
+    next_entry: *const JitCodeEntry,
+    prev_entry: *const JitCodeEntry,
+    symfile_addr: *const u8,
+    symfile_size: u64,
+}
+
+#[repr(C)]
+struct JitDescriptor {
+    version: u32,
+    action_flag: u32,
+    relevant_entry: *const JitCodeEntry,
+    first_entry: *const JitCodeEntry,
+}
+
+extern "C" {
+    static __jit_debug_descriptor: JitDescriptor;
+}
+
+/// A function symbol read from a registered ELF object.
+#[derive(Debug, PartialEq, Eq)]
+struct Symbol {
+    name: String,
+    address: u64,
+    size: u64,
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Finds the function symbols of an ELF object, at the address of the section they are in.
+fn read_symbols(elf: &[u8]) -> Vec<Symbol> {
+    assert_eq!(&elf[..4], b"\x7fELF");
+    assert_eq!(read_u16(elf, 18), 62);
+    let section_headers = read_u64(elf, 40) as usize;
+    let section = |index: usize| &elf[section_headers + index * 64..][..64];
+    let mut symbols = Vec::new();
+    for index in 0..read_u16(elf, 60) as usize {
+        let symtab = section(index);
+        if read_u32(symtab, 4) != 2 {
+            continue;
+        }
+        let strtab = read_u64(section(read_u32(symtab, 40) as usize), 24) as usize;
+        let (offset, size) = (read_u64(symtab, 24) as usize, read_u64(symtab, 32) as usize);
+        for symbol in elf[offset..offset + size].chunks(24) {
+            if symbol[4] & 0xf != 2 {
+                continue;
+            }
+            let name = CStr::from_bytes_until_nul(&elf[strtab + read_u32(symbol, 0) as usize..])
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string();
+            let text = section(read_u16(symbol, 6) as usize);
+            symbols.push(Symbol {
+                name,
+                address: read_u64(text, 16) + read_u64(symbol, 8),
+                size: read_u64(symbol, 16),
+            });
+        }
+    }
+    symbols
+}
+
+fn registered_symbols() -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    unsafe {
+        let descriptor = &*std::ptr::addr_of!(__jit_debug_descriptor);
+        assert_eq!(descriptor.version, 1);
+        let mut entry = descriptor.first_entry;
+        while let Some(current) = entry.as_ref() {
+            let elf =
+                std::slice::from_raw_parts(current.symfile_addr, current.symfile_size as usize);
+            symbols.extend(read_symbols(elf));
+            entry = current.next_entry;
+        }
+    }
+    symbols
+}
+
+#[test]
+fn test_gdb_jit_registration() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/call.wasm");
+    // nothing is registered unless a runtime asks for it
+    let mut runtime = Runtime::init(parser::parse(bytes)?)?;
+    runtime.call_func_by_name("_start", &[])?;
+    assert!(registered_symbols().is_empty());
+
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    runtime.enable_gdb_jit();
+    runtime.enable_listing();
+    runtime.call_func_by_name("_start", &[])?;
+    let mut expected: Vec<Symbol> = runtime
+        .listings()
+        .iter()
+        .map(|listing| Symbol {
+            name: format!("wasm_func_{}", listing.func_index),
+            address: listing.address as u64,
+            size: listing.code.len() as u64,
+        })
+        .collect();
+    assert_eq!(expected.len(), 3);
+    // the newest registration comes first
+    expected.reverse();
+    assert_eq!(registered_symbols(), expected);
+    let descriptor = unsafe { &*std::ptr::addr_of!(__jit_debug_descriptor) };
+    assert_eq!(descriptor.action_flag, 1);
+
+    // the functions are withdrawn with the runtime that owns their code
+    drop(runtime);
+    assert!(registered_symbols().is_empty());
+    assert_eq!(descriptor.action_flag, 2);
+    Ok(())
+}
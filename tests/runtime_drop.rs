@@ -0,0 +1,31 @@
+//! Counts the mappings of the whole process, so this runs as its own test binary where no
+//! other test creates or drops runtimes concurrently.
+
+use anyhow::Result;
+use wasm_jit::{
+    parser,
+    runtime::{Runtime, Value},
+};
+
+#[test]
+fn test_runtime_drop() -> Result<()> {
+    let mappings =
+        || -> Result<usize> { Ok(std::fs::read_to_string("/proc/self/maps")?.lines().count()) };
+    let bytes = include_bytes!("../tests/wasm/memory.wasm");
+    let before = mappings()?;
+    for _ in 0..1000 {
+        let modules = parser::parse(bytes)?;
+        let mut runtime = Runtime::init(modules)?;
+        runtime.call_func_by_name("load_i32", &[Value::I32(0)])?;
+    }
+    // each leaked stack guard page or code area would be a mapping of its own
+    assert!(mappings()? < before + 200);
+    // freed stacks are handed out again, which faults if their guard pages were left behind
+    let buffers: Vec<Vec<u8>> = (0..1000).map(|i| vec![i as u8; 20 * 1024]).collect();
+    assert!(buffers
+        .iter()
+        .enumerate()
+        .all(|(i, b)| b[b.len() - 1] == i as u8));
+
+    Ok(())
+}
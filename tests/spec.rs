@@ -0,0 +1,612 @@
+//! Runs `.wast` scripts: the WebAssembly spec testsuite vendored in `tests/spec/testsuite`, and
+//! the hand-written scripts in `tests/wast`.
+//!
+//! Both follow the layout of the upstream testsuite: scripts directly in the directory are the
+//! core suite and scripts in `proposals/<name>` belong to that proposal. The testsuite is an
+//! unmodified copy of `https://github.com/WebAssembly/testsuite` made by
+//! `tests/spec/update-testsuite.sh`, which records the revision in `testsuite/REVISION`.
+//! Its commands that are known to fail are listed in `tests/spec/expected_failures.txt`, which
+//! a run with `UPDATE_EXPECTED_FAILURES=1` rewrites from the failures it saw.
+//! Commands the runner cannot express, such as components or threads, are counted as skipped.
+//! Everything else that goes wrong, including operators the compiler lacks, is a failure.
+//!
+//! The testsuite is not checked in, so `spec_testsuite` is ignored by default and fails when
+//! the testsuite is missing:
+//!
+//! ```text
+//! tests/spec/update-testsuite.sh <revision>
+//! cargo test --test spec spec_testsuite -- --ignored
+//! ```
+//!
+//! The scripts in `tests/wast` are named after what they cover rather than after spec scripts,
+//! since each covers only a small part of its spec counterpart.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use wasm_jit::{
+    parser,
+    runtime::{error::RuntimeError, global::Global, linker::Linker, Runtime, Value},
+    wasm::WasmModule,
+};
+use wasmparser::{ExternalKind, FuncType, GlobalType, TypeRef, ValType};
+use wast::{
+    core::{NanPattern, WastArgCore, WastRetCore},
+    parser::{self as wast_parser, ParseBuffer},
+    QuoteWat, Wast, WastArg, WastDirective, WastExecute, WastInvoke, WastRet,
+};
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Counts {
+    passed: usize,
+    failed: usize,
+    skipped: usize,
+}
+
+impl Counts {
+    fn add(&mut self, other: Counts) {
+        self.passed += other.passed;
+        self.failed += other.failed;
+        self.skipped += other.skipped;
+    }
+}
+
+enum Outcome {
+    Passed,
+    Skipped,
+}
+
+/// An error for commands the runtime cannot run yet, such as ones using reference types.
+#[derive(Debug, thiserror::Error)]
+#[error("unsupported: {0}")]
+struct Unsupported(String);
+
+/// Commands that need features the runner lacks are skipped rather than failed.
+fn is_unsupported(err: &anyhow::Error) -> bool {
+    err.is::<Unsupported>()
+}
+
+/// An instantiated module together with its exports, which `register` needs.
+struct Instance {
+    runtime: RefCell<Runtime<'static>>,
+    funcs: Vec<(String, FuncType)>,
+    globals: Vec<String>,
+}
+
+impl Instance {
+    fn new(bytes: Vec<u8>, linker: &Linker) -> Result<Instance> {
+        // the runtime borrows the module bytes for as long as it lives
+        let bytes: &'static [u8] = bytes.leak();
+        let module = parser::parse(bytes)?;
+        let (funcs, globals) = export_types(&module)?;
+        let runtime = Runtime::instantiate(module, linker)?;
+        Ok(Instance {
+            runtime: RefCell::new(runtime),
+            funcs,
+            globals,
+        })
+    }
+}
+
+/// The exported functions with their types, and the names of the exported globals.
+#[allow(clippy::type_complexity)]
+fn export_types(module: &WasmModule<'_>) -> Result<(Vec<(String, FuncType)>, Vec<String>)> {
+    let mut func_types = Vec::new();
+    for import in &module.imports {
+        if let TypeRef::Func(index) = import.ty {
+            func_types.push(index);
+        }
+    }
+    func_types.extend(&module.funcs);
+
+    let mut funcs = Vec::new();
+    let mut globals = Vec::new();
+    for export in &module.exports {
+        let index = export.index as usize;
+        match export.kind {
+            ExternalKind::Func => {
+                let ty = func_types
+                    .get(index)
+                    .and_then(|ty| module.types.get(*ty as usize))
+                    .with_context(|| format!("unknown function {}", index))?;
+                funcs.push((export.name.to_string(), ty.clone()));
+            }
+            ExternalKind::Global => globals.push(export.name.to_string()),
+            _ => {}
+        }
+    }
+    Ok((funcs, globals))
+}
+
+/// Imports every spectest script can use. Printing is a no-op.
+fn spectest_linker() -> Result<Linker> {
+    let mut linker = Linker::new();
+    let prints: [(&str, &[ValType]); 7] = [
+        ("print", &[]),
+        ("print_i32", &[ValType::I32]),
+        ("print_i64", &[ValType::I64]),
+        ("print_f32", &[ValType::F32]),
+        ("print_f64", &[ValType::F64]),
+        ("print_i32_f32", &[ValType::I32, ValType::F32]),
+        ("print_f64_f64", &[ValType::F64, ValType::F64]),
+    ];
+    for (name, params) in prints {
+        linker.func(
+            "spectest",
+            name,
+            FuncType::new(params.iter().copied(), []),
+            |_, _| Ok(vec![]),
+        );
+    }
+    let globals = [
+        ("global_i32", Value::I32(666)),
+        ("global_i64", Value::I64(666)),
+        ("global_f32", Value::F32(666.6)),
+        ("global_f64", Value::F64(666.6)),
+    ];
+    for (name, value) in globals {
+        let ty = GlobalType {
+            content_type: value.ty(),
+            mutable: false,
+            shared: false,
+        };
+        linker.global("spectest", name, Global::new(ty, value)?);
+    }
+    Ok(linker)
+}
+
+struct ScriptRunner {
+    linker: Linker,
+    current: Option<Rc<Instance>>,
+    named: HashMap<String, Rc<Instance>>,
+}
+
+impl ScriptRunner {
+    fn new() -> Result<ScriptRunner> {
+        Ok(ScriptRunner {
+            linker: spectest_linker()?,
+            current: None,
+            named: HashMap::new(),
+        })
+    }
+
+    fn instance(&self, id: Option<wast::token::Id<'_>>) -> Result<Rc<Instance>> {
+        match id {
+            Some(id) => self
+                .named
+                .get(id.name())
+                .cloned()
+                .with_context(|| format!("unknown module ${}", id.name())),
+            None => self.current.clone().context("no module instantiated"),
+        }
+    }
+
+    fn run(&mut self, directive: WastDirective<'_>) -> Result<Outcome> {
+        match directive {
+            WastDirective::Module(mut module) => {
+                let name = module.name();
+                let instance = Rc::new(Instance::new(module.encode()?, &self.linker)?);
+                if let Some(name) = name {
+                    self.named.insert(name.name().to_string(), instance.clone());
+                }
+                self.current = Some(instance);
+            }
+            WastDirective::Register { name, module, .. } => {
+                let instance = self.instance(module)?;
+                self.register(name, &instance)?;
+            }
+            WastDirective::Invoke(invoke) => {
+                self.invoke(&invoke)?;
+            }
+            WastDirective::AssertReturn { exec, results, .. } => {
+                let values = self.execute(exec)?;
+                if values.len() != results.len() {
+                    bail!("expected {} results, got {:?}", results.len(), values);
+                }
+                for (value, expected) in values.iter().zip(&results) {
+                    if !matches_result(value, expected)? {
+                        bail!("expected {:?}, got {:?}", expected, values);
+                    }
+                }
+            }
+            WastDirective::AssertTrap { exec, message, .. } => {
+                let result = self.execute(exec);
+                expect_trap(result, message)?;
+            }
+            WastDirective::AssertExhaustion { call, message, .. } => {
+                let result = self.invoke(&call);
+                expect_trap(result, message)?;
+            }
+            WastDirective::AssertInvalid {
+                module, message, ..
+            }
+            | WastDirective::AssertMalformed {
+                module, message, ..
+            } => {
+                if let QuoteWat::QuoteComponent(..) = module {
+                    return Ok(Outcome::Skipped);
+                }
+                if self.try_instantiate(module).is_ok() {
+                    bail!("expected module to be rejected: {}", message);
+                }
+            }
+            WastDirective::AssertUnlinkable {
+                module, message, ..
+            } => {
+                if self.try_instantiate(QuoteWat::Wat(module)).is_ok() {
+                    bail!("expected module to fail to link: {}", message);
+                }
+            }
+            WastDirective::ModuleDefinition(_)
+            | WastDirective::AssertInvalidCustom { .. }
+            | WastDirective::AssertMalformedCustom { .. }
+            | WastDirective::ModuleInstance { .. }
+            | WastDirective::AssertException { .. }
+            | WastDirective::AssertSuspension { .. }
+            | WastDirective::Thread(_)
+            | WastDirective::Wait { .. } => return Ok(Outcome::Skipped),
+        }
+        Ok(Outcome::Passed)
+    }
+
+    fn try_instantiate(&self, mut module: QuoteWat<'_>) -> Result<Instance> {
+        Instance::new(module.encode()?, &self.linker)
+    }
+
+    /// Makes the exports of `instance` importable as module `name`. Exported globals are
+    /// shared, so importers see later writes by the instance and the other way around.
+    fn register(&mut self, name: &str, instance: &Rc<Instance>) -> Result<()> {
+        for (export, ty) in &instance.funcs {
+            let callee = instance.clone();
+            let export_name = export.clone();
+            self.linker.func(name, export, ty.clone(), move |_, args| {
+                callee
+                    .runtime
+                    .borrow_mut()
+                    .call_func_by_name(&export_name, args)
+            });
+        }
+        for export in &instance.globals {
+            let global = instance
+                .runtime
+                .borrow()
+                .get_exported_global(export)?
+                .clone();
+            self.linker.global(name, export, global);
+        }
+        Ok(())
+    }
+
+    fn invoke(&self, invoke: &WastInvoke<'_>) -> Result<Vec<Value>> {
+        let instance = self.instance(invoke.module)?;
+        let args = invoke
+            .args
+            .iter()
+            .map(to_value)
+            .collect::<Result<Vec<_>>>()?;
+        let result = instance
+            .runtime
+            .borrow_mut()
+            .call_func_by_name(invoke.name, &args);
+        result
+    }
+
+    fn execute(&mut self, exec: WastExecute<'_>) -> Result<Vec<Value>> {
+        match exec {
+            WastExecute::Invoke(invoke) => self.invoke(&invoke),
+            WastExecute::Get { module, global, .. } => {
+                let instance = self.instance(module)?;
+                let value = instance.runtime.borrow().get_global(global)?;
+                Ok(vec![value])
+            }
+            WastExecute::Wat(module) => {
+                self.try_instantiate(QuoteWat::Wat(module))?;
+                Ok(vec![])
+            }
+        }
+    }
+}
+
+fn to_value(arg: &WastArg<'_>) -> Result<Value> {
+    let WastArg::Core(arg) = arg else {
+        bail!(Unsupported("component argument".to_string()));
+    };
+    Ok(match arg {
+        WastArgCore::I32(v) => Value::I32(*v),
+        WastArgCore::I64(v) => Value::I64(*v),
+        WastArgCore::F32(v) => Value::F32(f32::from_bits(v.bits)),
+        WastArgCore::F64(v) => Value::F64(f64::from_bits(v.bits)),
+        arg => bail!(Unsupported(format!("argument {:?}", arg))),
+    })
+}
+
+/// Compares floats by their bits, so that NaN payloads and the sign of zero are checked.
+fn matches_result(value: &Value, expected: &WastRet<'_>) -> Result<bool> {
+    let WastRet::Core(expected) = expected else {
+        bail!(Unsupported("component result".to_string()));
+    };
+    Ok(match (value, expected) {
+        (Value::I32(v), WastRetCore::I32(e)) => v == e,
+        (Value::I64(v), WastRetCore::I64(e)) => v == e,
+        (Value::F32(v), WastRetCore::F32(pattern)) => {
+            let bits = v.to_bits();
+            match pattern {
+                NanPattern::CanonicalNan => bits & 0x7fff_ffff == 0x7fc0_0000,
+                NanPattern::ArithmeticNan => bits & 0x7fc0_0000 == 0x7fc0_0000,
+                NanPattern::Value(e) => bits == e.bits,
+            }
+        }
+        (Value::F64(v), WastRetCore::F64(pattern)) => {
+            let bits = v.to_bits();
+            match pattern {
+                NanPattern::CanonicalNan => bits & 0x7fff_ffff_ffff_ffff == 0x7ff8_0000_0000_0000,
+                NanPattern::ArithmeticNan => bits & 0x7ff8_0000_0000_0000 == 0x7ff8_0000_0000_0000,
+                NanPattern::Value(e) => bits == e.bits,
+            }
+        }
+        (
+            _,
+            WastRetCore::I32(_) | WastRetCore::I64(_) | WastRetCore::F32(_) | WastRetCore::F64(_),
+        ) => false,
+        (_, expected) => bail!(Unsupported(format!("result {:?}", expected))),
+    })
+}
+
+fn expect_trap(result: Result<Vec<Value>>, message: &str) -> Result<()> {
+    match result {
+        Ok(values) => bail!("expected trap \"{}\", got {:?}", message, values),
+        Err(err) => {
+            if is_unsupported(&err) {
+                return Err(err);
+            }
+            match err.downcast_ref::<RuntimeError>() {
+                Some(RuntimeError::Trap(trap)) if trap.to_string().contains(message) => Ok(()),
+                _ => bail!("expected trap \"{}\", got: {:#}", message, err),
+            }
+        }
+    }
+}
+
+/// A failed command, or a script that could not be read or parsed.
+struct Failure {
+    /// `<script>:<line>` with the script relative to the directory the scripts are in, or just
+    /// the script if it failed as a whole. This is how `expected_failures.txt` lists it.
+    location: String,
+    message: String,
+}
+
+/// Runs one script. Failures are appended to `failures` with their position in the script.
+fn run_script(root: &Path, path: &Path, failures: &mut Vec<Failure>) -> Counts {
+    let mut counts = Counts::default();
+    let script = path
+        .strip_prefix(root)
+        .unwrap_or(path)
+        .display()
+        .to_string();
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            failures.push(Failure {
+                location: script,
+                message: err.to_string(),
+            });
+            counts.failed += 1;
+            return counts;
+        }
+    };
+    let parsed = ParseBuffer::new(&text).and_then(|buf| {
+        let wast = wast_parser::parse::<Wast>(&buf)?;
+        // the directives borrow the buffer, so run them while it is alive
+        Ok(run_directives(&script, &text, wast, failures))
+    });
+    match parsed {
+        Ok(script_counts) => counts.add(script_counts),
+        Err(mut err) => {
+            err.set_path(path);
+            err.set_text(&text);
+            failures.push(Failure {
+                location: script,
+                message: err.to_string(),
+            });
+            counts.failed += 1;
+        }
+    }
+    counts
+}
+
+fn run_directives(script: &str, text: &str, wast: Wast<'_>, failures: &mut Vec<Failure>) -> Counts {
+    let mut counts = Counts::default();
+    let mut runner = match ScriptRunner::new() {
+        Ok(runner) => runner,
+        Err(err) => {
+            failures.push(Failure {
+                location: script.to_string(),
+                message: format!("{:#}", err),
+            });
+            counts.failed += 1;
+            return counts;
+        }
+    };
+    for directive in wast.directives {
+        let (line, col) = directive.span().linecol_in(text);
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| runner.run(directive)))
+            .unwrap_or_else(|panic| {
+                let message = panic
+                    .downcast_ref::<String>()
+                    .map(String::as_str)
+                    .or_else(|| panic.downcast_ref::<&str>().copied())
+                    .unwrap_or("unknown panic");
+                Err(anyhow!("panicked: {}", message))
+            });
+        match outcome {
+            Ok(Outcome::Passed) => counts.passed += 1,
+            Ok(Outcome::Skipped) => counts.skipped += 1,
+            Err(err) if is_unsupported(&err) => counts.skipped += 1,
+            Err(err) => {
+                failures.push(Failure {
+                    location: format!("{}:{}", script, line + 1),
+                    message: format!("{}:{}:{}: {:#}", script, line + 1, col + 1, err),
+                });
+                counts.failed += 1;
+            }
+        }
+    }
+    counts
+}
+
+fn wast_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "wast"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Groups the scripts under `root` by proposal, with the core scripts under "core".
+fn collect_scripts(root: &Path) -> BTreeMap<String, Vec<PathBuf>> {
+    let mut scripts = BTreeMap::new();
+    scripts.insert("core".to_string(), wast_files(root));
+    let proposals = fs::read_dir(root.join("proposals")).into_iter().flatten();
+    for entry in proposals.filter_map(|entry| entry.ok()) {
+        if entry.path().is_dir() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            scripts.insert(name, wast_files(&entry.path()));
+        }
+    }
+    scripts
+}
+
+/// Runs every script under `root`, printing the counts per proposal.
+fn run_scripts(root: &Path) -> (Counts, Vec<Failure>) {
+    let mut failures = Vec::new();
+    let mut total = Counts::default();
+    println!(
+        "{:<32} {:>8} {:>8} {:>8}",
+        "proposal", "passed", "failed", "skipped"
+    );
+    for (proposal, scripts) in collect_scripts(root) {
+        let mut counts = Counts::default();
+        for script in &scripts {
+            counts.add(run_script(root, script, &mut failures));
+        }
+        println!(
+            "{:<32} {:>8} {:>8} {:>8}",
+            proposal, counts.passed, counts.failed, counts.skipped
+        );
+        total.add(counts);
+    }
+    println!(
+        "{:<32} {:>8} {:>8} {:>8}",
+        "total", total.passed, total.failed, total.skipped
+    );
+    (total, failures)
+}
+
+/// Reads `expected_failures.txt`, one location per line. `#` starts a comment.
+fn expected_failures(path: &Path) -> Result<BTreeSet<String>> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    Ok(text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Replaces the entries of `expected_failures.txt` with `failures`, keeping its header comment.
+fn update_expected_failures(path: &Path, failures: &[Failure]) -> Result<()> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let mut updated: String = text
+        .lines()
+        .take_while(|line| line.starts_with('#'))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    let locations: BTreeSet<&str> = failures
+        .iter()
+        .map(|failure| failure.location.as_str())
+        .collect();
+    for location in locations {
+        updated.push_str(location);
+        updated.push('\n');
+    }
+    fs::write(path, updated).with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[test]
+#[ignore = "needs the spec testsuite, vendored by tests/spec/update-testsuite.sh"]
+fn spec_testsuite() -> Result<()> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/spec");
+    let root = dir.join("testsuite");
+    let revision = fs::read_to_string(root.join("REVISION")).with_context(|| {
+        format!(
+            "the spec testsuite is not vendored in {}, run tests/spec/update-testsuite.sh",
+            root.display()
+        )
+    })?;
+    println!("testsuite revision {}", revision.trim());
+    let expected_path = dir.join("expected_failures.txt");
+    let expected = expected_failures(&expected_path)?;
+    let (total, failures) = run_scripts(&root);
+    assert!(
+        total.passed > 0,
+        "no spec scripts found in {}",
+        root.display()
+    );
+    if std::env::var_os("UPDATE_EXPECTED_FAILURES").is_some() {
+        return update_expected_failures(&expected_path, &failures);
+    }
+
+    let failed: BTreeSet<&str> = failures
+        .iter()
+        .map(|failure| failure.location.as_str())
+        .collect();
+    let unexpected: Vec<&Failure> = failures
+        .iter()
+        .filter(|failure| !expected.contains(&failure.location))
+        .collect();
+    for failure in &unexpected {
+        println!("{}", failure.message);
+    }
+    // entries are removed once they pass, so the list stays an accurate picture
+    let fixed: Vec<&String> = expected
+        .iter()
+        .filter(|location| !failed.contains(location.as_str()))
+        .collect();
+    for location in &fixed {
+        println!(
+            "{}: passes but is listed in expected_failures.txt",
+            location
+        );
+    }
+    assert!(
+        unexpected.is_empty() && fixed.is_empty(),
+        "{} unexpected failures, {} expected failures passed",
+        unexpected.len(),
+        fixed.len()
+    );
+    Ok(())
+}
+
+/// The hand-written scripts are regression tests for this runtime and must pass completely.
+#[test]
+fn wast_scripts() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/wast");
+    let (total, failures) = run_scripts(&root);
+    for failure in &failures {
+        println!("{}", failure.message);
+    }
+    assert!(total.passed > 0, "no scripts found in {}", root.display());
+    assert!(failures.is_empty(), "{} commands failed", failures.len());
+}
@@ -0,0 +1,31 @@
+#!/bin/sh
+# Vendors the WebAssembly spec testsuite at <revision> into tests/spec/testsuite, unmodified,
+# records the commit it resolved to in tests/spec/testsuite/REVISION, and regenerates
+# tests/spec/expected_failures.txt from a run of the new testsuite.
+#
+# usage: tests/spec/update-testsuite.sh <revision>
+set -eu
+
+revision=$1
+dir=$(cd "$(dirname "$0")" && pwd)
+checkout=$(mktemp -d)
+trap 'rm -rf "$checkout"' EXIT
+
+git clone --quiet https://github.com/WebAssembly/testsuite "$checkout"
+git -C "$checkout" checkout --quiet "$revision"
+
+rm -rf "$dir/testsuite"
+mkdir "$dir/testsuite"
+# the runner reads the core scripts and proposals/<name>/*.wast, so only those are copied
+(cd "$checkout" && find . -maxdepth 3 -name '*.wast' \( -path './*.wast' -o -path './proposals/*' \)) |
+    while read -r script; do
+        mkdir -p "$dir/testsuite/$(dirname "$script")"
+        cp "$checkout/$script" "$dir/testsuite/$script"
+    done
+if [ -f "$checkout/LICENSE" ]; then
+    cp "$checkout/LICENSE" "$dir/testsuite/"
+fi
+git -C "$checkout" rev-parse HEAD > "$dir/testsuite/REVISION"
+
+cd "$dir/../.."
+UPDATE_EXPECTED_FAILURES=1 cargo test --test spec spec_testsuite -- --ignored
@@ -1,14 +1,25 @@
-use anyhow::Result;
+use std::{cell::RefCell, io::Write, rc::Rc};
+
+use anyhow::{anyhow, Result};
 use wasm_jit::{
-    parser,
-    runtime::{Runtime, Value},
+    compiler::error::CompileError,
+    disassembler::disassemble,
+    parser::{self, ParseError},
+    runtime::{
+        error::{RuntimeError, Trap, WasmBacktrace},
+        global::Global,
+        linker::Linker,
+        Runtime, Value,
+    },
+    wasi::{self, Wasi},
 };
+use wasmparser::{FuncType, GlobalType, ValType};
 
 #[test]
 fn test_add() -> Result<()> {
     let bytes = include_bytes!("../tests/wasm/add.wasm");
     let modules = parser::parse(bytes)?;
-    let mut runtime = Runtime::init(modules);
+    let mut runtime = Runtime::init(modules)?;
     let result = runtime.call_func_by_name("_start", &[])?;
     assert_eq!(result, vec![Value::I64(30)]);
 
@@ -19,7 +30,7 @@ fn test_add() -> Result<()> {
 fn test_add_with_args() -> Result<()> {
     let bytes = include_bytes!("../tests/wasm/add_with_arg.wasm");
     let modules = parser::parse(bytes)?;
-    let mut runtime = Runtime::init(modules);
+    let mut runtime = Runtime::init(modules)?;
     let result = runtime.call_func_by_name("add", &[Value::I64(10), Value::I64(20)])?;
     assert_eq!(result, vec![Value::I64(30)]);
     let result = runtime.call_func_by_name("add", &[Value::I64(1000), Value::I64(2000)])?;
@@ -36,7 +47,7 @@ fn test_add_with_args() -> Result<()> {
 fn test_call() -> Result<()> {
     let bytes = include_bytes!("../tests/wasm/call.wasm");
     let modules = parser::parse(bytes)?;
-    let mut runtime = Runtime::init(modules);
+    let mut runtime = Runtime::init(modules)?;
     let result = runtime.call_func_by_name("_start", &[])?;
     assert_eq!(result, vec![Value::I64(200)]);
 
@@ -47,7 +58,7 @@ fn test_call() -> Result<()> {
 fn test_fib() -> Result<()> {
     let bytes = include_bytes!("../tests/wasm/fib.wasm");
     let modules = parser::parse(bytes)?;
-    let mut runtime = Runtime::init(modules);
+    let mut runtime = Runtime::init(modules)?;
     let start = std::time::Instant::now();
     let result = runtime.call_func_by_name("fib", &[Value::I64(30)])?;
     let elapsed = start.elapsed();
@@ -61,7 +72,7 @@ fn test_fib() -> Result<()> {
 fn test_eq() -> Result<()> {
     let bytes = include_bytes!("../tests/wasm/eq.wasm");
     let modules = parser::parse(bytes)?;
-    let mut runtime = Runtime::init(modules);
+    let mut runtime = Runtime::init(modules)?;
     let result = runtime.call_func_by_name("i64eq", &[Value::I64(10), Value::I64(10)])?;
     assert_eq!(result, vec![Value::I32(1)]);
     let result = runtime.call_func_by_name("i64eq", &[Value::I64(10), Value::I64(20)])?;
@@ -78,7 +89,7 @@ fn test_eq() -> Result<()> {
 fn test_sub() -> Result<()> {
     let bytes = include_bytes!("../tests/wasm/sub.wasm");
     let modules = parser::parse(bytes)?;
-    let mut runtime = Runtime::init(modules);
+    let mut runtime = Runtime::init(modules)?;
     let result = runtime.call_func_by_name("i64sub", &[Value::I64(20), Value::I64(10)])?;
     assert_eq!(result, vec![Value::I64(10)]);
     let result = runtime.call_func_by_name("i64sub", &[Value::I64(10), Value::I64(20)])?;
@@ -90,3 +101,1792 @@ fn test_sub() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_locals() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/locals.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name("swap_sub", &[Value::I64(10), Value::I64(3)])?;
+    assert_eq!(result, vec![Value::I64(-7)]);
+    let result = runtime.call_func_by_name("tee", &[Value::I32(10)])?;
+    assert_eq!(result, vec![Value::I32(30)]);
+    let result = runtime.call_func_by_name("zero_init", &[])?;
+    assert_eq!(result, vec![Value::I64(0)]);
+    let result = runtime.call_func_by_name("call_with_locals", &[Value::I64(10)])?;
+    assert_eq!(result, vec![Value::I64(32)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_many_locals() -> Result<()> {
+    let many = "i64 ".repeat(100);
+    let wat = format!(
+        r#"
+        (module
+          (func $dirty (local i64 i64 i64 i64 i64 i64 i64 i64 i64 i64 i64 i64)
+            (local.set 0 (i64.const -1)) (local.set 5 (i64.const -1))
+            (local.set 11 (i64.const -1)))
+          (func $sum (result i64) (local i64 i64 i64 i64 i64 i64 i64 i64 i64 i64 i64 i64)
+            (i64.add (i64.add (local.get 0) (local.get 5)) (local.get 11)))
+          (func $many (result i64) (local {many})
+            (local.set 98 (i64.const 3))
+            (i64.add (local.get 98) (local.get 99)))
+          (func (export "run") (result i64)
+            (call $dirty)
+            (i64.add (call $sum) (call $many))))
+        "#
+    );
+    let bytes = parser::wat_to_wasm(wat.as_bytes())?;
+    let modules = parser::parse(&bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    runtime.enable_listing();
+    // $sum reuses the frame of $dirty, so its locals are only zero if the prologue cleared them
+    let result = runtime.call_func_by_name("run", &[])?;
+    assert_eq!(result, vec![Value::I64(3)]);
+    // the locals are cleared in a loop, so the prologue does not grow with their number,
+    // apart from wider immediates for the frame size
+    let prologue_len = |func_index: u32| {
+        let listing = runtime
+            .listings()
+            .iter()
+            .find(|listing| listing.func_index == func_index)
+            .expect("listing");
+        listing.ops[1].0
+    };
+    assert!(prologue_len(2) <= prologue_len(1) + 8);
+
+    Ok(())
+}
+
+#[test]
+fn test_control_flow() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/control.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name("sum", &[Value::I64(100)])?;
+    assert_eq!(result, vec![Value::I64(5050)]);
+    let result = runtime.call_func_by_name("count_down", &[Value::I32(12)])?;
+    assert_eq!(result, vec![Value::I32(12)]);
+    let result = runtime.call_func_by_name("block_result", &[Value::I64(0)])?;
+    assert_eq!(result, vec![Value::I64(11)]);
+    let result = runtime.call_func_by_name("block_result", &[Value::I64(3)])?;
+    assert_eq!(result, vec![Value::I64(12)]);
+    for (x, expected) in [(0, 100), (1, 101), (2, 102), (3, 999), (-1, 999)] {
+        let result = runtime.call_func_by_name("switch", &[Value::I32(x)])?;
+        assert_eq!(result, vec![Value::I64(expected)]);
+    }
+    let result = runtime.call_func_by_name("early_return", &[Value::I64(0)])?;
+    assert_eq!(result, vec![Value::I64(7)]);
+    let result = runtime.call_func_by_name("early_return", &[Value::I64(4)])?;
+    assert_eq!(result, vec![Value::I64(5)]);
+    let result = runtime.call_func_by_name("nested", &[Value::I64(12)])?;
+    assert_eq!(result, vec![Value::I64(144)]);
+    let result = runtime.call_func_by_name("dead_code", &[])?;
+    assert_eq!(result, vec![Value::I64(5)]);
+    let result = runtime.call_func_by_name("if_keeps_stack", &[Value::I32(0)])?;
+    assert_eq!(result, vec![Value::I64(42)]);
+    let result = runtime.call_func_by_name("if_keeps_stack", &[Value::I32(1)])?;
+    assert_eq!(result, vec![Value::I64(42)]);
+
+    Ok(())
+}
+
+fn assert_trap<T: std::fmt::Debug>(result: Result<T>, trap: Trap) {
+    let err = result.expect_err("expected a trap");
+    match err.downcast_ref::<RuntimeError>() {
+        Some(RuntimeError::Trap(actual)) => assert_eq!(*actual, trap),
+        _ => panic!("expected {:?}, got {:?}", trap, err),
+    }
+}
+
+#[test]
+fn test_memory() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/memory.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name("load_i64", &[Value::I32(16)])?;
+    assert_eq!(result, vec![Value::I64(0x0807060504030201)]);
+    let result = runtime.call_func_by_name("load_i32", &[Value::I32(16)])?;
+    assert_eq!(result, vec![Value::I32(0x08070605)]);
+    let result = runtime.call_func_by_name("load_narrow", &[])?;
+    assert_eq!(result, vec![Value::I64(-1 + 255 - 257 + 0xfeff)]);
+    let result = runtime.call_func_by_name("store_load", &[Value::I32(100), Value::I64(-5)])?;
+    assert_eq!(result, vec![Value::I64(-5 + 0x567834)]);
+    let result = runtime.call_func_by_name("size", &[])?;
+    assert_eq!(result, vec![Value::I32(1)]);
+    let result = runtime.call_func_by_name("grow", &[Value::I32(2)])?;
+    assert_eq!(result, vec![Value::I32(1)]);
+    let result = runtime.call_func_by_name("grow", &[Value::I32(1)])?;
+    assert_eq!(result, vec![Value::I32(-1)]);
+    let result = runtime.call_func_by_name("size", &[])?;
+    assert_eq!(result, vec![Value::I32(3)]);
+    let result = runtime.call_func_by_name("load_i32", &[Value::I32(0x2fff8)])?;
+    assert_eq!(result, vec![Value::I32(0)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_memory_trap() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/memory.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name("oob_load", &[Value::I32(0x10000)]);
+    assert_trap(result, Trap::MemoryOutOfBounds);
+    let result = runtime.call_func_by_name("oob_load", &[Value::I32(-1)]);
+    assert_trap(result, Trap::MemoryOutOfBounds);
+    let result = runtime.call_func_by_name("oob_store", &[Value::I32(1)]);
+    assert_trap(result, Trap::MemoryOutOfBounds);
+    let result = runtime.call_func_by_name("trap_through_call", &[Value::I32(0x10000)]);
+    assert_trap(result, Trap::MemoryOutOfBounds);
+    let result = runtime.call_func_by_name("unreachable", &[]);
+    assert_trap(result, Trap::Unreachable);
+    let result = runtime.call_func_by_name("oob_load", &[Value::I32(0xfffc)])?;
+    assert_eq!(result, vec![Value::I32(0)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_stack_overflow() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/fib.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name("fib", &[Value::I64(1_000_000)]);
+    assert_trap(result, Trap::StackOverflow);
+    let result = runtime.call_func_by_name("fib", &[Value::I64(20)])?;
+    assert_eq!(result, vec![Value::I64(6765)]);
+
+    let bytes = include_bytes!("../tests/wasm/recursion.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name("depth", &[Value::I64(100)])?;
+    assert_eq!(result, vec![Value::I64(100)]);
+    let result = runtime.call_func_by_name("depth", &[Value::I64(1_000_000)]);
+    assert_trap(result, Trap::StackOverflow);
+    let result = runtime.call_func_by_name("ping", &[Value::I64(1_000_000)]);
+    assert_trap(result, Trap::StackOverflow);
+    let result = runtime.call_func_by_name("big_frames", &[Value::I64(4)])?;
+    assert_eq!(result, vec![Value::I64(0)]);
+    let result = runtime.call_func_by_name("big_frames", &[Value::I64(1_000_000)]);
+    assert_trap(result, Trap::StackOverflow);
+
+    Ok(())
+}
+
+#[test]
+fn test_host_func() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/import.wasm");
+    let logs = Rc::new(RefCell::new(Vec::new()));
+    let mut linker = Linker::new();
+    linker
+        .func(
+            "env",
+            "add_one",
+            FuncType::new([ValType::I64], [ValType::I64]),
+            |_, args| match args {
+                [Value::I64(n)] => Ok(vec![Value::I64(n + 1)]),
+                _ => unreachable!(),
+            },
+        )
+        .func(
+            "env",
+            "log",
+            FuncType::new([ValType::I32, ValType::I32], []),
+            {
+                let logs = logs.clone();
+                move |caller, args| {
+                    let [Value::I32(ptr), Value::I32(len)] = args else {
+                        unreachable!()
+                    };
+                    let memory = caller.memory().expect("memory is not defined");
+                    let bytes = &memory.data()[*ptr as usize..(*ptr + *len) as usize];
+                    logs.borrow_mut()
+                        .push(String::from_utf8_lossy(bytes).into_owned());
+                    Ok(vec![])
+                }
+            },
+        )
+        .func("env", "fail", FuncType::new([], []), |_, _| {
+            Err(anyhow!("host failure"))
+        });
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::instantiate(modules, &linker)?;
+    let result = runtime.call_func_by_name("call_add_one", &[Value::I64(5)])?;
+    assert_eq!(result, vec![Value::I64(16)]);
+    let result = runtime.call_func_by_name("add_one", &[Value::I64(41)])?;
+    assert_eq!(result, vec![Value::I64(42)]);
+    runtime.call_func_by_name("greet", &[])?;
+    assert_eq!(*logs.borrow(), vec!["hello".to_string()]);
+    let err = runtime.call_func_by_name("call_fail", &[]).unwrap_err();
+    assert_eq!(err.to_string(), "host failure");
+
+    let modules = parser::parse(bytes)?;
+    let err = Runtime::init(modules).err().expect("imports are missing");
+    assert!(matches!(
+        err.downcast_ref::<RuntimeError>(),
+        Some(RuntimeError::UnknownImport(_))
+    ));
+
+    let mut linker = linker.clone();
+    linker.func("env", "fail", FuncType::new([ValType::I32], []), |_, _| {
+        Ok(vec![])
+    });
+    let modules = parser::parse(bytes)?;
+    let err = Runtime::instantiate(modules, &linker)
+        .err()
+        .expect("import type does not match");
+    assert!(matches!(
+        err.downcast_ref::<RuntimeError>(),
+        Some(RuntimeError::IncompatibleImportType(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_host_func_panic() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/import.wasm");
+    let mut linker = Linker::new();
+    linker
+        .func(
+            "env",
+            "add_one",
+            FuncType::new([ValType::I64], [ValType::I64]),
+            |_, args| match args {
+                [Value::I64(n)] => Ok(vec![Value::I64(n + 1)]),
+                _ => unreachable!(),
+            },
+        )
+        .func(
+            "env",
+            "log",
+            FuncType::new([ValType::I32, ValType::I32], []),
+            |_, _| Ok(vec![]),
+        )
+        .func("env", "fail", FuncType::new([], []), |_, _| {
+            panic!("host panic")
+        });
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::instantiate(modules, &linker)?;
+    // the panic unwinds to the embedder once the JIT frames are gone
+    let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        runtime.call_func_by_name("call_fail", &[])
+    }))
+    .expect_err("host function panicked");
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"host panic"));
+    // the runtime stays usable
+    let result = runtime.call_func_by_name("call_add_one", &[Value::I64(5)])?;
+    assert_eq!(result, vec![Value::I64(16)]);
+
+    Ok(())
+}
+
+/// A stdout that can be inspected after the runtime has taken ownership of it.
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuffer {
+    fn take(&self) -> String {
+        String::from_utf8(std::mem::take(&mut *self.0.borrow_mut())).unwrap()
+    }
+}
+
+#[test]
+fn test_wasi_hello() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/wasi_hello.wasm");
+    let stdout = SharedBuffer::default();
+    let mut linker = Linker::new();
+    Wasi::new()
+        .stdout(stdout.clone())
+        .add_to_linker(&mut linker);
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::instantiate(modules, &linker)?;
+    let err = runtime.call_func_by_name("_start", &[]).unwrap_err();
+    assert_eq!(err.downcast_ref::<wasi::Exit>(), Some(&wasi::Exit(0)));
+    assert_eq!(stdout.take(), "Hello, world!\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_wasi() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/wasi.wasm");
+    let stdout = SharedBuffer::default();
+    let mut linker = Linker::new();
+    Wasi::new()
+        .args(["prog", "-v", "input.txt"])
+        .env([("HOME", "/home/wasm"), ("LANG", "C")])
+        .stdin(&b"ping"[..])
+        .stdout(stdout.clone())
+        .add_to_linker(&mut linker);
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::instantiate(modules, &linker)?;
+
+    let result = runtime.call_func_by_name("args", &[])?;
+    assert_eq!(result, vec![Value::I32(3)]);
+    assert_eq!(stdout.take(), "prog\0-v\0input.txt\0");
+    let result = runtime.call_func_by_name("environ", &[])?;
+    assert_eq!(result, vec![Value::I32(2)]);
+    assert_eq!(stdout.take(), "HOME=/home/wasm\0LANG=C\0");
+    let result = runtime.call_func_by_name("echo", &[])?;
+    assert_eq!(result, vec![Value::I32(0)]);
+    assert_eq!(stdout.take(), "ping");
+
+    // EBADF and EFAULT are reported to the program rather than trapping.
+    let result = runtime.call_func_by_name("write_bad_fd", &[])?;
+    assert_eq!(result, vec![Value::I32(8)]);
+    let result = runtime.call_func_by_name("write_oob", &[])?;
+    assert_eq!(result, vec![Value::I32(21)]);
+
+    let Value::I64(realtime) = runtime.call_func_by_name("clock", &[Value::I32(0)])?[0] else {
+        unreachable!()
+    };
+    assert!(realtime > 1_600_000_000 * 1_000_000_000);
+    let Value::I64(monotonic) = runtime.call_func_by_name("clock", &[Value::I32(1)])?[0] else {
+        unreachable!()
+    };
+    assert!(monotonic > 0);
+    let result = runtime.call_func_by_name("random", &[])?;
+    assert_eq!(result, vec![Value::I32(0)]);
+
+    let err = runtime
+        .call_func_by_name("exit", &[Value::I32(3)])
+        .unwrap_err();
+    assert_eq!(err.downcast_ref::<wasi::Exit>(), Some(&wasi::Exit(3)));
+
+    Ok(())
+}
+
+#[test]
+fn test_wasi_guest_pointers() -> Result<()> {
+    let bytes = parser::wat_to_wasm(
+        br#"
+        (module
+          (import "wasi_snapshot_preview1" "fd_write"
+            (func $fd_write (param i32 i32 i32 i32) (result i32)))
+          (import "wasi_snapshot_preview1" "fd_read"
+            (func $fd_read (param i32 i32 i32 i32) (result i32)))
+          (import "wasi_snapshot_preview1" "args_get"
+            (func $args_get (param i32 i32) (result i32)))
+          (memory 1)
+          ;; two iovecs whose lengths add up to more than u32::MAX
+          (data (i32.const 0) "\10\00\00\00\01\00\00\00\10\00\00\00\ff\ff\ff\ff")
+          (func (export "write") (param i32 i32) (result i32)
+            (call $fd_write (i32.const 1) (local.get 0) (local.get 1) (i32.const 32)))
+          (func (export "read") (param i32 i32) (result i32)
+            (call $fd_read (i32.const 0) (local.get 0) (local.get 1) (i32.const 32)))
+          (func (export "args") (param i32 i32) (result i32)
+            (call $args_get (local.get 0) (local.get 1))))
+        "#,
+    )?;
+    let stdout = SharedBuffer::default();
+    let mut linker = Linker::new();
+    Wasi::new()
+        .args(["prog", "arg"])
+        .stdin(&b"ping"[..])
+        .stdout(stdout.clone())
+        .add_to_linker(&mut linker);
+    let modules = parser::parse(&bytes)?;
+    let mut runtime = Runtime::instantiate(modules, &linker)?;
+    // addresses that would wrap around are EFAULT instead of overflowing
+    let mut call = |name: &str, ptr: u32, len: u32| -> Result<Vec<Value>> {
+        runtime.call_func_by_name(name, &[Value::I32(ptr as i32), Value::I32(len as i32)])
+    };
+    assert_eq!(call("write", 0xffff_fff8, 2)?, vec![Value::I32(21)]);
+    assert_eq!(call("write", u32::MAX, u32::MAX)?, vec![Value::I32(21)]);
+    assert_eq!(call("read", 0xffff_fffc, 1)?, vec![Value::I32(21)]);
+    assert_eq!(call("args", 0xffff_fffc, 0)?, vec![Value::I32(21)]);
+    assert_eq!(call("args", 100, 0xffff_fffe)?, vec![Value::I32(21)]);
+    // a total that does not fit in the u32 result is EOVERFLOW
+    assert_eq!(call("write", 0, 2)?, vec![Value::I32(61)]);
+    assert_eq!(stdout.take(), "\0");
+
+    Ok(())
+}
+
+#[test]
+fn test_global() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/global.wasm");
+    let counter = Global::new(
+        GlobalType {
+            content_type: ValType::I64,
+            mutable: true,
+            shared: false,
+        },
+        Value::I64(0),
+    )?;
+    let base = Global::new(
+        GlobalType {
+            content_type: ValType::I32,
+            mutable: false,
+            shared: false,
+        },
+        Value::I32(64),
+    )?;
+    let mut linker = Linker::new();
+    linker
+        .global("env", "counter", counter.clone())
+        .global("env", "base", base.clone());
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::instantiate(modules, &linker)?;
+
+    let result = runtime.call_func_by_name("get_g", &[])?;
+    assert_eq!(result, vec![Value::I64(10)]);
+    let result = runtime.call_func_by_name("add_g", &[Value::I64(5)])?;
+    assert_eq!(result, vec![Value::I64(15)]);
+    assert_eq!(runtime.get_global("g")?, Value::I64(15));
+    runtime.call_func_by_name("set_imm", &[])?;
+    assert_eq!(runtime.get_global("g")?, Value::I64(99));
+    runtime.set_global("g", Value::I64(-1))?;
+    let result = runtime.call_func_by_name("get_g", &[])?;
+    assert_eq!(result, vec![Value::I64(-1)]);
+
+    runtime.call_func_by_name("bump", &[])?;
+    runtime.call_func_by_name("bump", &[])?;
+    assert_eq!(counter.get(), Value::I64(2));
+    assert_eq!(runtime.get_global("counter")?, Value::I64(2));
+
+    assert_eq!(runtime.get_global("derived")?, Value::I32(64));
+    assert_eq!(runtime.get_global("pi")?, Value::F64(3.5));
+    let result = runtime.call_func_by_name("load_base", &[])?;
+    assert_eq!(result, vec![Value::I32(0x2a)]);
+    let result = runtime.call_func_by_name("derived_plus", &[])?;
+    assert_eq!(result, vec![Value::I32(65)]);
+
+    let err = runtime.set_global("pi", Value::F64(1.0)).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RuntimeError>(),
+        Some(RuntimeError::ImmutableGlobal(_))
+    ));
+    let err = runtime.set_global("g", Value::I32(1)).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RuntimeError>(),
+        Some(RuntimeError::GlobalTypeMismatch(_))
+    ));
+
+    let mut linker = Linker::new();
+    linker
+        .global("env", "counter", base)
+        .global("env", "base", counter);
+    let modules = parser::parse(bytes)?;
+    let err = Runtime::instantiate(modules, &linker)
+        .err()
+        .expect("import type does not match");
+    assert!(matches!(
+        err.downcast_ref::<RuntimeError>(),
+        Some(RuntimeError::IncompatibleImportType(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_call_indirect() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/table.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name("call", &[Value::I64(10), Value::I32(0)])?;
+    assert_eq!(result, vec![Value::I64(20)]);
+    let result = runtime.call_func_by_name("call", &[Value::I64(10), Value::I32(1)])?;
+    assert_eq!(result, vec![Value::I64(110)]);
+    // structurally equal types match
+    let result = runtime.call_func_by_name("call", &[Value::I64(10), Value::I32(4)])?;
+    assert_eq!(result, vec![Value::I64(11)]);
+    let result = runtime.call_func_by_name("call_seven", &[Value::I32(2)])?;
+    assert_eq!(result, vec![Value::I32(7)]);
+    let result = runtime.call_func_by_name("call_imm", &[])?;
+    assert_eq!(result, vec![Value::I64(6)]);
+    let result = runtime.call_func_by_name("call_keep", &[Value::I64(21)])?;
+    assert_eq!(result, vec![Value::I64(63)]);
+
+    let result = runtime.call_func_by_name("call", &[Value::I64(10), Value::I32(2)]);
+    assert_trap(result, Trap::IndirectCallTypeMismatch);
+    let result = runtime.call_func_by_name("call", &[Value::I64(10), Value::I32(3)]);
+    assert_trap(result, Trap::UninitializedElement);
+    let result = runtime.call_func_by_name("call", &[Value::I64(10), Value::I32(5)]);
+    assert_trap(result, Trap::UninitializedElement);
+    let result = runtime.call_func_by_name("call", &[Value::I64(10), Value::I32(6)]);
+    assert_trap(result, Trap::UndefinedElement);
+    let result = runtime.call_func_by_name("call", &[Value::I64(10), Value::I32(-1)]);
+    assert_trap(result, Trap::UndefinedElement);
+
+    let bytes = include_bytes!("../tests/wasm/table_oob.wasm");
+    let modules = parser::parse(bytes)?;
+    let err = Runtime::init(modules)
+        .err()
+        .expect("segment is out of bounds");
+    assert!(matches!(
+        err.downcast_ref::<RuntimeError>(),
+        Some(RuntimeError::Trap(Trap::TableOutOfBounds))
+    ));
+
+    Ok(())
+}
+
+fn f32_bits(values: &[Value]) -> Vec<u32> {
+    values
+        .iter()
+        .map(|value| match value {
+            Value::F32(v) => v.to_bits(),
+            _ => panic!("expected f32, got {:?}", value),
+        })
+        .collect()
+}
+
+fn f64_bits(values: &[Value]) -> Vec<u64> {
+    values
+        .iter()
+        .map(|value| match value {
+            Value::F64(v) => v.to_bits(),
+            _ => panic!("expected f64, got {:?}", value),
+        })
+        .collect()
+}
+
+#[test]
+fn test_float_arith() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/float.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name("f64_arith", &[Value::F64(7.5), Value::F64(2.5)])?;
+    assert_eq!(
+        result,
+        vec![
+            Value::F64(10.0),
+            Value::F64(5.0),
+            Value::F64(18.75),
+            Value::F64(3.0)
+        ]
+    );
+    let result = runtime.call_func_by_name("f32_arith", &[Value::F32(1.5), Value::F32(-0.5)])?;
+    assert_eq!(
+        result,
+        vec![
+            Value::F32(1.0),
+            Value::F32(2.0),
+            Value::F32(-0.75),
+            Value::F32(-3.0)
+        ]
+    );
+    let result = runtime.call_func_by_name("f64_arith", &[Value::F64(1.0), Value::F64(0.0)])?;
+    assert_eq!(result[3], Value::F64(f64::INFINITY));
+
+    let result = runtime.call_func_by_name("f64_unary", &[Value::F64(2.5)])?;
+    assert_eq!(
+        result,
+        vec![
+            Value::F64(2.5_f64.sqrt()),
+            Value::F64(3.0),
+            Value::F64(2.0),
+            Value::F64(2.0),
+            Value::F64(2.0),
+            Value::F64(2.5),
+            Value::F64(-2.5)
+        ]
+    );
+    let result = runtime.call_func_by_name("f32_unary", &[Value::F32(-3.5)])?;
+    assert!(matches!(result[0], Value::F32(v) if v.is_nan()));
+    assert_eq!(
+        result[1..],
+        [
+            Value::F32(-3.0),
+            Value::F32(-4.0),
+            Value::F32(-3.0),
+            Value::F32(-4.0),
+            Value::F32(3.5),
+            Value::F32(3.5)
+        ]
+    );
+    // rounding keeps the sign of zero
+    let result = runtime.call_func_by_name("f64_unary", &[Value::F64(-0.5)])?;
+    assert_eq!(
+        f64_bits(&result[1..5]),
+        vec![
+            (-0.0_f64).to_bits(),
+            (-1.0_f64).to_bits(),
+            (-0.0_f64).to_bits(),
+            (-0.0_f64).to_bits()
+        ]
+    );
+    // abs and neg only touch the sign bit, even for NaN
+    let nan = f64::from_bits(0x7ff0_0000_0000_0001);
+    let result = runtime.call_func_by_name("f64_unary", &[Value::F64(-nan)])?;
+    assert_eq!(f64_bits(&result[5..]), vec![nan.to_bits(), nan.to_bits()]);
+    let result = runtime.call_func_by_name("f32_copysign", &[Value::F32(2.0), Value::F32(-0.0)])?;
+    assert_eq!(result, vec![Value::F32(-2.0)]);
+    let result = runtime.call_func_by_name("f64_copysign", &[Value::F64(-2.0), Value::F64(1.0)])?;
+    assert_eq!(result, vec![Value::F64(2.0)]);
+    let result = runtime.call_func_by_name("consts", &[])?;
+    assert_eq!(
+        result,
+        vec![Value::F64(-1.5), Value::F32(2.5), Value::F64(0.75)]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_float_min_max() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/float.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name("f64_min_max", &[Value::F64(1.0), Value::F64(-2.0)])?;
+    assert_eq!(result, vec![Value::F64(-2.0), Value::F64(1.0)]);
+    let result = runtime.call_func_by_name("f32_min_max", &[Value::F32(3.0), Value::F32(3.0)])?;
+    assert_eq!(result, vec![Value::F32(3.0), Value::F32(3.0)]);
+    for (a, b) in [(0.0, -0.0), (-0.0, 0.0)] {
+        let result = runtime.call_func_by_name("f64_min_max", &[Value::F64(a), Value::F64(b)])?;
+        assert_eq!(
+            f64_bits(&result),
+            vec![(-0.0_f64).to_bits(), 0.0_f64.to_bits()]
+        );
+        let result = runtime
+            .call_func_by_name("f32_min_max", &[Value::F32(a as f32), Value::F32(b as f32)])?;
+        assert_eq!(
+            f32_bits(&result),
+            vec![(-0.0_f32).to_bits(), 0.0_f32.to_bits()]
+        );
+    }
+    for (a, b) in [(f64::NAN, 1.0), (1.0, f64::NAN), (f64::NAN, f64::NAN)] {
+        let result = runtime.call_func_by_name("f64_min_max", &[Value::F64(a), Value::F64(b)])?;
+        assert!(result
+            .iter()
+            .all(|v| matches!(v, Value::F64(v) if v.is_nan())));
+        let result = runtime
+            .call_func_by_name("f32_min_max", &[Value::F32(a as f32), Value::F32(b as f32)])?;
+        assert!(result
+            .iter()
+            .all(|v| matches!(v, Value::F32(v) if v.is_nan())));
+    }
+    let result = runtime.call_func_by_name(
+        "f64_min_max",
+        &[Value::F64(f64::NEG_INFINITY), Value::F64(f64::INFINITY)],
+    )?;
+    assert_eq!(
+        result,
+        vec![Value::F64(f64::NEG_INFINITY), Value::F64(f64::INFINITY)]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_float_cmp() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/float.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let i32s = |v: [i32; 6]| v.map(Value::I32).to_vec();
+    // eq, ne, lt, gt, le, ge
+    let result = runtime.call_func_by_name("f64_cmp", &[Value::F64(1.0), Value::F64(2.0)])?;
+    assert_eq!(result, i32s([0, 1, 1, 0, 1, 0]));
+    let result = runtime.call_func_by_name("f64_cmp", &[Value::F64(2.0), Value::F64(1.0)])?;
+    assert_eq!(result, i32s([0, 1, 0, 1, 0, 1]));
+    let result = runtime.call_func_by_name("f64_cmp", &[Value::F64(-0.0), Value::F64(0.0)])?;
+    assert_eq!(result, i32s([1, 0, 0, 0, 1, 1]));
+    let result = runtime.call_func_by_name("f64_cmp", &[Value::F64(f64::NAN), Value::F64(1.0)])?;
+    assert_eq!(result, i32s([0, 1, 0, 0, 0, 0]));
+    let result = runtime.call_func_by_name("f32_cmp", &[Value::F32(1.0), Value::F32(f32::NAN)])?;
+    assert_eq!(result, i32s([0, 1, 0, 0, 0, 0]));
+    let result = runtime.call_func_by_name("f32_cmp", &[Value::F32(-1.0), Value::F32(-1.0)])?;
+    assert_eq!(result, i32s([1, 0, 0, 0, 1, 1]));
+
+    Ok(())
+}
+
+#[test]
+fn test_float_registers() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/float.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name("many_live", &[Value::F64(0.5)])?;
+    assert_eq!(result, vec![Value::F64(22.5)]);
+    let result = runtime.call_func_by_name("mixed", &[Value::F64(2.0), Value::I32(1)])?;
+    assert_eq!(result, vec![Value::F64(3.0)]);
+    let result = runtime.call_func_by_name("mixed", &[Value::F64(2.0), Value::I32(0)])?;
+    assert_eq!(result, vec![Value::F64(2.0)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_int_arith() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/int.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name("i32_arith", &[Value::I32(-6), Value::I32(0b1011)])?;
+    assert_eq!(
+        result,
+        vec![
+            Value::I32(-66),
+            Value::I32(-6 & 0b1011),
+            Value::I32(-6 | 0b1011),
+            Value::I32(-6 ^ 0b1011),
+            Value::I32(89),
+            Value::I32(-18)
+        ]
+    );
+    let result = runtime.call_func_by_name(
+        "i64_arith",
+        &[Value::I64(i64::MAX), Value::I64(0x0f0f_0000_0000)],
+    )?;
+    assert_eq!(
+        result,
+        vec![
+            Value::I64(i64::MAX.wrapping_mul(0x0f0f_0000_0000)),
+            Value::I64(0x0f0f_0000_0000),
+            Value::I64(i64::MAX),
+            Value::I64(i64::MAX ^ 0x0f0f_0000_0000),
+            Value::I64(100 - 0x0f0f_0000_0000)
+        ]
+    );
+    let result = runtime.call_func_by_name("const_fold", &[])?;
+    assert_eq!(
+        result,
+        vec![
+            Value::I32(0),
+            Value::I32(i32::MAX),
+            Value::I32(3),
+            Value::I64(-4),
+            Value::I32(31),
+            Value::I32(1)
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_int_div() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/int.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name("i32_div", &[Value::I32(-7), Value::I32(2)])?;
+    assert_eq!(
+        result,
+        vec![
+            Value::I32(-3),
+            Value::I32((-7_i32 as u32 / 2) as i32),
+            Value::I32(-1),
+            Value::I32(1)
+        ]
+    );
+    let result = runtime.call_func_by_name("i64_div", &[Value::I64(-7), Value::I64(2)])?;
+    assert_eq!(
+        result,
+        vec![
+            Value::I64(-3),
+            Value::I64((-7_i64 as u64 / 2) as i64),
+            Value::I64(-1),
+            Value::I64(1)
+        ]
+    );
+    let result = runtime.call_func_by_name("i32_div", &[Value::I32(1), Value::I32(0)]);
+    assert_trap(result, Trap::IntegerDivideByZero);
+    let result = runtime.call_func_by_name("i64_div", &[Value::I64(1), Value::I64(0)]);
+    assert_trap(result, Trap::IntegerDivideByZero);
+    let result = runtime.call_func_by_name("i32_div_s", &[Value::I32(i32::MIN), Value::I32(-1)]);
+    assert_trap(result, Trap::IntegerOverflow);
+    let result = runtime.call_func_by_name("i64_div_s", &[Value::I64(i64::MIN), Value::I64(-1)]);
+    assert_trap(result, Trap::IntegerOverflow);
+    let result = runtime.call_func_by_name("i32_rem_s", &[Value::I32(i32::MIN), Value::I32(-1)])?;
+    assert_eq!(result, vec![Value::I32(0)]);
+    let result = runtime.call_func_by_name("i64_div_s", &[Value::I64(-9), Value::I64(-1)])?;
+    assert_eq!(result, vec![Value::I64(9)]);
+    let result = runtime.call_func_by_name("const_div_zero", &[]);
+    assert_trap(result, Trap::IntegerDivideByZero);
+
+    Ok(())
+}
+
+#[test]
+fn test_int_shift_and_bits() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/int.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result =
+        runtime.call_func_by_name("i32_shift", &[Value::I32(-0x7fff_fff0), Value::I32(36)])?;
+    let n = -0x7fff_fff0_i32;
+    assert_eq!(
+        result,
+        vec![
+            Value::I32(n << 4),
+            Value::I32(n >> 4),
+            Value::I32((n as u32 >> 4) as i32),
+            Value::I32(n.rotate_left(4)),
+            Value::I32(n.rotate_right(4)),
+            Value::I32(n << 1)
+        ]
+    );
+    let n = -0x7fff_ffff_ffff_fff0_i64;
+    let result = runtime.call_func_by_name("i64_shift", &[Value::I64(n), Value::I64(68)])?;
+    assert_eq!(
+        result,
+        vec![
+            Value::I64(n << 4),
+            Value::I64(n >> 4),
+            Value::I64((n as u64 >> 4) as i64),
+            Value::I64(n.rotate_left(4)),
+            Value::I64(n.rotate_right(4)),
+            Value::I64((n as u64 >> 4) as i64)
+        ]
+    );
+    let result = runtime.call_func_by_name("i32_bits", &[Value::I32(0x00f0_0000)])?;
+    assert_eq!(
+        result,
+        vec![Value::I32(8), Value::I32(20), Value::I32(4), Value::I32(0)]
+    );
+    let result = runtime.call_func_by_name("i32_bits", &[Value::I32(0)])?;
+    assert_eq!(
+        result,
+        vec![Value::I32(32), Value::I32(32), Value::I32(0), Value::I32(1)]
+    );
+    let result = runtime.call_func_by_name("i64_bits", &[Value::I64(0x0100_0000_0000)])?;
+    assert_eq!(
+        result,
+        vec![Value::I64(23), Value::I64(40), Value::I64(1), Value::I32(0)]
+    );
+    let result = runtime.call_func_by_name("i64_bits", &[Value::I64(0)])?;
+    assert_eq!(
+        result,
+        vec![Value::I64(64), Value::I64(64), Value::I64(0), Value::I32(1)]
+    );
+    let args: Vec<Value> = [1, 2, 3, 4, 5, 100, 7]
+        .into_iter()
+        .map(Value::I32)
+        .collect();
+    let result = runtime.call_func_by_name("register_pressure", &args)?;
+    assert_eq!(
+        result,
+        vec![Value::I32(1 + 2 + 3 + 4 + 5 + 100 / 7 + (5 << 3))]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_int_cmp() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/cmp.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name("i32_cmp", &[Value::I32(-1), Value::I32(1)])?;
+    assert_eq!(result, [1, 1, 0, 0, 1, 1, 1].map(Value::I32).to_vec());
+    let result = runtime.call_func_by_name("i32_cmp", &[Value::I32(3), Value::I32(3)])?;
+    assert_eq!(result, [0, 0, 0, 0, 0, 1, 1].map(Value::I32).to_vec());
+    let result = runtime.call_func_by_name("i32_cmp_imm", &[Value::I32(7)])?;
+    assert_eq!(result, [0, 1, 1, 0, 1, 1].map(Value::I32).to_vec());
+    let result = runtime.call_func_by_name("i32_cmp_imm", &[Value::I32(5)])?;
+    assert_eq!(result, [0, 0, 1, 1, 1, 1].map(Value::I32).to_vec());
+    let result = runtime.call_func_by_name("i64_cmp", &[Value::I64(i64::MIN), Value::I64(1)])?;
+    assert_eq!(result, [1, 1, 0, 0, 0, 1, 1].map(Value::I32).to_vec());
+    let result = runtime.call_func_by_name("eqz_fused", &[Value::I32(1), Value::I32(2)])?;
+    assert_eq!(result, vec![Value::I32(0), Value::I32(10)]);
+    let result = runtime.call_func_by_name("eqz_fused", &[Value::I32(2), Value::I32(1)])?;
+    assert_eq!(result, vec![Value::I32(1), Value::I32(20)]);
+    let result = runtime.call_func_by_name("cmp_then_push", &[Value::I32(1), Value::I32(2)])?;
+    assert_eq!(result, vec![Value::I32(1), Value::I32(1), Value::I32(3)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_float_cmp_nan() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/cmp.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name("f64_cmp", &[Value::F64(1.0), Value::F64(2.0)])?;
+    assert_eq!(result, [0, 1, 1, 0, 1, 0].map(Value::I32).to_vec());
+    let result = runtime.call_func_by_name("f64_cmp", &[Value::F64(2.0), Value::F64(2.0)])?;
+    assert_eq!(result, [1, 0, 0, 0, 1, 1].map(Value::I32).to_vec());
+    let result = runtime.call_func_by_name("f64_cmp", &[Value::F64(f64::NAN), Value::F64(2.0)])?;
+    assert_eq!(result, [0, 1, 0, 0, 0, 0].map(Value::I32).to_vec());
+    let result =
+        runtime.call_func_by_name("f64_cmp", &[Value::F64(f64::NAN), Value::F64(f64::NAN)])?;
+    assert_eq!(result, [0, 1, 0, 0, 0, 0].map(Value::I32).to_vec());
+
+    for (lhs, rhs, eq) in [(1.0, 1.0, 1), (1.0, 2.0, 0), (f32::NAN, f32::NAN, 0)] {
+        let result = runtime.call_func_by_name("f32_if", &[Value::F32(lhs), Value::F32(rhs)])?;
+        assert_eq!(result, vec![Value::I32(eq), Value::I32(1 - eq)]);
+        let args = [Value::F64(lhs as f64), Value::F64(rhs as f64)];
+        let result = runtime.call_func_by_name("f64_br_if", &args)?;
+        assert_eq!(result, vec![Value::I32(eq), Value::I32(1 - eq)]);
+        let result = runtime.call_func_by_name("if_no_else", &args)?;
+        assert_eq!(result, vec![Value::I32(7 * eq)]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_fused_branch_and_select() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/cmp.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name("sum_loop", &[Value::I32(100)])?;
+    assert_eq!(result, vec![Value::I32(4950)]);
+    let result = runtime.call_func_by_name("sum_loop", &[Value::I32(0)])?;
+    assert_eq!(result, vec![Value::I32(0)]);
+    let result = runtime.call_func_by_name("count_loop", &[Value::I32(10)])?;
+    assert_eq!(result, vec![Value::I32(10)]);
+    let result = runtime.call_func_by_name("count_loop", &[Value::I32(-3)])?;
+    assert_eq!(result, vec![Value::I32(1)]);
+
+    let result =
+        runtime.call_func_by_name("select", &[Value::I32(1), Value::I32(2), Value::I32(5)])?;
+    assert_eq!(result, vec![Value::I32(1), Value::I32(1), Value::I32(200)]);
+    let result =
+        runtime.call_func_by_name("select", &[Value::I32(3), Value::I32(2), Value::I32(0)])?;
+    assert_eq!(result, vec![Value::I32(2), Value::I32(2), Value::I32(200)]);
+    let result = runtime.call_func_by_name("select_f64", &[Value::F64(1.5), Value::F64(2.5)])?;
+    assert_eq!(result, vec![Value::F64(1.5)]);
+    let result = runtime.call_func_by_name("select_f64", &[Value::F64(2.5), Value::F64(2.5)])?;
+    assert_eq!(result, vec![Value::F64(2.5)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_int_conversions() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/conv.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name(
+        "int_conv",
+        &[Value::I64(0x1234_5678_8000_ff80), Value::I32(-0x7f7f)],
+    )?;
+    assert_eq!(
+        result,
+        vec![
+            Value::I32(0x8000_ff80_u32 as i32),
+            Value::I64(-0x7f7f),
+            Value::I64(-0x7f7f_i32 as u32 as i64),
+            Value::I32(-0x7f),
+            Value::I32(-0x7f7f),
+            Value::I64(-0x80),
+            Value::I64(0x8000_ff80_u32 as i32 as i64)
+        ]
+    );
+    let result = runtime.call_func_by_name("wrap_then_extend", &[Value::I64(-1)])?;
+    assert_eq!(result, vec![Value::I64(0xffff_ffff)]);
+    let result = runtime.call_func_by_name("const_conv", &[])?;
+    assert_eq!(
+        result,
+        vec![
+            Value::I32(-0x7fff_ffff),
+            Value::I64(-2),
+            Value::I64(0xffff_fffe),
+            Value::I32(-0x80),
+            Value::I64(0x7fff)
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_float_trunc() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/conv.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let mut call = |name: &str, arg: Value| runtime.call_func_by_name(name, &[arg]);
+    assert_eq!(
+        call("i32_trunc_f32_s", Value::F32(-3.9))?,
+        vec![Value::I32(-3)]
+    );
+    assert_eq!(
+        call("i32_trunc_f32_s", Value::F32(-2147483648.0))?,
+        vec![Value::I32(i32::MIN)]
+    );
+    assert_trap(
+        call("i32_trunc_f32_s", Value::F32(2147483648.0)),
+        Trap::IntegerOverflow,
+    );
+    assert_trap(
+        call("i32_trunc_f32_s", Value::F32(f32::NAN)),
+        Trap::InvalidConversionToInteger,
+    );
+    assert_eq!(
+        call("i32_trunc_f32_u", Value::F32(-0.9))?,
+        vec![Value::I32(0)]
+    );
+    assert_eq!(
+        call("i32_trunc_f32_u", Value::F32(4294967040.0))?,
+        vec![Value::I32(-256)]
+    );
+    assert_trap(
+        call("i32_trunc_f32_u", Value::F32(-1.0)),
+        Trap::IntegerOverflow,
+    );
+    assert_eq!(
+        call("i32_trunc_f64_s", Value::F64(-2147483648.9))?,
+        vec![Value::I32(i32::MIN)]
+    );
+    assert_trap(
+        call("i32_trunc_f64_s", Value::F64(-2147483649.0)),
+        Trap::IntegerOverflow,
+    );
+    assert_eq!(
+        call("i32_trunc_f64_u", Value::F64(4294967295.9))?,
+        vec![Value::I32(-1)]
+    );
+    assert_trap(
+        call("i32_trunc_f64_u", Value::F64(4294967296.0)),
+        Trap::IntegerOverflow,
+    );
+    assert_trap(
+        call("i32_trunc_f64_u", Value::F64(f64::NAN)),
+        Trap::InvalidConversionToInteger,
+    );
+    assert_eq!(
+        call("i64_trunc_f32_s", Value::F32(-9223372036854775808.0))?,
+        vec![Value::I64(i64::MIN)]
+    );
+    assert_trap(
+        call("i64_trunc_f32_s", Value::F32(9223372036854775808.0)),
+        Trap::IntegerOverflow,
+    );
+    assert_eq!(
+        call("i64_trunc_f64_s", Value::F64(-1e18))?,
+        vec![Value::I64(-1_000_000_000_000_000_000)]
+    );
+    assert_eq!(
+        call("i64_trunc_f64_u", Value::F64(18446744073709549568.0))?,
+        vec![Value::I64(-2048)]
+    );
+    assert_eq!(
+        call("i64_trunc_f64_u", Value::F64(1e10))?,
+        vec![Value::I64(10_000_000_000)]
+    );
+    assert_trap(
+        call("i64_trunc_f64_u", Value::F64(18446744073709551616.0)),
+        Trap::IntegerOverflow,
+    );
+
+    for arg in [f64::NAN, -1e20, -5.5, 3e9, 1e19, 1e20, f64::INFINITY] {
+        let result = call("trunc_sat_f64", Value::F64(arg))?;
+        assert_eq!(
+            result,
+            vec![
+                Value::I32(arg as i32),
+                Value::I32(arg as u32 as i32),
+                Value::I64(arg as i64),
+                Value::I64(arg as u64 as i64)
+            ],
+            "{}",
+            arg
+        );
+        let arg = arg as f32;
+        let result = call("trunc_sat_f32", Value::F32(arg))?;
+        assert_eq!(
+            result,
+            vec![
+                Value::I32(arg as i32),
+                Value::I32(arg as u32 as i32),
+                Value::I64(arg as i64),
+                Value::I64(arg as u64 as i64)
+            ],
+            "{}",
+            arg
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_float_convert() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/conv.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    for arg in [0, 7, -1, i32::MIN, 16777217] {
+        let result = runtime.call_func_by_name("convert_i32", &[Value::I32(arg)])?;
+        assert_eq!(
+            result,
+            vec![
+                Value::F32(arg as f32),
+                Value::F32(arg as u32 as f32),
+                Value::F64(arg as f64),
+                Value::F64(arg as u32 as f64)
+            ]
+        );
+    }
+    // the last two round differently when the low bit is dropped before converting
+    for arg in [
+        0,
+        -7,
+        i64::MIN,
+        i64::MAX,
+        -1,
+        -0x7fff_ff7f_ffff_ffff,
+        0x0020_0000_0000_0001,
+    ] {
+        let result = runtime.call_func_by_name("convert_i64", &[Value::I64(arg)])?;
+        assert_eq!(
+            result,
+            vec![
+                Value::F32(arg as f32),
+                Value::F32(arg as u64 as f32),
+                Value::F64(arg as f64),
+                Value::F64(arg as u64 as f64)
+            ],
+            "{}",
+            arg
+        );
+    }
+    let result =
+        runtime.call_func_by_name("demote_promote", &[Value::F64(0.1), Value::F32(0.1)])?;
+    assert_eq!(result, vec![Value::F32(0.1), Value::F64(0.1_f32 as f64)]);
+
+    let result = runtime.call_func_by_name(
+        "reinterpret",
+        &[Value::F32(1.5), Value::I64(2.5_f64.to_bits() as i64)],
+    )?;
+    assert_eq!(
+        result,
+        vec![
+            Value::I32((-1.5_f32).to_bits() as i32),
+            Value::F64(2.5),
+            Value::I32((-1.0_f32).to_bits() as i32),
+            Value::F32(1.0)
+        ]
+    );
+    let result =
+        runtime.call_func_by_name("reinterpret_freg", &[Value::F64(1.0), Value::F64(2.0)])?;
+    assert_eq!(result, vec![Value::I64(3.0_f64.to_bits() as i64)]);
+
+    Ok(())
+}
+
+/// Encodes a module exporting `count` as `(func (param i32) (result i32))` that adds 1 to its
+/// param `repeat` times, which compiles to far more code than one chunk of code memory.
+fn counting_module(repeat: usize) -> Vec<u8> {
+    fn section(id: u8, contents: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![id];
+        bytes.extend(leb128(contents.len()));
+        bytes.extend(contents);
+        bytes
+    }
+    fn leb128(mut n: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let byte = (n & 0x7f) as u8;
+            n >>= 7;
+            if n == 0 {
+                bytes.push(byte);
+                return bytes;
+            }
+            bytes.push(byte | 0x80);
+        }
+    }
+    // no locals, then (local.set 0 (i32.add (local.get 0) (i32.const 1))) repeated
+    let mut body = vec![0x00];
+    for _ in 0..repeat {
+        body.extend([0x20, 0x00, 0x41, 0x01, 0x6a, 0x21, 0x00]);
+    }
+    body.extend([0x20, 0x00, 0x0b]);
+    let mut code = vec![0x01];
+    code.extend(leb128(body.len()));
+    code.extend(body);
+
+    let mut bytes = b"\0asm\x01\0\0\0".to_vec();
+    bytes.extend(section(1, &[0x01, 0x60, 0x01, 0x7f, 0x01, 0x7f]));
+    bytes.extend(section(3, &[0x01, 0x00]));
+    bytes.extend(section(
+        7,
+        &[0x01, 0x05, b'c', b'o', b'u', b'n', b't', 0x00, 0x00],
+    ));
+    bytes.extend(section(10, &code));
+    bytes
+}
+
+#[test]
+fn test_large_function() -> Result<()> {
+    let bytes = counting_module(20_000);
+    let modules = parser::parse(&bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name("count", &[Value::I32(5)])?;
+    assert_eq!(result, vec![Value::I32(20_005)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_code_limit() -> Result<()> {
+    let bytes = counting_module(20_000);
+    let modules = parser::parse(&bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    runtime.set_code_limit(64 * 1024);
+    let err = runtime
+        .call_func_by_name("count", &[Value::I32(5)])
+        .expect_err("expected the code limit to be exceeded");
+    assert!(matches!(
+        err.downcast_ref::<CompileError>(),
+        Some(CompileError::CodeAreaExhausted(_))
+    ));
+    // the failed function doesn't use up any space
+    runtime.set_code_limit(1024 * 1024);
+    let result = runtime.call_func_by_name("count", &[Value::I32(5)])?;
+    assert_eq!(result, vec![Value::I32(20_005)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_packed_functions() -> Result<()> {
+    let count = "(local.set 0 (i32.add (local.get 0) (i32.const 1)))".repeat(20_000);
+    let wat = format!(
+        r#"
+        (module
+          (func (export "small") (param i32) (result i32) (i32.add (local.get 0) (i32.const 1)))
+          (func (export "count") (param i32) (result i32) {count} (local.get 0)))
+        "#
+    );
+    let bytes = parser::wat_to_wasm(wat.as_bytes())?;
+    let modules = parser::parse(&bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    runtime.enable_listing();
+    runtime.set_code_limit(64 * 1024);
+    assert_eq!(
+        runtime.call_func_by_name("small", &[Value::I32(1)])?,
+        vec![Value::I32(2)]
+    );
+    runtime
+        .call_func_by_name("count", &[Value::I32(5)])
+        .expect_err("expected the code limit to be exceeded");
+    // the page shared with the failed function is executable again
+    assert_eq!(
+        runtime.call_func_by_name("small", &[Value::I32(2)])?,
+        vec![Value::I32(3)]
+    );
+    runtime.set_code_limit(1024 * 1024);
+    assert_eq!(
+        runtime.call_func_by_name("count", &[Value::I32(5)])?,
+        vec![Value::I32(20_005)]
+    );
+    // functions follow each other at 16-byte boundaries instead of each taking whole pages
+    let [small, count] = runtime.listings() else {
+        panic!("expected two listings, got {}", runtime.listings().len());
+    };
+    assert_eq!(
+        count.address,
+        (small.address + small.code.len()).next_multiple_of(16)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_no_writable_code() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/fib.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name("fib", &[Value::I64(10)])?;
+    assert_eq!(result, vec![Value::I64(55)]);
+    let maps = std::fs::read_to_string("/proc/self/maps")?;
+    for line in maps.lines() {
+        let permissions = line.split_whitespace().nth(1).unwrap_or_default();
+        assert!(
+            !permissions.starts_with("rwx"),
+            "writable and executable mapping: {}",
+            line
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_wat_module() -> Result<()> {
+    let bytes = parser::wat_to_wasm(
+        br#"
+        (module
+          (func (export "sum") (param $n i32) (result i32)
+            (local $sum i32)
+            (block $done
+              (loop $next
+                (br_if $done (i32.eqz (local.get $n)))
+                (local.set $sum (i32.add (local.get $sum) (local.get $n)))
+                (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+                (br $next)))
+            (local.get $sum)))
+        "#,
+    )?;
+    let modules = parser::parse(&bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let result = runtime.call_func_by_name("sum", &[Value::I32(100)])?;
+    assert_eq!(result, vec![Value::I32(5050)]);
+
+    // binary input is passed through
+    let bytes = include_bytes!("../tests/wasm/add.wasm");
+    assert_eq!(&*parser::wat_to_wasm(bytes)?, bytes);
+
+    Ok(())
+}
+
+#[test]
+fn test_wat_error_position() -> Result<()> {
+    let err = parser::wat_to_wasm(b"(module\n  (func (result i32)\n    (i32.const x)))")
+        .expect_err("expected a parse error");
+    assert!(err.to_string().contains(":3:16"), "{}", err);
+
+    let path = std::env::temp_dir().join("wasm_jit_test_wat_error_position.wat");
+    std::fs::write(&path, "(module (func (export \"f\") (i32.add))")?;
+    let err = parser::read_module(&path).expect_err("expected a parse error");
+    std::fs::remove_file(&path)?;
+    assert!(
+        err.to_string().contains(&format!("{}:1:", path.display())),
+        "{}",
+        err
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_invalid_module() -> Result<()> {
+    let bytes = parser::wat_to_wasm(
+        br#"
+        (module
+          (func (export "ok") (result i32) (i32.const 1))
+          (func (export "bad") (result i32) (i64.const 1)))
+        "#,
+    )?;
+    let err = parser::parse(&bytes).expect_err("expected a validation error");
+    match err.downcast_ref::<ParseError>() {
+        Some(ParseError::Func {
+            func_index,
+            offset,
+            message,
+        }) => {
+            assert_eq!(*func_index, 1);
+            // the error points at the end of the body, where the result is checked
+            assert_eq!(bytes[*offset], 0x0b);
+            assert!(message.contains("type mismatch"), "{}", message);
+        }
+        _ => panic!("unexpected error: {}", err),
+    }
+
+    let err = parser::parse(b"\0asm\x02\0\0\0").expect_err("expected a decoding error");
+    assert!(
+        matches!(
+            err.downcast_ref::<ParseError>(),
+            Some(ParseError::Module { message, .. }) if message.contains("binary version")
+        ),
+        "{:?}",
+        err
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_compile_errors() -> Result<()> {
+    let bytes = parser::wat_to_wasm(
+        br#"
+        (module
+          (memory 1)
+          (func $fill (export "fill") (memory.fill (i32.const 0) (i32.const 0) (i32.const 1)))
+          (func (export "call_fill") (result i32) (call $fill) (i32.const 1))
+          (func (export "v128") (local v128))
+          (func (export "results") (result i32)
+            (block (result i32 i32 i32 i32 i32 i32 i32 i32)
+              (i32.const 1) (i32.const 2) (i32.const 3) (i32.const 4)
+              (i32.const 5) (i32.const 6) (i32.const 7) (i32.const 8))
+            (drop) (drop) (drop) (drop) (drop) (drop) (drop))
+          (func (export "ok") (result i32) (i32.const 42)))
+        "#,
+    )?;
+    let modules = parser::parse(&bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let compile_error = |runtime: &mut Runtime, name: &str| -> CompileError {
+        let err = runtime
+            .call_func_by_name(name, &[])
+            .expect_err("expected a compile error");
+        err.downcast_ref::<CompileError>()
+            .unwrap_or_else(|| panic!("unexpected error: {}", err))
+            .clone()
+    };
+    assert!(matches!(
+        compile_error(&mut runtime, "fill"),
+        CompileError::UnsupportedOperator(op) if op.contains("MemoryFill") && op.ends_with(" in fill")
+    ));
+    // the callee is compiled when JIT code calls it first
+    assert!(matches!(
+        compile_error(&mut runtime, "call_fill"),
+        CompileError::UnsupportedOperator(op) if op.ends_with(" in fill")
+    ));
+    assert!(matches!(
+        compile_error(&mut runtime, "v128"),
+        CompileError::UnsupportedValueType(ty) if ty.ends_with(" in wasm_func_2")
+    ));
+    assert!(matches!(
+        compile_error(&mut runtime, "results"),
+        CompileError::TooManyResults(_)
+    ));
+    // the runtime stays usable
+    let result = runtime.call_func_by_name("ok", &[])?;
+    assert_eq!(result, vec![Value::I32(42)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_call_arg_mismatch() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/add_with_arg.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    for args in [
+        &[][..],
+        &[Value::I64(1)],
+        &[Value::I64(1), Value::I64(2), Value::I64(3)],
+        &[Value::I32(1), Value::I64(2)],
+        &[Value::I64(1), Value::F64(2.0)],
+    ] {
+        let err = runtime
+            .call_func_by_name("add", args)
+            .expect_err("expected a type mismatch");
+        assert!(matches!(
+            err.downcast_ref::<RuntimeError>(),
+            Some(RuntimeError::FunctionTypeMismatch(_))
+        ));
+    }
+    let result = runtime.call_func_by_name("add", &[Value::I64(1), Value::I64(2)])?;
+    assert_eq!(result, vec![Value::I64(3)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_typed_func() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/add_with_arg.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let add = runtime.get_typed_func::<(i64, i64), i64>("add")?;
+    assert_eq!(add.call(&mut runtime, (10, 20))?, 30);
+    assert_eq!(add.call(&mut runtime, (-1, i64::MIN))?, i64::MAX);
+    let add32 = runtime.get_typed_func::<(i32, i32), i32>("add32")?;
+    assert_eq!(add32.call(&mut runtime, (-1000, 2000))?, 1000);
+
+    let err = runtime
+        .get_typed_func::<(i32, i32), i64>("add")
+        .expect_err("expected a type mismatch");
+    assert!(matches!(
+        err.downcast_ref::<RuntimeError>(),
+        Some(RuntimeError::FunctionTypeMismatch(_))
+    ));
+    assert!(runtime.get_typed_func::<i64, i64>("add").is_err());
+    assert!(runtime.get_typed_func::<(i64, i64), ()>("add").is_err());
+    assert!(runtime.get_typed_func::<(), ()>("missing").is_err());
+
+    // a handle only works with the runtime it came from
+    let modules = parser::parse(bytes)?;
+    let mut other = Runtime::init(modules)?;
+    let err = add32
+        .call(&mut other, (1, 2))
+        .expect_err("expected a foreign function error");
+    assert!(matches!(
+        err.downcast_ref::<RuntimeError>(),
+        Some(RuntimeError::ForeignFunc(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_typed_func_multi_value() -> Result<()> {
+    let bytes = parser::wat_to_wasm(
+        br#"
+        (module
+          (global $calls (mut i32) (i32.const 0))
+          (func (export "tick") (global.set $calls (i32.add (global.get $calls) (i32.const 1))))
+          (func (export "calls") (result i32) (global.get $calls))
+          (func (export "swap") (param i32 f64 i64) (result i64 f64 i32)
+            (local.get 2) (local.get 1) (local.get 0))
+          (func (export "div") (param i32 i32) (result i32) (i32.div_s (local.get 0) (local.get 1))))
+        "#,
+    )?;
+    let modules = parser::parse(&bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    let tick = runtime.get_typed_func::<(), ()>("tick")?;
+    tick.call(&mut runtime, ())?;
+    tick.call(&mut runtime, ())?;
+    let calls = runtime.get_typed_func::<(), i32>("calls")?;
+    assert_eq!(calls.call(&mut runtime, ())?, 2);
+    let swap = runtime.get_typed_func::<(i32, f64, i64), (i64, f64, i32)>("swap")?;
+    assert_eq!(swap.call(&mut runtime, (1, 2.5, -3))?, (-3, 2.5, 1));
+    let div = runtime.get_typed_func::<(i32, i32), i32>("div")?;
+    assert_trap(div.call(&mut runtime, (1, 0)), Trap::IntegerDivideByZero);
+    assert_eq!(div.call(&mut runtime, (7, 2))?, 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_invoke() -> Result<()> {
+    let run = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_wasm_jit"))
+            .arg("run")
+            .args(args)
+            .output()
+    };
+    let output = run(&[
+        "tests/wasm/add_with_arg.wasm",
+        "--invoke",
+        "add",
+        "10",
+        "20",
+    ])?;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout)?, "30\n");
+    // integers may be given in their unsigned range
+    let output = run(&[
+        "tests/wasm/add_with_arg.wat",
+        "--invoke",
+        "add32",
+        "4294967295",
+        "3",
+    ])?;
+    assert_eq!(String::from_utf8(output.stdout)?, "2\n");
+
+    let output = run(&["tests/wasm/int.wasm", "--invoke", "i32_div_s", "1", "0"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("integer divide by zero"));
+    let output = run(&["tests/wasm/add_with_arg.wasm", "--invoke", "add", "1"])?;
+    assert!(!output.status.success());
+    let output = run(&["tests/wasm/add_with_arg.wasm", "--invoke", "add", "1", "x"])?;
+    assert!(!output.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn test_disassembler() {
+    use wasm_jit::assembler::{
+        Cmp, Cmpsd, Condition, Jcc, Lea, Mov, Movq, Movsx, Popcnt, Push, Register32::*,
+        Register64::*, Register8::*, Setcc, Shl, WordPtr, XmmRegister::*,
+    };
+    let cases: Vec<(Vec<u8>, &str)> = vec![
+        (Rax.mov(R11.with_offset(8)), "mov rax, qword ptr [r11+0x8]"),
+        (
+            Rsp.with_offset(-0x100).mov(R12),
+            "mov qword ptr [rsp-0x100], r12",
+        ),
+        (Rax.lea(R12.to_mem()), "lea rax, [r12]"),
+        (Rax.cmp(Rbp.with_offset(0)), "cmp rax, qword ptr [rbp]"),
+        (R9d.mov(-1), "mov r9d, -0x1"),
+        (R10.mov(-1_i64), "mov r10, 0xffffffffffffffff"),
+        (Esi.movsx(WordPtr(R8.to_mem())), "movsx esi, word ptr [r8]"),
+        (R13d.popcnt(Eax), "popcnt r13d, eax"),
+        (Rcx.shl(3_u8), "shl rcx, 3"),
+        (Dl.setcc(Condition::Le), "setle dl"),
+        (Xmm9.cmpsd(Xmm2, 4), "cmpsd xmm9, xmm2, 4"),
+        (Rdi.movq(Xmm3), "movq rdi, xmm3"),
+        (R11.with_offset(-16).push(), "push qword ptr [r11-0x10]"),
+        (7_i32.push(), "push 0x7"),
+        ((-6_i32).jcc(Condition::Ne), "jne 0x0"),
+        (vec![0x0f, 0x0b], "db 0x0f"),
+    ];
+    for (code, text) in cases {
+        let instructions = disassemble(&code);
+        assert_eq!(instructions[0].text, text, "{:02x?}", code);
+        if !text.starts_with("db") {
+            assert_eq!(instructions.len(), 1);
+            assert_eq!(instructions[0].len, code.len());
+        }
+    }
+}
+
+#[test]
+fn test_listing() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/add_with_arg.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    runtime.call_func_by_name("add", &[Value::I64(1), Value::I64(2)])?;
+    // nothing is recorded until listings are enabled
+    assert!(runtime.listings().is_empty());
+    runtime.enable_listing();
+    runtime.call_func_by_name("add32", &[Value::I32(1), Value::I32(2)])?;
+    runtime.call_func_by_name("add32", &[Value::I32(3), Value::I32(4)])?;
+    let [listing] = runtime.listings() else {
+        panic!("expected one listing, got {}", runtime.listings().len());
+    };
+    assert_eq!(
+        listing.ops.first().map(|(_, op)| op.as_str()),
+        Some("prologue")
+    );
+    assert_eq!(
+        listing.ops.last().map(|(_, op)| op.as_str()),
+        Some("epilogue")
+    );
+    let text = listing.to_string();
+    let add = text.find("; I32Add").expect("operator in listing");
+    assert!(text[add..]
+        .lines()
+        .nth(1)
+        .unwrap()
+        .ends_with("add edi, esi"));
+    assert!(text.trim_end().ends_with("ret"));
+    // undecoded bytes are shown as `db`, which raw bytes in the hex column can look like
+    assert!(disassemble(&listing.code)
+        .iter()
+        .all(|instruction| !instruction.text.starts_with("db ")));
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_wasm_jit"))
+        .args(["run", "--listing", "tests/wasm/add_with_arg.wasm"])
+        .args(["--invoke", "add", "1", "2"])
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout)?, "3\n");
+    assert!(String::from_utf8(output.stderr)?.contains("; I64Add"));
+
+    Ok(())
+}
+
+#[test]
+fn test_perf_map_and_jitdump() -> Result<()> {
+    let bytes = include_bytes!("../tests/wasm/fib.wasm");
+    let modules = parser::parse(bytes)?;
+    let mut runtime = Runtime::init(modules)?;
+    runtime.enable_listing();
+    runtime.enable_perf_map()?;
+    let dir = std::env::temp_dir().join(format!("wasm_jit_jitdump_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    runtime.enable_jitdump(&dir)?;
+    runtime.call_func_by_name("fib", &[Value::I64(10)])?;
+    let [listing] = runtime.listings() else {
+        panic!("expected one listing");
+    };
+    // a runtime only writes to the files it enabled, although the jitdump is open
+    let modules = parser::parse(bytes)?;
+    let mut perf_map_only = Runtime::init(modules)?;
+    perf_map_only.enable_listing();
+    perf_map_only.enable_perf_map()?;
+    perf_map_only.call_func_by_name("fib", &[Value::I64(10)])?;
+    let [perf_map_only_listing] = perf_map_only.listings() else {
+        panic!("expected one listing");
+    };
+
+    let perf_map_path = format!("/tmp/perf-{}.map", std::process::id());
+    let perf_map = std::fs::read_to_string(&perf_map_path)?;
+    std::fs::remove_file(&perf_map_path)?;
+    let entry = format!(
+        "{:x} {:x} wasm_func_{}",
+        listing.address,
+        listing.code.len(),
+        listing.func_index
+    );
+    assert!(perf_map.lines().any(|line| line == entry), "{}", perf_map);
+    let entry = format!("{:x} ", perf_map_only_listing.address);
+    assert!(perf_map.lines().any(|line| line.starts_with(&entry)));
+
+    // the header is followed by a JIT_CODE_LOAD record with the name and the code
+    let dump = std::fs::read(dir.join(format!("jit-{}.dump", std::process::id())))?;
+    let u32_at = |offset: usize| u32::from_ne_bytes(dump[offset..offset + 4].try_into().unwrap());
+    let u64_at = |offset: usize| u64::from_ne_bytes(dump[offset..offset + 8].try_into().unwrap());
+    assert_eq!(u32_at(0), 0x4a695444);
+    assert_eq!(u32_at(20), std::process::id());
+    let mut offset = u32_at(8) as usize;
+    let mut found = false;
+    while offset < dump.len() {
+        let (id, size) = (u32_at(offset), u32_at(offset + 4) as usize);
+        if id == 0 && u64_at(offset + 32) == listing.address as u64 {
+            let name = b"wasm_func_0\0";
+            let code_start = offset + 56 + name.len();
+            assert_eq!(&dump[offset + 56..code_start], name);
+            assert_eq!(u64_at(offset + 40), listing.code.len() as u64);
+            assert_eq!(&dump[code_start..offset + size], &listing.code[..]);
+            found = true;
+        }
+        assert!(id != 0 || u64_at(offset + 32) != perf_map_only_listing.address as u64);
+        offset += size;
+    }
+    assert!(found);
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_name_section() -> Result<()> {
+    let bytes = parser::wat_to_wasm(
+        br#"
+        (module $m
+          (func $div (param $a i32) (param $b i32) (result i32)
+            (i32.div_u (local.get $a) (local.get $b)))
+          (func $outer (export "outer") (param $x i32) (result i32)
+            (call $div (local.get $x) (i32.const 0)))
+          (func (export "unnamed") (result i32)
+            (call $outer (i32.const 1))))
+        "#,
+    )?;
+    let modules = parser::parse(&bytes)?;
+    assert_eq!(modules.names.module, Some("m"));
+    assert_eq!(modules.names.funcs.get(&1), Some(&"outer"));
+    assert_eq!(modules.names.funcs.get(&2), None);
+    assert_eq!(modules.names.locals[&0].get(&1), Some(&"b"));
+    let mut runtime = Runtime::init(modules)?;
+    runtime.enable_listing();
+
+    // the trap is still the root cause, with the functions it unwound through as context
+    let err = runtime.call_func_by_name("unnamed", &[]).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RuntimeError>(),
+        Some(RuntimeError::Trap(Trap::IntegerDivideByZero))
+    ));
+    let backtrace = err.downcast_ref::<WasmBacktrace>().expect("wasm backtrace");
+    assert_eq!(backtrace.frames, ["m!div", "m!outer", "m!wasm_func_2"]);
+    assert_eq!(
+        format!("{:#}", err),
+        "wasm backtrace:\n  0: m!div\n  1: m!outer\n  2: m!wasm_func_2: Trap: integer divide by zero"
+    );
+
+    let listings = runtime.listings();
+    assert_eq!(listings.len(), 3);
+    let outer = listings
+        .iter()
+        .find(|listing| listing.func_index == 1)
+        .expect("listing of outer");
+    assert_eq!(outer.name.as_deref(), Some("outer"));
+    let text = outer.to_string();
+    assert!(text.starts_with("func 1 $outer at "), "{}", text);
+    assert!(
+        text.contains("; LocalGet { local_index: 0 } $x"),
+        "{}",
+        text
+    );
+    assert!(
+        text.contains("; Call { function_index: 0 } $div"),
+        "{}",
+        text
+    );
+
+    // names become symbols in line-based and NUL-terminated formats
+    let bytes = parser::wat_to_wasm(
+        br#"
+        (module
+          (func (@name "bad\0a\00name") (export "trap") unreachable))
+        "#,
+    )?;
+    let modules = parser::parse(&bytes)?;
+    assert_eq!(modules.names.funcs.get(&0), Some(&"bad\n\0name"));
+    let mut runtime = Runtime::init(modules)?;
+    runtime.enable_listing();
+    let err = runtime.call_func_by_name("trap", &[]).unwrap_err();
+    let backtrace = err.downcast_ref::<WasmBacktrace>().expect("wasm backtrace");
+    assert_eq!(backtrace.frames, ["bad__name"]);
+    assert_eq!(runtime.listings()[0].name.as_deref(), Some("bad__name"));
+
+    Ok(())
+}
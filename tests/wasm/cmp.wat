@@ -0,0 +1,86 @@
+(module
+  (func (export "i32_cmp") (param i32 i32) (result i32 i32 i32 i32 i32 i32 i32)
+    (i32.ne (local.get 0) (local.get 1))
+    (i32.lt_s (local.get 0) (local.get 1))
+    (i32.lt_u (local.get 0) (local.get 1))
+    (i32.gt_s (local.get 0) (local.get 1))
+    (i32.gt_u (local.get 0) (local.get 1))
+    (i32.le_s (local.get 0) (local.get 1))
+    (i32.ge_u (local.get 0) (local.get 1)))
+  (func (export "i32_cmp_imm") (param i32) (result i32 i32 i32 i32 i32 i32)
+    (i32.lt_s (local.get 0) (i32.const 5))
+    (i32.lt_s (i32.const 5) (local.get 0))
+    (i32.le_u (local.get 0) (i32.const -1))
+    (i32.ge_s (i32.const 5) (local.get 0))
+    (i32.gt_u (i32.const -1) (i32.const 5))
+    (i32.lt_s (i32.const -1) (i32.const 5)))
+  (func (export "i64_cmp") (param i64 i64) (result i32 i32 i32 i32 i32 i32 i32)
+    (i64.ne (local.get 0) (local.get 1))
+    (i64.lt_s (local.get 0) (local.get 1))
+    (i64.lt_u (local.get 0) (local.get 1))
+    (i64.gt_s (local.get 0) (i64.const 0))
+    (i64.gt_u (i64.const 0) (local.get 1))
+    (i64.le_s (local.get 0) (local.get 1))
+    (i64.ge_u (local.get 0) (local.get 1)))
+  (func (export "f64_cmp") (param f64 f64) (result i32 i32 i32 i32 i32 i32)
+    (f64.eq (local.get 0) (local.get 1))
+    (f64.ne (local.get 0) (local.get 1))
+    (f64.lt (local.get 0) (local.get 1))
+    (f64.gt (local.get 0) (local.get 1))
+    (f64.le (local.get 0) (local.get 1))
+    (f64.ge (local.get 0) (local.get 1)))
+  (func (export "f32_if") (param f32 f32) (result i32 i32)
+    (if (result i32) (f32.eq (local.get 0) (local.get 1))
+      (then (i32.const 1))
+      (else (i32.const 0)))
+    (if (result i32) (f32.ne (local.get 0) (local.get 1))
+      (then (i32.const 1))
+      (else (i32.const 0))))
+  (func (export "f64_br_if") (param f64 f64) (result i32 i32)
+    (block (result i32)
+      (br_if 0 (i32.const 1) (f64.eq (local.get 0) (local.get 1)))
+      (drop)
+      (i32.const 0))
+    (block (result i32)
+      (i32.const 1)
+      (br_if 0 (f64.ne (local.get 0) (local.get 1)))
+      (drop)
+      (i32.const 0)))
+  (func (export "if_no_else") (param f64 f64) (result i32)
+    (local i32)
+    (if (f64.eq (local.get 0) (local.get 1))
+      (then (local.set 2 (i32.const 7))))
+    (local.get 2))
+  ;; sum of 0..n, counting down with a fused lt_u/br_if
+  (func (export "sum_loop") (param i32) (result i32)
+    (local i32)
+    (block
+      (loop
+        (br_if 1 (i32.eqz (local.get 0)))
+        (local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+        (local.set 1 (i32.add (local.get 1) (local.get 0)))
+        (br_if 0 (i32.gt_u (local.get 0) (i32.const 0)))))
+    (local.get 1))
+  (func (export "count_loop") (param i32) (result i32)
+    (local i32)
+    (loop
+      (local.set 1 (i32.add (local.get 1) (i32.const 1)))
+      (br_if 0 (i32.lt_s (local.get 1) (local.get 0))))
+    (local.get 1))
+  (func (export "eqz_fused") (param i32 i32) (result i32 i32)
+    (i32.eqz (i32.lt_s (local.get 0) (local.get 1)))
+    (if (result i32) (i32.eqz (i32.ge_s (local.get 0) (local.get 1)))
+      (then (i32.const 10))
+      (else (i32.const 20))))
+  (func (export "cmp_then_push") (param i32 i32) (result i32 i32 i32)
+    ;; the comparison result has to stay below the values pushed after it
+    (i32.lt_s (local.get 0) (local.get 1))
+    (local.get 0)
+    (i32.add (local.get 1) (i32.const 1)))
+  (func (export "select") (param i32 i32 i32) (result i32 i32 i32)
+    (select (local.get 0) (local.get 1) (local.get 2))
+    (select (local.get 0) (local.get 1) (i32.lt_u (local.get 0) (local.get 1)))
+    (select (i32.const 100) (i32.const 200) (i32.const 0)))
+  (func (export "select_f64") (param f64 f64) (result f64)
+    (select (result f64) (local.get 0) (local.get 1) (f64.ne (local.get 0) (local.get 1))))
+)
@@ -0,0 +1,94 @@
+(module
+  (func (export "sum") (param $n i64) (result i64) (local $acc i64)
+    (block $done
+      (loop $continue
+        (br_if $done (i64.eq (local.get $n) (i64.const 0)))
+        (local.set $acc (i64.add (local.get $acc) (local.get $n)))
+        (local.set $n (i64.sub (local.get $n) (i64.const 1)))
+        (br $continue)
+      )
+    )
+    local.get $acc
+  )
+  (func (export "count_down") (param $n i32) (result i32) (local $steps i32)
+    (loop $again
+      (local.set $steps (i32.add (local.get $steps) (i32.const 1)))
+      (local.tee $n (i32.sub (local.get $n) (i32.const 1)))
+      br_if $again
+    )
+    local.get $steps
+  )
+  (func (export "block_result") (param $x i64) (result i64)
+    (block $out (result i64)
+      i64.const 100
+      i64.const 1
+      (br_if $out (i64.eq (local.get $x) (i64.const 0)))
+      drop
+      drop
+      i64.const 2
+    )
+    i64.const 10
+    i64.add
+  )
+  (func (export "switch") (param $x i32) (result i64)
+    (block $default
+      (block $two
+        (block $one
+          (block $zero
+            (br_table $zero $one $two $default (local.get $x))
+          )
+          (return (i64.const 100))
+        )
+        (return (i64.const 101))
+      )
+      (return (i64.const 102))
+    )
+    i64.const 999
+  )
+  (func (export "early_return") (param $x i64) (result i64)
+    (if (i64.eq (local.get $x) (i64.const 0))
+      (then
+        i64.const 7
+        return
+      )
+    )
+    local.get $x
+    i64.const 1
+    i64.add
+  )
+  (func (export "nested") (param $n i64) (result i64) (local $i i64) (local $j i64) (local $acc i64)
+    (block $outer_done
+      (loop $outer
+        (br_if $outer_done (i64.eq (local.get $i) (local.get $n)))
+        (local.set $j (i64.const 0))
+        (block $inner_done
+          (loop $inner
+            (br_if $inner_done (i64.eq (local.get $j) (local.get $n)))
+            (local.set $acc (i64.add (local.get $acc) (i64.const 1)))
+            (local.set $j (i64.add (local.get $j) (i64.const 1)))
+            (br $inner)
+          )
+        )
+        (local.set $i (i64.add (local.get $i) (i64.const 1)))
+        (br $outer)
+      )
+    )
+    local.get $acc
+  )
+  (func (export "dead_code") (result i64)
+    (block $b (result i64)
+      i64.const 5
+      br $b
+      i64.const 1
+      i64.add
+    )
+  )
+  (func (export "if_keeps_stack") (param $x i32) (result i64)
+    i64.const 40
+    (if (local.get $x)
+      (then nop)
+    )
+    i64.const 2
+    i64.add
+  )
+)
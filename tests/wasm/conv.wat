@@ -0,0 +1,55 @@
+(module
+  (func (export "int_conv") (param i64 i32) (result i32 i64 i64 i32 i32 i64 i64)
+    (i32.wrap_i64 (local.get 0))
+    (i64.extend_i32_s (local.get 1))
+    (i64.extend_i32_u (local.get 1))
+    (i32.extend8_s (local.get 1))
+    (i32.extend16_s (local.get 1))
+    (i64.extend8_s (local.get 0))
+    (i64.extend32_s (local.get 0)))
+  (func (export "wrap_then_extend") (param i64) (result i64)
+    (i64.extend_i32_u (i32.wrap_i64 (local.get 0))))
+  (func (export "const_conv") (result i32 i64 i64 i32 i64)
+    (i32.wrap_i64 (i64.const 0x1_8000_0001))
+    (i64.extend_i32_s (i32.const -2))
+    (i64.extend_i32_u (i32.const -2))
+    (i32.extend8_s (i32.const 0x80))
+    (i64.extend16_s (i64.const 0x7fff)))
+  (func (export "i32_trunc_f32_s") (param f32) (result i32) (i32.trunc_f32_s (local.get 0)))
+  (func (export "i32_trunc_f32_u") (param f32) (result i32) (i32.trunc_f32_u (local.get 0)))
+  (func (export "i32_trunc_f64_s") (param f64) (result i32) (i32.trunc_f64_s (local.get 0)))
+  (func (export "i32_trunc_f64_u") (param f64) (result i32) (i32.trunc_f64_u (local.get 0)))
+  (func (export "i64_trunc_f32_s") (param f32) (result i64) (i64.trunc_f32_s (local.get 0)))
+  (func (export "i64_trunc_f64_s") (param f64) (result i64) (i64.trunc_f64_s (local.get 0)))
+  (func (export "i64_trunc_f64_u") (param f64) (result i64) (i64.trunc_f64_u (local.get 0)))
+  (func (export "trunc_sat_f64") (param f64) (result i32 i32 i64 i64)
+    (i32.trunc_sat_f64_s (local.get 0))
+    (i32.trunc_sat_f64_u (local.get 0))
+    (i64.trunc_sat_f64_s (local.get 0))
+    (i64.trunc_sat_f64_u (local.get 0)))
+  (func (export "trunc_sat_f32") (param f32) (result i32 i32 i64 i64)
+    (i32.trunc_sat_f32_s (local.get 0))
+    (i32.trunc_sat_f32_u (local.get 0))
+    (i64.trunc_sat_f32_s (local.get 0))
+    (i64.trunc_sat_f32_u (local.get 0)))
+  (func (export "convert_i32") (param i32) (result f32 f32 f64 f64)
+    (f32.convert_i32_s (local.get 0))
+    (f32.convert_i32_u (local.get 0))
+    (f64.convert_i32_s (local.get 0))
+    (f64.convert_i32_u (local.get 0)))
+  (func (export "convert_i64") (param i64) (result f32 f32 f64 f64)
+    (f32.convert_i64_s (local.get 0))
+    (f32.convert_i64_u (local.get 0))
+    (f64.convert_i64_s (local.get 0))
+    (f64.convert_i64_u (local.get 0)))
+  (func (export "demote_promote") (param f64 f32) (result f32 f64)
+    (f32.demote_f64 (local.get 0))
+    (f64.promote_f32 (local.get 1)))
+  (func (export "reinterpret") (param f32 i64) (result i32 f64 i32 f32)
+    (i32.reinterpret_f32 (f32.neg (local.get 0)))
+    (f64.reinterpret_i64 (local.get 1))
+    (i32.reinterpret_f32 (f32.const -1))
+    (f32.reinterpret_i32 (i32.const 0x3f800000)))
+  (func (export "reinterpret_freg") (param f64 f64) (result i64)
+    (i64.reinterpret_f64 (f64.add (local.get 0) (local.get 1))))
+)
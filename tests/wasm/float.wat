@@ -0,0 +1,77 @@
+(module
+  (memory 1)
+  (func (export "f64_arith") (param f64 f64) (result f64 f64 f64 f64)
+    (f64.add (local.get 0) (local.get 1))
+    (f64.sub (local.get 0) (local.get 1))
+    (f64.mul (local.get 0) (local.get 1))
+    (f64.div (local.get 0) (local.get 1)))
+  (func (export "f32_arith") (param f32 f32) (result f32 f32 f32 f32)
+    (f32.add (local.get 0) (local.get 1))
+    (f32.sub (local.get 0) (local.get 1))
+    (f32.mul (local.get 0) (local.get 1))
+    (f32.div (local.get 0) (local.get 1)))
+  (func (export "f64_unary") (param f64) (result f64 f64 f64 f64 f64 f64 f64)
+    (f64.sqrt (local.get 0))
+    (f64.ceil (local.get 0))
+    (f64.floor (local.get 0))
+    (f64.trunc (local.get 0))
+    (f64.nearest (local.get 0))
+    (f64.abs (local.get 0))
+    (f64.neg (local.get 0)))
+  (func (export "f32_unary") (param f32) (result f32 f32 f32 f32 f32 f32 f32)
+    (f32.sqrt (local.get 0))
+    (f32.ceil (local.get 0))
+    (f32.floor (local.get 0))
+    (f32.trunc (local.get 0))
+    (f32.nearest (local.get 0))
+    (f32.abs (local.get 0))
+    (f32.neg (local.get 0)))
+  (func (export "f64_min_max") (param f64 f64) (result f64 f64)
+    (f64.min (local.get 0) (local.get 1))
+    (f64.max (local.get 0) (local.get 1)))
+  (func (export "f32_min_max") (param f32 f32) (result f32 f32)
+    (f32.min (local.get 0) (local.get 1))
+    (f32.max (local.get 0) (local.get 1)))
+  (func (export "f64_copysign") (param f64 f64) (result f64)
+    (f64.copysign (local.get 0) (local.get 1)))
+  (func (export "f32_copysign") (param f32 f32) (result f32)
+    (f32.copysign (local.get 0) (local.get 1)))
+  (func (export "f64_cmp") (param f64 f64) (result i32 i32 i32 i32 i32 i32)
+    (f64.eq (local.get 0) (local.get 1))
+    (f64.ne (local.get 0) (local.get 1))
+    (f64.lt (local.get 0) (local.get 1))
+    (f64.gt (local.get 0) (local.get 1))
+    (f64.le (local.get 0) (local.get 1))
+    (f64.ge (local.get 0) (local.get 1)))
+  (func (export "f32_cmp") (param f32 f32) (result i32 i32 i32 i32 i32 i32)
+    (f32.eq (local.get 0) (local.get 1))
+    (f32.ne (local.get 0) (local.get 1))
+    (f32.lt (local.get 0) (local.get 1))
+    (f32.gt (local.get 0) (local.get 1))
+    (f32.le (local.get 0) (local.get 1))
+    (f32.ge (local.get 0) (local.get 1)))
+  (func (export "consts") (result f64 f32 f64)
+    (f64.neg (f64.const 1.5))
+    (f32.abs (f32.const -2.5))
+    (f64.add (f64.const 0.25) (f64.const 0.5)))
+  ;; keeps more floats live than there are xmm registers
+  (func (export "many_live") (param f64) (result f64)
+    (f64.mul (local.get 0) (f64.const 1))
+    (f64.mul (local.get 0) (f64.const 2))
+    (f64.mul (local.get 0) (f64.const 3))
+    (f64.mul (local.get 0) (f64.const 4))
+    (f64.mul (local.get 0) (f64.const 5))
+    (f64.mul (local.get 0) (f64.const 6))
+    (f64.mul (local.get 0) (f64.const 7))
+    (f64.mul (local.get 0) (f64.const 8))
+    (f64.mul (local.get 0) (f64.const 9))
+    f64.add f64.add f64.add f64.add f64.add f64.add f64.add f64.add)
+  ;; float values flow through locals, memory, calls and branches
+  (func $half (param f64) (result f64) (f64.mul (local.get 0) (f64.const 0.5)))
+  (func (export "mixed") (param f64 i32) (result f64)
+    (local f64)
+    (local.set 2 (local.tee 0 (f64.add (local.get 0) (f64.const 1))))
+    (f64.store (i32.const 8) (f64.mul (local.get 2) (f64.const 2)))
+    (if (result f64) (local.get 1)
+      (then (call $half (f64.load (i32.const 8))))
+      (else (f64.sub (local.get 0) (f64.const 1))))))
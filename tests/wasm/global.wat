@@ -0,0 +1,21 @@
+(module
+  (import "env" "counter" (global $counter (mut i64)))
+  (import "env" "base" (global $base i32))
+  (global $g (mut i64) (i64.const 10))
+  (global $derived i32 (global.get $base))
+  (global $pi f64 (f64.const 3.5))
+  (export "g" (global $g))
+  (export "derived" (global $derived))
+  (export "pi" (global $pi))
+  (export "counter" (global $counter))
+  (memory 1)
+  (data (global.get $base) "\2a")
+  (func (export "get_g") (result i64) (global.get $g))
+  (func (export "add_g") (param i64) (result i64)
+    (global.set $g (i64.add (global.get $g) (local.get 0)))
+    (global.get $g))
+  (func (export "set_imm") (global.set $g (i64.const 99)))
+  (func (export "bump")
+    (global.set $counter (i64.add (global.get $counter) (i64.const 1))))
+  (func (export "load_base") (result i32) (i32.load8_u (global.get $derived)))
+  (func (export "derived_plus") (result i32) (i32.add (global.get $derived) (i32.const 1))))
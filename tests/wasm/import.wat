@@ -0,0 +1,18 @@
+(module
+  (import "env" "add_one" (func $add_one (param i64) (result i64)))
+  (import "env" "log" (func $log (param i32 i32)))
+  (import "env" "fail" (func $fail))
+  (memory 1)
+  (data (i32.const 0) "hello")
+  (func (export "call_add_one") (param i64) (result i64)
+    (i64.add (call $add_one (local.get 0)) (i64.const 10))
+  )
+  (func (export "greet")
+    (call $log (i32.const 0) (i32.const 5))
+  )
+  (func (export "call_fail") (result i32)
+    call $fail
+    i32.const 1
+  )
+  (export "add_one" (func $add_one))
+)
@@ -0,0 +1,79 @@
+(module
+  (func (export "i32_arith") (param i32 i32) (result i32 i32 i32 i32 i32 i32)
+    (i32.mul (local.get 0) (local.get 1))
+    (i32.and (local.get 0) (local.get 1))
+    (i32.or (local.get 0) (local.get 1))
+    (i32.xor (local.get 0) (local.get 1))
+    (i32.sub (i32.const 100) (local.get 1))
+    (i32.mul (local.get 0) (i32.const 3)))
+  (func (export "i64_arith") (param i64 i64) (result i64 i64 i64 i64 i64)
+    (i64.mul (local.get 0) (local.get 1))
+    (i64.and (local.get 0) (local.get 1))
+    (i64.or (local.get 0) (local.get 1))
+    (i64.xor (local.get 0) (local.get 1))
+    (i64.sub (i64.const 100) (local.get 1)))
+  (func (export "i32_div") (param i32 i32) (result i32 i32 i32 i32)
+    (i32.div_s (local.get 0) (local.get 1))
+    (i32.div_u (local.get 0) (local.get 1))
+    (i32.rem_s (local.get 0) (local.get 1))
+    (i32.rem_u (local.get 0) (local.get 1)))
+  (func (export "i64_div") (param i64 i64) (result i64 i64 i64 i64)
+    (i64.div_s (local.get 0) (local.get 1))
+    (i64.div_u (local.get 0) (local.get 1))
+    (i64.rem_s (local.get 0) (local.get 1))
+    (i64.rem_u (local.get 0) (local.get 1)))
+  (func (export "i32_div_s") (param i32 i32) (result i32)
+    (i32.div_s (local.get 0) (local.get 1)))
+  (func (export "i64_div_s") (param i64 i64) (result i64)
+    (i64.div_s (local.get 0) (local.get 1)))
+  (func (export "i32_rem_s") (param i32 i32) (result i32)
+    (i32.rem_s (local.get 0) (local.get 1)))
+  (func (export "i32_shift") (param i32 i32) (result i32 i32 i32 i32 i32 i32)
+    (i32.shl (local.get 0) (local.get 1))
+    (i32.shr_s (local.get 0) (local.get 1))
+    (i32.shr_u (local.get 0) (local.get 1))
+    (i32.rotl (local.get 0) (local.get 1))
+    (i32.rotr (local.get 0) (local.get 1))
+    (i32.shl (local.get 0) (i32.const 33)))
+  (func (export "i64_shift") (param i64 i64) (result i64 i64 i64 i64 i64 i64)
+    (i64.shl (local.get 0) (local.get 1))
+    (i64.shr_s (local.get 0) (local.get 1))
+    (i64.shr_u (local.get 0) (local.get 1))
+    (i64.rotl (local.get 0) (local.get 1))
+    (i64.rotr (local.get 0) (local.get 1))
+    (i64.shr_u (local.get 0) (i64.const 4)))
+  (func (export "i32_bits") (param i32) (result i32 i32 i32 i32)
+    (i32.clz (local.get 0))
+    (i32.ctz (local.get 0))
+    (i32.popcnt (local.get 0))
+    (i32.eqz (local.get 0)))
+  (func (export "i64_bits") (param i64) (result i64 i64 i64 i32)
+    (i64.clz (local.get 0))
+    (i64.ctz (local.get 0))
+    (i64.popcnt (local.get 0))
+    (i64.eqz (local.get 0)))
+  (func (export "const_fold") (result i32 i32 i32 i64 i32 i32)
+    (i32.mul (i32.const 0x10000) (i32.const 0x10000))
+    (i32.div_u (i32.const -1) (i32.const 2))
+    (i32.rotl (i32.const 0x80000001) (i32.const 1))
+    (i64.shr_s (i64.const -16) (i64.const 2))
+    (i32.clz (i32.const 1))
+    (i32.eqz (i32.const 0)))
+  (func (export "const_div_zero") (result i32)
+    (i32.div_u (i32.const 1) (i32.const 0)))
+  (func (export "register_pressure") (param i32 i32 i32 i32 i32 i32 i32) (result i32)
+    ;; keep every register live so div and shifts have to evict rdx and rcx
+    (local.get 0)
+    (local.get 1)
+    (local.get 2)
+    (local.get 3)
+    (local.get 4)
+    (i32.div_s (local.get 5) (local.get 6))
+    (i32.shl (local.get 4) (local.get 2))
+    (i32.add)
+    (i32.add)
+    (i32.add)
+    (i32.add)
+    (i32.add)
+    (i32.add))
+)
@@ -0,0 +1,42 @@
+(module
+  (func (export "swap_sub") (param i64 i64) (result i64) (local $tmp i64)
+    local.get 0
+    local.set $tmp
+    local.get 1
+    local.set 0
+    local.get $tmp
+    local.set 1
+    local.get 0
+    local.get 1
+    i64.sub
+  )
+  (func (export "tee") (param i32) (result i32) (local i32)
+    local.get 0
+    i32.const 5
+    i32.add
+    local.tee 1
+    local.get 1
+    i32.add
+  )
+  (func (export "zero_init") (result i64) (local i32 i64) (local i64)
+    i64.const 7
+    local.set 1
+    local.get 2
+  )
+  (func (export "call_with_locals") (param i64) (result i64) (local i64 i64)
+    local.get 0
+    i64.const 1
+    i64.add
+    local.set 2
+    local.get 2
+    local.get 0
+    call $add
+    local.get 2
+    i64.add
+  )
+  (func $add (param i64 i64) (result i64) (local i64)
+    local.get 0
+    local.get 1
+    i64.add
+  )
+)
@@ -0,0 +1,52 @@
+(module
+  (memory 1 3)
+  (data (i32.const 16) "\01\02\03\04\05\06\07\08\ff\fe")
+  (func (export "load_i64") (param $addr i32) (result i64)
+    (i64.load (local.get $addr))
+  )
+  (func (export "load_i32") (param $addr i32) (result i32)
+    (i32.load offset=4 (local.get $addr))
+  )
+  (func (export "load_narrow") (result i64)
+    (i64.add
+      (i64.add
+        (i64.load8_s (i32.const 24))
+        (i64.load8_u (i32.const 24))
+      )
+      (i64.add
+        (i64.load16_s (i32.const 24))
+        (i64.load32_u (i32.const 24))
+      )
+    )
+  )
+  (func (export "store_load") (param $addr i32) (param $value i64) (result i64)
+    (i64.store (local.get $addr) (local.get $value))
+    (i32.store8 offset=8 (local.get $addr) (i32.const 0x1234))
+    (i64.store16 offset=9 (local.get $addr) (i64.const 0x5678))
+    (i64.add
+      (i64.load (local.get $addr))
+      (i64.load offset=8 (local.get $addr))
+    )
+  )
+  (func (export "size") (result i32)
+    memory.size
+  )
+  (func (export "grow") (param $delta i32) (result i32)
+    (memory.grow (local.get $delta))
+  )
+  (func (export "oob_load") (param $addr i32) (result i32)
+    (i32.load (local.get $addr))
+  )
+  (func (export "oob_store") (param $addr i32)
+    (i64.store offset=0xffffffff (local.get $addr) (i64.const 1))
+  )
+  (func (export "trap_through_call") (param $addr i32) (result i32)
+    (i32.add (call $oob_load (local.get $addr)) (i32.const 1))
+  )
+  (func $oob_load (param $addr i32) (result i32)
+    (i32.load (local.get $addr))
+  )
+  (func (export "unreachable") (result i32)
+    unreachable
+  )
+)
@@ -0,0 +1,22 @@
+(module
+  (type $i64_i64 (func (param i64) (result i64)))
+  (type $i64_i64_dup (func (param i64) (result i64)))
+  (type $void_i32 (func (result i32)))
+  (table 6 funcref)
+  (elem (i32.const 0) $double $plus_100 $seven)
+  ;; index 3 stays null
+  (elem (i32.const 4) funcref (ref.func $inc) (ref.null func))
+  (func $double (type $i64_i64) (i64.add (local.get 0) (local.get 0)))
+  (func $plus_100 (param i64) (result i64) (i64.add (local.get 0) (i64.const 100)))
+  (func $seven (result i32) (i32.const 7))
+  (func $inc (type $i64_i64_dup) (i64.add (local.get 0) (i64.const 1)))
+  (func (export "call") (param i64 i32) (result i64)
+    (call_indirect (type $i64_i64) (local.get 0) (local.get 1)))
+  (func (export "call_seven") (param i32) (result i32)
+    (call_indirect (type $void_i32) (local.get 0)))
+  (func (export "call_imm") (result i64)
+    (call_indirect (type $i64_i64_dup) (i64.const 5) (i32.const 4)))
+  (func (export "call_keep") (param i64) (result i64)
+    (i64.add
+      (local.get 0)
+      (call_indirect (type $i64_i64) (local.get 0) (i32.const 0)))))
@@ -0,0 +1,65 @@
+(module
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read"
+    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "args_sizes_get"
+    (func $args_sizes_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "args_get"
+    (func $args_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "environ_sizes_get"
+    (func $environ_sizes_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "environ_get"
+    (func $environ_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "clock_time_get"
+    (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "random_get"
+    (func $random_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (memory (export "memory") 1)
+
+  ;; Writes the `len` bytes at `ptr` to stdout.
+  (func $print (param $ptr i32) (param $len i32) (result i32)
+    (i32.store (i32.const 0) (local.get $ptr))
+    (i32.store (i32.const 4) (local.get $len))
+    (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))
+
+  ;; Returns argc and prints the argument buffer.
+  (func (export "args") (result i32)
+    (drop (call $args_sizes_get (i32.const 16) (i32.const 20)))
+    (drop (call $args_get (i32.const 0x100) (i32.const 0x200)))
+    (drop (call $print (i32.const 0x200) (i32.load (i32.const 20))))
+    (i32.load (i32.const 16)))
+
+  ;; Returns the environment count and prints the environment buffer.
+  (func (export "environ") (result i32)
+    (drop (call $environ_sizes_get (i32.const 16) (i32.const 20)))
+    (drop (call $environ_get (i32.const 0x100) (i32.const 0x200)))
+    (drop (call $print (i32.const 0x200) (i32.load (i32.const 20))))
+    (i32.load (i32.const 16)))
+
+  ;; Reads up to 64 bytes from stdin and echoes them to stdout.
+  (func (export "echo") (result i32)
+    (i32.store (i32.const 0) (i32.const 0x300))
+    (i32.store (i32.const 4) (i32.const 64))
+    (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+    (call $print (i32.const 0x300) (i32.load (i32.const 8))))
+
+  (func (export "write_bad_fd") (result i32)
+    (call $fd_write (i32.const 7) (i32.const 0) (i32.const 0) (i32.const 8)))
+
+  (func (export "write_oob") (result i32)
+    (i32.store (i32.const 0) (i32.const 0xfff0))
+    (i32.store (i32.const 4) (i32.const 32))
+    (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))
+
+  (func (export "clock") (param $id i32) (result i64)
+    (drop (call $clock_time_get (local.get $id) (i64.const 1) (i32.const 0x400)))
+    (i64.load (i32.const 0x400)))
+
+  (func (export "random") (result i32)
+    (call $random_get (i32.const 0x400) (i32.const 32)))
+
+  (func (export "exit") (param $code i32)
+    (call $proc_exit (local.get $code))
+    (unreachable)))
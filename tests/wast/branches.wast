@@ -0,0 +1,100 @@
+;; Blocks, branches and select
+
+(module
+  (func (export "as-br_if-value") (param $c i32) (result i32)
+    (block (result i32) (drop (br_if 0 (i32.const 8) (local.get $c))) (i32.const 7))
+  )
+  (func (export "nested-br_if") (param $x i32) (param $y i32) (result i32)
+    (block $outer (result i32)
+      (block $inner
+        (br_if $inner (i32.eqz (local.get $x)))
+        (br $outer (i32.const 1))
+      )
+      (br_if $outer (i32.const 2) (local.get $y))
+      (drop)
+      (i32.const 3)
+    )
+  )
+  (func (export "br_if-lt") (param $a i32) (param $b i32) (result i32)
+    (block (br_if 0 (i32.lt_s (local.get $a) (local.get $b))) (return (i32.const 0)))
+    (i32.const 1)
+  )
+  (func (export "select-i32") (param i32 i32 i32) (result i32)
+    (select (local.get 0) (local.get 1) (local.get 2))
+  )
+  (func (export "select-i64") (param i64 i64 i32) (result i64)
+    (select (local.get 0) (local.get 1) (local.get 2))
+  )
+  (func (export "select-f64") (param f64 f64 i32) (result f64)
+    (select (result f64) (local.get 0) (local.get 1) (local.get 2))
+  )
+  (func (export "select-cmp") (param i32 i32) (result i32)
+    (select (i32.const 10) (i32.const 20) (i32.gt_u (local.get 0) (local.get 1)))
+  )
+  (func (export "br_table") (param i32) (result i32)
+    (block (block (block (br_table 0 1 2 (local.get 0))) (return (i32.const 10))) (return (i32.const 11)))
+    (i32.const 12)
+  )
+  (func (export "loop-sum") (param $n i32) (result i32)
+    (local $sum i32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.eqz (local.get $n)))
+        (local.set $sum (i32.add (local.get $sum) (local.get $n)))
+        (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+        (br $next)
+      )
+    )
+    (local.get $sum)
+  )
+  (func (export "if-else") (param i32) (result i32)
+    (if (result i32) (local.get 0) (then (i32.const 1)) (else (i32.const 2)))
+  )
+  (func (export "unreachable") (unreachable))
+)
+
+(assert_return (invoke "as-br_if-value" (i32.const 0)) (i32.const 7))
+(assert_return (invoke "as-br_if-value" (i32.const 1)) (i32.const 8))
+(assert_return (invoke "nested-br_if" (i32.const 0) (i32.const 0)) (i32.const 3))
+(assert_return (invoke "nested-br_if" (i32.const 1) (i32.const 0)) (i32.const 1))
+(assert_return (invoke "nested-br_if" (i32.const 0) (i32.const 1)) (i32.const 2))
+(assert_return (invoke "br_if-lt" (i32.const 1) (i32.const 2)) (i32.const 1))
+(assert_return (invoke "br_if-lt" (i32.const 2) (i32.const 1)) (i32.const 0))
+(assert_return (invoke "br_if-lt" (i32.const -1) (i32.const 1)) (i32.const 1))
+(assert_return (invoke "select-i32" (i32.const 1) (i32.const 2) (i32.const 1)) (i32.const 1))
+(assert_return (invoke "select-i32" (i32.const 1) (i32.const 2) (i32.const 0)) (i32.const 2))
+(assert_return (invoke "select-i32" (i32.const 1) (i32.const 2) (i32.const -1)) (i32.const 1))
+(assert_return (invoke "select-i64" (i64.const 2) (i64.const 1) (i32.const 1)) (i64.const 2))
+(assert_return (invoke "select-i64" (i64.const 2) (i64.const 1) (i32.const 0)) (i64.const 1))
+(assert_return (invoke "select-f64" (f64.const 2) (f64.const nan) (i32.const 0)) (f64.const nan))
+(assert_return (invoke "select-f64" (f64.const -0.0) (f64.const 1) (i32.const 1)) (f64.const -0.0))
+(assert_return (invoke "select-cmp" (i32.const 2) (i32.const 1)) (i32.const 10))
+(assert_return (invoke "select-cmp" (i32.const 1) (i32.const -1)) (i32.const 20))
+(assert_return (invoke "br_table" (i32.const 0)) (i32.const 10))
+(assert_return (invoke "br_table" (i32.const 1)) (i32.const 11))
+(assert_return (invoke "br_table" (i32.const 2)) (i32.const 12))
+(assert_return (invoke "br_table" (i32.const 100)) (i32.const 12))
+(assert_return (invoke "br_table" (i32.const -1)) (i32.const 12))
+(assert_return (invoke "loop-sum" (i32.const 0)) (i32.const 0))
+(assert_return (invoke "loop-sum" (i32.const 100)) (i32.const 5050))
+(assert_return (invoke "if-else" (i32.const 0)) (i32.const 2))
+(assert_return (invoke "if-else" (i32.const 7)) (i32.const 1))
+(assert_trap (invoke "unreachable") "unreachable")
+
+(assert_malformed (module quote "(func (block $l (br $m)))") "unknown label")
+
+(assert_invalid
+  (module (func $type-false-arg-void-vs-num (result i32)
+    (block (result i32) (br_if 0 (i32.const 0)) (i32.const 1))
+  ))
+  "type mismatch"
+)
+(assert_invalid
+  (module (func $type-cond-num-vs-i32 (block (br_if 0 (i64.const 0)))))
+  "type mismatch"
+)
+(assert_invalid (module (func $unbound-label (br_if 1 (i32.const 1)))) "unknown label")
+(assert_invalid (module (func $large-label (br 0x10000001))) "unknown label")
+(assert_invalid (module (func $arity-mismatch (select (i32.const 1) (i64.const 1) (i32.const 1)) (drop))) "type mismatch")
+(assert_invalid (module (func $if-missing-else (result i32) (if (result i32) (i32.const 1) (then (i32.const 1))))) "type mismatch")
+(assert_invalid (module (func $unreachable-unused (result i32) (unreachable) (i64.const 0))) "type mismatch")
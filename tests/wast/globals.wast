@@ -0,0 +1,68 @@
+;; Globals
+
+(module
+  (global (import "spectest" "global_i32") i32)
+  (global (import "spectest" "global_f64") f64)
+  (global $a i32 (i32.const -2))
+  (global $b i64 (i64.const -5))
+  (global $x (mut i32) (i32.const -12))
+  (global $y (mut i64) (i64.const -15))
+  (global $z (mut f32) (f32.const -3))
+  (global $c i32 (global.get 0))
+  (global $e (export "e") (mut f64) (f64.const 1.5))
+
+  (func (export "get-a") (result i32) (global.get $a))
+  (func (export "get-b") (result i64) (global.get $b))
+  (func (export "get-x") (result i32) (global.get $x))
+  (func (export "get-y") (result i64) (global.get $y))
+  (func (export "get-z") (result f32) (global.get $z))
+  (func (export "get-c") (result i32) (global.get $c))
+  (func (export "get-0") (result i32) (global.get 0))
+  (func (export "get-1") (result f64) (global.get 1))
+  (func (export "set-x") (param i32) (global.set $x (local.get 0)))
+  (func (export "set-y") (param i64) (global.set $y (local.get 0)))
+  (func (export "set-z") (param f32) (global.set $z (local.get 0)))
+  (func (export "set-e") (param f64) (global.set $e (local.get 0)))
+)
+
+(assert_return (invoke "get-a") (i32.const -2))
+(assert_return (invoke "get-b") (i64.const -5))
+(assert_return (invoke "get-x") (i32.const -12))
+(assert_return (invoke "get-y") (i64.const -15))
+(assert_return (invoke "get-z") (f32.const -3))
+(assert_return (invoke "get-c") (i32.const 666))
+(assert_return (invoke "get-0") (i32.const 666))
+(assert_return (invoke "get-1") (f64.const 666.6))
+(assert_return (get "e") (f64.const 1.5))
+
+(assert_return (invoke "set-x" (i32.const 6)))
+(assert_return (invoke "set-y" (i64.const 7)))
+(assert_return (invoke "set-z" (f32.const 8)))
+(assert_return (invoke "set-e" (f64.const -0.0)))
+(assert_return (invoke "get-x") (i32.const 6))
+(assert_return (invoke "get-y") (i64.const 7))
+(assert_return (invoke "get-z") (f32.const 8))
+(assert_return (get "e") (f64.const -0.0))
+
+(assert_unlinkable
+  (module (global (import "spectest" "global_i32") i64))
+  "incompatible import type"
+)
+(assert_unlinkable
+  (module (global (import "spectest" "global_i32") (mut i32)))
+  "incompatible import type"
+)
+
+(assert_invalid
+  (module (global f32 (f32.const 0)) (func (global.set 0 (f32.const 1))))
+  "global is immutable"
+)
+(assert_invalid
+  (module (global i32 (i32.const 0)) (func (result i32) (global.get 1)))
+  "unknown global"
+)
+(assert_invalid
+  (module (global (mut i64) (i64.const 0)) (func (global.set 0 (i32.const 1))))
+  "type mismatch"
+)
+(assert_invalid (module (global i32 (i64.const 0))) "type mismatch")
@@ -0,0 +1,70 @@
+;; Loads, stores and memory growth
+
+(module
+  (memory 1 3)
+  (data (i32.const 0) "abcdefghijklmnopqrstuvwxyz")
+  (data (i32.const 0xfff8) "\01\02\03\04\05\06\07\08")
+
+  (func (export "i32.load8_s") (param i32) (result i32) (i32.load8_s (local.get 0)))
+  (func (export "i32.load8_u") (param i32) (result i32) (i32.load8_u (local.get 0)))
+  (func (export "i32.load16_u") (param i32) (result i32) (i32.load16_u offset=1 (local.get 0)))
+  (func (export "i32.load") (param i32) (result i32) (i32.load (local.get 0)))
+  (func (export "i64.load") (param i32) (result i64) (i64.load (local.get 0)))
+  (func (export "i64.load32_s") (param i32) (result i64) (i64.load32_s (local.get 0)))
+  (func (export "f64.load") (param i32) (result f64) (f64.load (local.get 0)))
+  (func (export "i32.store8") (param i32 i32) (i32.store8 (local.get 0) (local.get 1)))
+  (func (export "i64.store") (param i32 i64) (i64.store (local.get 0) (local.get 1)))
+  (func (export "f64.store") (param i32 f64) (f64.store (local.get 0) (local.get 1)))
+  (func (export "size") (result i32) (memory.size))
+  (func (export "grow") (param i32) (result i32) (memory.grow (local.get 0)))
+)
+
+(assert_return (invoke "i32.load8_u" (i32.const 0)) (i32.const 97))
+(assert_return (invoke "i32.load8_u" (i32.const 25)) (i32.const 122))
+(assert_return (invoke "i32.load16_u" (i32.const 0)) (i32.const 0x6362))
+(assert_return (invoke "i32.load" (i32.const 0)) (i32.const 0x64636261))
+(assert_return (invoke "i32.load" (i32.const 0xfffc)) (i32.const 0x08070605))
+(assert_return (invoke "i64.load" (i32.const 0xfff8)) (i64.const 0x0807060504030201))
+(assert_return (invoke "i32.load8_u" (i32.const 26)) (i32.const 0))
+
+(invoke "i32.store8" (i32.const 100) (i32.const 0xff))
+(assert_return (invoke "i32.load8_s" (i32.const 100)) (i32.const -1))
+(assert_return (invoke "i32.load8_u" (i32.const 100)) (i32.const 255))
+(invoke "i64.store" (i32.const 200) (i64.const 0x80000000ffffffff))
+(assert_return (invoke "i64.load32_s" (i32.const 200)) (i64.const -1))
+(assert_return (invoke "i64.load32_s" (i32.const 204)) (i64.const 0xffffffff80000000))
+(invoke "f64.store" (i32.const 300) (f64.const -nan:0x4))
+(assert_return (invoke "f64.load" (i32.const 300)) (f64.const -nan:0x4))
+(assert_return (invoke "i64.load" (i32.const 300)) (i64.const 0xfff0000000000004))
+
+(assert_trap (invoke "i32.load8_u" (i32.const 0x10000)) "out of bounds memory access")
+(assert_trap (invoke "i32.load" (i32.const 0xfffd)) "out of bounds memory access")
+(assert_trap (invoke "i64.load" (i32.const 0xfff9)) "out of bounds memory access")
+(assert_trap (invoke "i32.load" (i32.const -1)) "out of bounds memory access")
+(assert_trap (invoke "i32.load16_u" (i32.const 0xfffe)) "out of bounds memory access")
+(assert_trap (invoke "i32.store8" (i32.const 0x10000) (i32.const 1)) "out of bounds memory access")
+(assert_trap (invoke "i64.store" (i32.const 0xfff9) (i64.const 0)) "out of bounds memory access")
+
+(assert_return (invoke "size") (i32.const 1))
+(assert_return (invoke "grow" (i32.const 1)) (i32.const 1))
+(assert_return (invoke "size") (i32.const 2))
+(assert_return (invoke "i32.load8_u" (i32.const 0x10000)) (i32.const 0))
+(invoke "i32.store8" (i32.const 0x1ffff) (i32.const 7))
+(assert_return (invoke "i32.load8_u" (i32.const 0x1ffff)) (i32.const 7))
+(assert_trap (invoke "i32.load8_u" (i32.const 0x20000)) "out of bounds memory access")
+(assert_return (invoke "grow" (i32.const 2)) (i32.const -1))
+(assert_return (invoke "grow" (i32.const 0)) (i32.const 2))
+(assert_return (invoke "grow" (i32.const 1)) (i32.const 2))
+(assert_return (invoke "size") (i32.const 3))
+
+(module (memory 0))
+(assert_trap (module (memory 1) (data (i32.const 0xffff) "ab")) "out of bounds memory access")
+
+(assert_invalid (module (func (drop (i32.load (i32.const 0))))) "unknown memory")
+(assert_invalid (module (func (drop (memory.size)))) "unknown memory")
+(assert_invalid
+  (module (memory 1) (func (drop (i64.load align=16 (i32.const 0)))))
+  "alignment must not be larger than natural"
+)
+(assert_invalid (module (memory 65537)) "memory size must be at most 65536 pages (4GiB)")
+(assert_invalid (module (memory 1) (func (i32.store (i32.const 0) (i64.const 0)))) "type mismatch")
@@ -0,0 +1,62 @@
+;; Functions and globals shared between instances
+
+(module $M
+  (global (export "glob") i32 (i32.const 42))
+  (func (export "call") (result i32) (i32.const 2))
+  (func $g (result i32) (i32.const 3))
+  (func (export "add") (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1)))
+  (func (export "tail") (param i64) (result i64) (i64.mul (local.get 0) (i64.const 3)))
+)
+(register "M" $M)
+
+(module $N
+  (func $f (import "M" "call") (result i32))
+  (func $add (import "M" "add") (param i32 i32) (result i32))
+  (global $glob (import "M" "glob") i32)
+  (func (export "call M.call") (result i32) (call $f))
+  (func (export "call M.add") (param i32) (result i32) (call $add (local.get 0) (i32.const 100)))
+  (func (export "M.glob") (result i32) (global.get $glob))
+)
+
+(assert_return (invoke $M "call") (i32.const 2))
+(assert_return (invoke $N "call M.call") (i32.const 2))
+(assert_return (invoke $N "call M.add" (i32.const 5)) (i32.const 105))
+(assert_return (invoke $N "M.glob") (i32.const 42))
+(assert_return (get $M "glob") (i32.const 42))
+
+(assert_unlinkable
+  (module (func (import "M" "call") (param i32)))
+  "incompatible import type"
+)
+(assert_unlinkable
+  (module (func (import "M" "missing")))
+  "unknown import"
+)
+(assert_unlinkable
+  (module (global (import "M" "glob") i64))
+  "incompatible import type"
+)
+
+;; a mutable global is the same global in every instance that imports it
+(module $G
+  (global (export "counter") (mut i32) (i32.const 0))
+  (func (export "bump") (global.set 0 (i32.add (global.get 0) (i32.const 1))))
+)
+(register "G" $G)
+(module $H
+  (global $counter (import "G" "counter") (mut i32))
+  (func (export "get") (result i32) (global.get $counter))
+  (func (export "set") (param i32) (global.set $counter (local.get 0)))
+)
+(invoke $G "bump")
+(assert_return (invoke $H "get") (i32.const 1))
+(invoke $H "set" (i32.const 10))
+(invoke $G "bump")
+(assert_return (get $G "counter") (i32.const 11))
+(assert_return (invoke $H "get") (i32.const 11))
+
+(module
+  (func $tail (import "M" "tail") (param i64) (result i64))
+  (func (export "twice") (param i64) (result i64) (call $tail (call $tail (local.get 0))))
+)
+(assert_return (invoke "twice" (i64.const 5)) (i64.const 45))
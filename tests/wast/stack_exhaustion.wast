@@ -0,0 +1,22 @@
+;; Unbounded recursion
+
+(module
+  (func $runaway (export "runaway") (call $runaway))
+  (func $mutual-a (export "mutual-runaway") (call $mutual-b))
+  (func $mutual-b (call $mutual-a))
+  (func $deep (export "deep") (param i64 i64 i64 i64) (result i64)
+    (call $deep (local.get 0) (local.get 1) (local.get 2) (local.get 3))
+  )
+  (func $fac (export "fac") (param i64) (result i64)
+    (if (result i64) (i64.eqz (local.get 0))
+      (then (i64.const 1))
+      (else (i64.mul (local.get 0) (call $fac (i64.sub (local.get 0) (i64.const 1)))))
+    )
+  )
+)
+
+(assert_exhaustion (invoke "runaway") "call stack exhausted")
+(assert_exhaustion (invoke "mutual-runaway") "call stack exhausted")
+(assert_exhaustion (invoke "deep" (i64.const 1) (i64.const 2) (i64.const 3) (i64.const 4)) "call stack exhausted")
+(assert_return (invoke "fac" (i64.const 20)) (i64.const 2432902008176640000))
+(assert_return (invoke "fac" (i64.const 25)) (i64.const 7034535277573963776))
@@ -0,0 +1,79 @@
+;; Indirect calls through a table
+
+(module
+  (type $i32 (func (result i32)))
+  (type $i64 (func (result i64)))
+  (type $i32-i32 (func (param i32) (result i32)))
+  (type $i32-dup (func (result i32)))
+
+  (func $const-i32 (type $i32) (i32.const 0x132))
+  (func $const-i64 (type $i64) (i64.const 0x164))
+  (func $id-i32 (type $i32-i32) (local.get 0))
+  (func $fac (type $i32-i32)
+    (if (result i32) (i32.eqz (local.get 0))
+      (then (i32.const 1))
+      (else
+        (i32.mul
+          (local.get 0)
+          (call_indirect (type $i32-i32) (i32.sub (local.get 0) (i32.const 1)) (i32.const 3))
+        )
+      )
+    )
+  )
+
+  (table funcref (elem $const-i32 $const-i64 $id-i32 $fac))
+  (table $empty 8 funcref)
+
+  (func (export "type-i32") (result i32) (call_indirect (type $i32) (i32.const 0)))
+  (func (export "type-i64") (result i64) (call_indirect (type $i64) (i32.const 1)))
+  (func (export "type-dup") (result i32) (call_indirect (type $i32-dup) (i32.const 0)))
+  (func (export "dispatch") (param i32 i32) (result i32)
+    (call_indirect (type $i32-i32) (local.get 1) (local.get 0))
+  )
+  (func (export "fac") (param i32) (result i32)
+    (call_indirect (type $i32-i32) (local.get 0) (i32.const 3))
+  )
+  (func (export "empty") (param i32) (result i32)
+    (call_indirect $empty (type $i32) (local.get 0))
+  )
+)
+
+(assert_return (invoke "type-i32") (i32.const 0x132))
+(assert_return (invoke "type-i64") (i64.const 0x164))
+(assert_return (invoke "type-dup") (i32.const 0x132))
+(assert_return (invoke "dispatch" (i32.const 2) (i32.const 5)) (i32.const 5))
+(assert_return (invoke "fac" (i32.const 0)) (i32.const 1))
+(assert_return (invoke "fac" (i32.const 5)) (i32.const 120))
+(assert_return (invoke "fac" (i32.const 10)) (i32.const 3628800))
+(assert_trap (invoke "dispatch" (i32.const 0) (i32.const 2)) "indirect call type mismatch")
+(assert_trap (invoke "dispatch" (i32.const 1) (i32.const 2)) "indirect call type mismatch")
+(assert_trap (invoke "dispatch" (i32.const 4) (i32.const 2)) "undefined element")
+(assert_trap (invoke "dispatch" (i32.const -1) (i32.const 2)) "undefined element")
+(assert_trap (invoke "empty" (i32.const 0)) "uninitialized element")
+(assert_trap (invoke "empty" (i32.const 7)) "uninitialized element")
+(assert_trap (invoke "empty" (i32.const 8)) "undefined element")
+
+(assert_invalid
+  (module (func $no-table (call_indirect (type 0) (i32.const 0))))
+  "unknown table"
+)
+(assert_invalid
+  (module (table 0 funcref) (func $unbound-type (call_indirect (type 1) (i32.const 0))))
+  "unknown type"
+)
+(assert_invalid
+  (module
+    (type (func (param i32)))
+    (table 0 funcref)
+    (func $type-arg-void-vs-num (call_indirect (type 0) (i32.const 0)))
+  )
+  "type mismatch"
+)
+(assert_invalid
+  (module
+    (type (func))
+    (table 0 funcref)
+    (func $type-func-num-vs-i32 (call_indirect (type 0) (i64.const 0)))
+  )
+  "type mismatch"
+)